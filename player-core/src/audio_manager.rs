@@ -0,0 +1,886 @@
+use rodio::{Decoder, Sink, Source};
+use std::f32::consts::FRAC_PI_2;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use crate::dsp::{DspControl, DspSource};
+use crate::equalizer::EqCurve;
+use crate::output::{self, AudioOutput, NullOutput};
+use crate::replaygain::{ReplayGain, ReplayGainOptions};
+use crate::settings::{AudioSettings, ChannelSettings, OutputKind, Settings};
+use crate::spectrum::Spectrum;
+
+/// How often the audio thread checks whether the current track has finished
+const END_OF_TRACK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the audio thread checks whether its output still works while nothing plays
+const OUTPUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the audio thread checks whether the source faded out
+const FADE_OUT_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How much longer than the ramp a fade out may take, the output still plays what it buffered
+const FADE_OUT_TIMEOUT: Duration = Duration::from_millis(200);
+/// How often the volumes of a crossfade are updated
+const CROSSFADE_STEP: Duration = Duration::from_millis(10);
+
+/// Commands that can be sent to the audio background thread. It shuts down once the
+/// `AudioManager` is dropped.
+enum AudioCommand {
+    /// The track, whether it plays as part of its album and its measured gain
+    Load(String, bool, ReplayGain),
+    Play,
+    Pause,
+    Stop,
+    SeekTo(Duration),
+    SeekForward,
+    SeekBackward,
+    SetSeekStep(Duration),
+    SetVolume(f32),
+    SetReplayGain(ReplayGainOptions),
+    SetEqualizer(EqCurve),
+    SetSpeed(f32),
+    SetPitch(f32),
+    /// Start and end of the A–B loop
+    SetSection(Option<(Duration, Duration)>),
+    SetRamp(Duration),
+    SetCrossfade(Duration),
+    SetChannels(ChannelSettings),
+    SetOutput(AudioSettings),
+    Detach,
+}
+
+/// Events sent from the audio background thread
+pub enum AudioEvent {
+    /// The loaded track played until its end
+    TrackFinished,
+    /// The loaded track ends within the crossfade, the next track loaded crossfades with it
+    TrackEnding,
+    /// A seek succeeded, with the new position
+    Seeked(Duration),
+    /// The output failed or could not be opened, the message says which output plays instead
+    OutputFallback(String),
+}
+
+/// Snapshot of the audio thread state, updated after every command
+#[derive(Clone, Debug, Default)]
+pub struct AudioStatus {
+    /// Length of the loaded track, when the decoder knows it
+    pub duration: Option<Duration>,
+    /// Position when the snapshot was taken
+    position: Duration,
+    /// Set while playing, the position keeps advancing from `position`
+    playing_since: Option<Instant>,
+    /// How much faster than real time the position advances
+    speed: f32,
+    /// The A–B loop the position wraps around in
+    section: Option<(Duration, Duration)>,
+}
+
+impl AudioStatus {
+    /// Current playback position
+    pub fn position(&self) -> Duration {
+        let position = match self.playing_since {
+            Some(instant) => self.position + instant.elapsed().mul_f32(self.speed),
+            None => self.position,
+        };
+        looped(position, self.section)
+    }
+}
+
+/// Where playback is after playing on to `position`, jumping back to the start of `section`
+/// each time its end is reached
+fn looped(position: Duration, section: Option<(Duration, Duration)>) -> Duration {
+    match section {
+        Some((start, end)) if position >= end => {
+            let length = (end - start).as_millis().max(1);
+            start + Duration::from_millis(((position - end).as_millis() % length) as u64)
+        }
+        _ => position,
+    }
+}
+
+/// Where a seek to `position` ends up: past the end of `section` it jumps to its start
+fn seek_target(position: Duration, section: Option<(Duration, Duration)>) -> Duration {
+    match section {
+        Some((start, end)) if position >= end => start,
+        _ => position,
+    }
+}
+
+/// The previous track fading out while the current one fades in
+struct Crossfade {
+    /// Sink of the previous track
+    sink: Sink,
+    /// ReplayGain factor of the previous track
+    gain_factor: f32,
+    start: Instant,
+    length: Duration,
+}
+
+impl Crossfade {
+    /// Volume factors of the previous and the current track, with equal power so the loudness
+    /// stays the same. `None` once the previous track faded out or ended.
+    fn weights(&self) -> Option<(f32, f32)> {
+        let progress = self.start.elapsed().as_secs_f32() / self.length.as_secs_f32();
+        if progress >= 1.0 || self.sink.empty() {
+            return None;
+        }
+        let (fade_in, fade_out) = (progress * FRAC_PI_2).sin_cos();
+        Some((fade_out, fade_in))
+    }
+}
+
+/// Stop the previous track of the crossfade and play the current one in `sink` at `volume`
+fn end_crossfade(fading: &mut Option<Crossfade>, sink: &Sink, volume: f32) {
+    if let Some(crossfade) = fading.take() {
+        crossfade.sink.stop();
+        sink.set_volume(volume);
+    }
+}
+
+/// Open and decode the audio file at `path`, processed with the parameters of `dsp`
+fn open_source(
+    path: &str,
+    dsp: &Arc<DspControl>,
+) -> Result<DspSource<Decoder<BufReader<File>>>, AudioPlayerError> {
+    let file = File::open(path).map_err(|e| AudioPlayerError::FileError(e.to_string()))?;
+    // knowing the file length lets the decoder seek backwards, which the A–B loop relies on
+    let decoder =
+        Decoder::try_from(file).map_err(|e| AudioPlayerError::DecodeError(e.to_string()))?;
+    Ok(DspSource::new(decoder, Arc::clone(dsp)))
+}
+
+/// Ramp the playing source down to silence and wait until it got there, so the sink can be
+/// paused, stopped, seeked or cleared without a click
+fn fade_out(sink: &Sink, dsp: &DspControl, ramp: Duration) {
+    dsp.update(|params| params.gain = 0.0);
+    let deadline = Instant::now() + ramp + FADE_OUT_TIMEOUT;
+    while !dsp.is_silent() && !sink.empty() && Instant::now() < deadline {
+        thread::sleep(FADE_OUT_POLL_INTERVAL);
+    }
+}
+
+/// Ramp the source back up to full volume, from where `fade_out` left it
+fn fade_in(dsp: &DspControl) {
+    dsp.update(|params| params.gain = 1.0);
+}
+
+/// Seek the sink to `position`
+fn seek(sink: &Sink, position: Duration) -> Result<(), AudioPlayerError> {
+    sink.try_seek(position)
+        .map_err(|e| AudioPlayerError::SinkError(e.to_string()))
+}
+
+/// Stop `sink`, then load `path` into it at `position`. It is left paused.
+fn reload_and_seek(sink: &Sink, path: &str, position: Duration, dsp: &Arc<DspControl>) -> bool {
+    sink.stop();
+    sink.clear();
+    match open_source(path, dsp) {
+        Ok(source) => {
+            sink.append(source);
+            if position.as_millis() > 0
+                && let Err(e) = seek(sink, position)
+            {
+                info!("Seek after reload failed: {}", e);
+                return false;
+            }
+
+            true
+        }
+        Err(e) => {
+            error!("Unable to reload {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// The output of `settings`, or the one of `fallback_output` when it can't be opened. The flag
+/// tells whether it is the fallback.
+fn open_output<F>(
+    open: &F,
+    settings: &AudioSettings,
+    event_tx: &Sender<AudioEvent>,
+) -> (Box<dyn AudioOutput>, bool)
+where
+    F: Fn(&AudioSettings) -> Result<Box<dyn AudioOutput>, AudioPlayerError>,
+{
+    match open(settings) {
+        Ok(output) => (output, false),
+        Err(e) => (
+            fallback_output(open, settings, &e.to_string(), event_tx),
+            true,
+        ),
+    }
+}
+
+/// Replacement for the output of `settings`, which failed because of `reason`: the system default
+/// device when a specific device was selected, otherwise a `NullOutput`. The fallback is reported
+/// through `event_tx`.
+fn fallback_output<F>(
+    open: &F,
+    settings: &AudioSettings,
+    reason: &str,
+    event_tx: &Sender<AudioEvent>,
+) -> Box<dyn AudioOutput>
+where
+    F: Fn(&AudioSettings) -> Result<Box<dyn AudioOutput>, AudioPlayerError>,
+{
+    if settings.output == OutputKind::Device && settings.output_device.is_some() {
+        let default = AudioSettings {
+            output_device: None,
+            ..settings.clone()
+        };
+        if let Ok(output) = open(&default) {
+            let message = format!("{}; playing on the default device", reason);
+            error!("{}", message);
+            let _ = event_tx.send(AudioEvent::OutputFallback(message));
+            return output;
+        }
+    }
+
+    let message = format!("{}; audio will not be audible", reason);
+    error!("{}", message);
+    let _ = event_tx.send(AudioEvent::OutputFallback(message));
+    Box::new(NullOutput::new(1.0))
+}
+
+/// AudioManager runs audio playback on a background thread to avoid blocking the main thread.
+/// Communication happens via channels - the main thread sends commands, the background thread executes them.
+pub struct AudioManager {
+    command_tx: Sender<AudioCommand>,
+    status: Arc<Mutex<AudioStatus>>,
+    dsp: Arc<DspControl>,
+    _thread_handle: Option<JoinHandle<()>>,
+}
+
+impl AudioManager {
+    /// Creates a new AudioManager with a background thread for audio playback.
+    /// The output, seek step and ReplayGain options are taken from `settings`, events are sent to `event_tx`.
+    pub fn new(settings: &Settings, event_tx: Sender<AudioEvent>) -> Self {
+        Self::with_output(settings, event_tx, output::open)
+    }
+
+    /// Like `new`, but outputs are opened by `open`, for the initial `settings.audio` and for
+    /// every `set_output`. It is called on the audio thread, as device streams can't be moved
+    /// between threads. Outputs that fail are replaced by the system default device or a
+    /// `NullOutput`.
+    pub fn with_output<F>(settings: &Settings, event_tx: Sender<AudioEvent>, open: F) -> Self
+    where
+        F: Fn(&AudioSettings) -> Result<Box<dyn AudioOutput>, AudioPlayerError> + Send + 'static,
+    {
+        let (command_tx, command_rx) = mpsc::channel::<AudioCommand>();
+        let mut output_settings = settings.audio.clone();
+        let mut seek_step = settings.seek_step();
+        let mut replaygain = settings.replaygain();
+        let mut ramp = settings.ramp();
+        let mut crossfade = settings.crossfade();
+        let dsp = Arc::new(DspControl::default());
+        dsp.update(|params| {
+            params.equalizer = settings.equalizer.curve();
+            params.channels = settings.channels;
+            params.ramp = ramp;
+            // nothing plays yet, the first track ramps in
+            params.gain = 0.0;
+        });
+        let status = Arc::new(Mutex::new(AudioStatus::default()));
+        let thread_status = Arc::clone(&status);
+        let thread_dsp = Arc::clone(&dsp);
+
+        let thread_handle = thread::spawn(move || {
+            // Audio stream and sink live entirely on this background thread
+            // set while playing on a fallback, selecting the same output again retries it
+            let (mut output, mut fallback) = open_output(&open, &output_settings, &event_tx);
+            let mut sink = Sink::connect_new(output.mixer());
+
+            // Position tracking state - all local to this thread
+            let mut accumulated_ms: u64 = 0; // Time accumulated from previous play sessions
+            let mut play_start: Option<Instant> = None; // When current play session started
+            // Tempo of the DSP stage, the position advances that much faster than real time
+            let mut speed: f32 = 1.0;
+            // The A–B loop, the position wraps around in it
+            let mut section: Option<(Duration, Duration)> = None;
+            let mut current_file_path = String::new();
+            let mut current_duration: Option<Duration> = None;
+            // Set once the end of the track was announced, the next load crossfades with it
+            let mut ending = false;
+            let mut fading: Option<Crossfade> = None;
+
+            // The sink volume is the user volume times the ReplayGain of the current track
+            let mut volume: f32 = 1.0;
+            let mut current_gain = ReplayGain::default();
+            let mut in_album = false;
+            let mut gain_factor: f32 = 1.0;
+
+            // Helper closure to get current position
+            let get_current_position_ms = |accumulated: u64,
+                                           start: &Option<Instant>,
+                                           speed: f32,
+                                           section: Option<(Duration, Duration)>|
+             -> u64 {
+                let position = match start {
+                    Some(instant) => {
+                        accumulated + instant.elapsed().mul_f32(speed).as_millis() as u64
+                    }
+                    None => accumulated,
+                };
+                looped(Duration::from_millis(position), section).as_millis() as u64
+            };
+
+            // Process commands from the main thread. Wake up regularly to notice the end of the
+            // track and a failing output.
+            loop {
+                let command = command_rx.recv_timeout(if fading.is_some() {
+                    CROSSFADE_STEP
+                } else if play_start.is_some() {
+                    END_OF_TRACK_POLL_INTERVAL
+                } else {
+                    OUTPUT_CHECK_INTERVAL
+                });
+
+                // move playback to a new output when the current one failed or another one was
+                // selected, continuing at the same position
+                let new_output = match &command {
+                    Ok(AudioCommand::SetOutput(settings))
+                        if *settings != output_settings || fallback =>
+                    {
+                        output_settings = settings.clone();
+                        Some(open_output(&open, &output_settings, &event_tx))
+                    }
+                    _ => output.failure().map(|reason| {
+                        let output = fallback_output(&open, &output_settings, &reason, &event_tx);
+                        (output, true)
+                    }),
+                };
+                if let Some((new_output, is_fallback)) = new_output {
+                    end_crossfade(&mut fading, &sink, volume * gain_factor);
+                    let new_sink = Sink::connect_new(new_output.mixer());
+                    new_sink.set_volume(sink.volume());
+                    if !sink.empty() {
+                        accumulated_ms =
+                            get_current_position_ms(accumulated_ms, &play_start, speed, section);
+                        let position = Duration::from_millis(accumulated_ms);
+                        if reload_and_seek(&new_sink, &current_file_path, position, &dsp)
+                            && play_start.is_some()
+                        {
+                            new_sink.play();
+                            play_start = Some(Instant::now());
+                        }
+                    }
+                    sink.stop();
+                    sink = new_sink;
+                    output = new_output;
+                    fallback = is_fallback;
+                    info!("Audio output changed");
+                }
+
+                match command {
+                    Err(RecvTimeoutError::Timeout) => {
+                        if play_start.is_some() && sink.empty() {
+                            accumulated_ms = 0;
+                            play_start = None;
+                            ending = false;
+                            info!("Audio finished: {}", current_file_path);
+                            let _ = event_tx.send(AudioEvent::TrackFinished);
+                        } else if play_start.is_some()
+                            && !ending
+                            && !crossfade.is_zero()
+                            && section.is_none()
+                            && let Some(duration) = current_duration
+                        {
+                            let position = Duration::from_millis(get_current_position_ms(
+                                accumulated_ms,
+                                &play_start,
+                                speed,
+                                section,
+                            ));
+                            // short tracks are crossfaded for half their length at most
+                            let remaining = duration.saturating_sub(position).div_f32(speed);
+                            if remaining <= crossfade.min(duration / 2) {
+                                ending = true;
+                                info!("Audio ending: {}", current_file_path);
+                                let _ = event_tx.send(AudioEvent::TrackEnding);
+                            }
+                        }
+                    }
+                    Ok(AudioCommand::Load(path, album, measured)) => {
+                        // a track that finished keeps the gain, so the next one follows
+                        // without a dip. One that is ending fades out under the next one.
+                        let crossfading = ending && play_start.is_some() && !sink.empty();
+                        ending = false;
+                        let interrupted = play_start.is_some() && !crossfading;
+                        if interrupted {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        // the loop belongs to the previous track
+                        if section.take().is_some() {
+                            dsp.update(|params| params.section = None);
+                        }
+                        match open_source(&path, &dsp) {
+                            Ok(source) => {
+                                if crossfading {
+                                    // the fade ends with the previous track
+                                    let position = Duration::from_millis(get_current_position_ms(
+                                        accumulated_ms,
+                                        &play_start,
+                                        speed,
+                                        section,
+                                    ));
+                                    let remaining = current_duration
+                                        .unwrap_or_default()
+                                        .saturating_sub(position)
+                                        .div_f32(speed);
+                                    let new_sink = Sink::connect_new(output.mixer());
+                                    new_sink.pause();
+                                    fading = Some(Crossfade {
+                                        sink: std::mem::replace(&mut sink, new_sink),
+                                        gain_factor,
+                                        start: Instant::now(),
+                                        length: remaining.min(crossfade).max(CROSSFADE_STEP),
+                                    });
+                                    info!("Crossfading over {:?}", remaining.min(crossfade));
+                                } else {
+                                    sink.stop();
+                                    sink.clear();
+                                }
+                                current_duration = source.total_duration();
+                                sink.append(source);
+                                current_file_path = path.clone();
+                                accumulated_ms = 0;
+                                play_start = None;
+                                current_gain = ReplayGain::read(Path::new(&path)).or(measured);
+                                in_album = album;
+                                gain_factor = current_gain.factor(&replaygain, in_album);
+                                sink.set_volume(volume * gain_factor);
+                                info!("Audio loaded: {} (gain {:.2})", path, gain_factor);
+                            }
+                            Err(e) => {
+                                error!("Unable to load {}: {}", path, e);
+                                // the previous track plays on
+                                if interrupted {
+                                    fade_in(&dsp);
+                                }
+                            }
+                        }
+                    }
+                    Ok(AudioCommand::Play) => {
+                        fade_in(&dsp);
+                        sink.play();
+                        play_start = Some(Instant::now());
+                        info!("Audio playing");
+                    }
+                    Ok(AudioCommand::Pause) => {
+                        if play_start.is_some() {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        // Accumulate elapsed time before pausing
+                        if let Some(start) = play_start.take() {
+                            accumulated_ms += start.elapsed().mul_f32(speed).as_millis() as u64;
+                        }
+                        sink.pause();
+                        info!("Audio paused at {}ms", accumulated_ms);
+                    }
+                    Ok(AudioCommand::Stop) => {
+                        if play_start.is_some() {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        ending = false;
+                        accumulated_ms = 0;
+                        play_start = None;
+                        sink.stop();
+                        dsp.tap.clear();
+                        info!("Audio stopped");
+                    }
+                    Ok(AudioCommand::SeekTo(position)) => {
+                        let position = seek_target(position, section);
+                        if play_start.is_some() {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        ending = false;
+                        let sought = seek(&sink, position);
+                        if play_start.is_some() {
+                            fade_in(&dsp);
+                        }
+                        if let Err(e) = sought {
+                            info!("Seek failed: {}", e);
+                        } else {
+                            accumulated_ms = position.as_millis() as u64;
+                            // Reset play_start if currently playing
+                            if play_start.is_some() {
+                                play_start = Some(Instant::now());
+                            }
+                            info!("Seeked to {:?}", position);
+                            let _ = event_tx.send(AudioEvent::Seeked(position));
+                        }
+                    }
+                    Ok(AudioCommand::SeekForward) => {
+                        let current_ms =
+                            get_current_position_ms(accumulated_ms, &play_start, speed, section);
+                        let new_pos_ms = current_ms.saturating_add(seek_step.as_millis() as u64);
+                        let new_pos = seek_target(Duration::from_millis(new_pos_ms), section);
+                        if play_start.is_some() {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        ending = false;
+                        let sought = seek(&sink, new_pos);
+                        if play_start.is_some() {
+                            fade_in(&dsp);
+                        }
+                        if let Err(e) = sought {
+                            info!("Seek forward failed: {}", e);
+                        } else {
+                            accumulated_ms = new_pos.as_millis() as u64;
+                            if play_start.is_some() {
+                                play_start = Some(Instant::now());
+                            }
+                            info!("Seeked forward to {:?}", new_pos);
+                            let _ = event_tx.send(AudioEvent::Seeked(new_pos));
+                        }
+                    }
+                    Ok(AudioCommand::SeekBackward) => {
+                        let current_ms =
+                            get_current_position_ms(accumulated_ms, &play_start, speed, section);
+                        let new_pos_ms = current_ms.saturating_sub(seek_step.as_millis() as u64);
+                        let new_pos = Duration::from_millis(new_pos_ms);
+
+                        // Backward seeking requires reloading the file since most decoders
+                        // don't support true backward seeking
+                        let was_playing = play_start.is_some();
+                        if was_playing {
+                            fade_out(&sink, &dsp, ramp);
+                        }
+                        end_crossfade(&mut fading, &sink, volume * gain_factor);
+                        ending = false;
+                        if reload_and_seek(&sink, &current_file_path, new_pos, &dsp) {
+                            accumulated_ms = new_pos_ms;
+                            if was_playing {
+                                fade_in(&dsp);
+                                sink.play();
+                                play_start = Some(Instant::now());
+                            } else {
+                                sink.pause();
+                                play_start = None;
+                            }
+                            info!("Seeked backward to {:?}", new_pos);
+                            let _ = event_tx.send(AudioEvent::Seeked(new_pos));
+                        } else {
+                            info!("Seek backward failed");
+                            if was_playing {
+                                fade_in(&dsp);
+                            }
+                        }
+                    }
+                    Ok(AudioCommand::SetSeekStep(step)) => {
+                        seek_step = step;
+                        info!("Seek step set to {:?}", step);
+                    }
+                    Ok(AudioCommand::SetVolume(new_volume)) => {
+                        volume = new_volume;
+                        sink.set_volume(volume * gain_factor);
+                        info!("Volume set to {}", volume);
+                    }
+                    Ok(AudioCommand::SetReplayGain(options)) => {
+                        replaygain = options;
+                        gain_factor = current_gain.factor(&replaygain, in_album);
+                        sink.set_volume(volume * gain_factor);
+                        info!(
+                            "ReplayGain set to {:?}, gain {:.2}",
+                            replaygain, gain_factor
+                        );
+                    }
+                    Ok(AudioCommand::SetEqualizer(curve)) => {
+                        info!("Equalizer set to {:?}", curve);
+                        dsp.update(|params| params.equalizer = curve);
+                    }
+                    Ok(AudioCommand::SetSpeed(new_speed)) => {
+                        // the time played so far counts at the old speed
+                        accumulated_ms =
+                            get_current_position_ms(accumulated_ms, &play_start, speed, section);
+                        if play_start.is_some() {
+                            play_start = Some(Instant::now());
+                        }
+                        speed = new_speed;
+                        dsp.update(|params| params.speed = speed);
+                        info!("Speed set to {}", speed);
+                    }
+                    Ok(AudioCommand::SetPitch(semitones)) => {
+                        dsp.update(|params| params.pitch_semitones = semitones);
+                        info!("Pitch set to {:+} semitones", semitones);
+                    }
+                    Ok(AudioCommand::SetSection(new_section)) => {
+                        // the previous track would loop along, it shares the parameters
+                        if fading.is_some() {
+                            fade_out(&sink, &dsp, ramp);
+                            end_crossfade(&mut fading, &sink, volume * gain_factor);
+                            fade_in(&dsp);
+                        }
+                        ending = false;
+                        // the time played so far wraps around in the old loop
+                        accumulated_ms =
+                            get_current_position_ms(accumulated_ms, &play_start, speed, section);
+                        if play_start.is_some() {
+                            play_start = Some(Instant::now());
+                        }
+                        // past the end of the new loop the source jumps to its start right away
+                        let position = Duration::from_millis(accumulated_ms);
+                        let target = seek_target(position, new_section);
+                        if target != position {
+                            accumulated_ms = target.as_millis() as u64;
+                            let _ = event_tx.send(AudioEvent::Seeked(target));
+                        }
+                        section = new_section;
+                        dsp.update(|params| params.section = section);
+                        info!("A-B loop set to {:?}", section);
+                    }
+                    Ok(AudioCommand::SetRamp(new_ramp)) => {
+                        ramp = new_ramp;
+                        dsp.update(|params| params.ramp = ramp);
+                        info!("Volume ramps set to {:?}", ramp);
+                    }
+                    Ok(AudioCommand::SetCrossfade(new_crossfade)) => {
+                        crossfade = new_crossfade;
+                        ending = false;
+                        info!("Crossfade set to {:?}", crossfade);
+                    }
+                    Ok(AudioCommand::SetChannels(channels)) => {
+                        dsp.update(|params| params.channels = channels);
+                        info!("Channels set to {:?}", channels);
+                    }
+                    // applied above
+                    Ok(AudioCommand::SetOutput(_)) => {}
+                    Ok(AudioCommand::Detach) => {
+                        // Keep the thread alive to let audio play, but stop processing commands
+                        sink.sleep_until_end();
+                        info!("Audio detached and finished");
+                        break;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        info!("Audio thread shutting down");
+                        break;
+                    }
+                }
+
+                // the volumes follow the crossfade, whatever the command set them to
+                if let Some(previous) = &fading {
+                    match previous.weights() {
+                        Some((fade_out, fade_in)) => {
+                            previous
+                                .sink
+                                .set_volume(volume * previous.gain_factor * fade_out);
+                            sink.set_volume(volume * gain_factor * fade_in);
+                        }
+                        None => end_crossfade(&mut fading, &sink, volume * gain_factor),
+                    }
+                }
+
+                *thread_status.lock().unwrap() = AudioStatus {
+                    duration: current_duration,
+                    position: Duration::from_millis(accumulated_ms),
+                    playing_since: play_start,
+                    speed,
+                    section,
+                };
+            }
+        });
+
+        Self {
+            command_tx,
+            status,
+            dsp: thread_dsp,
+            _thread_handle: Some(thread_handle),
+        }
+    }
+
+    /// Snapshot of the playback state as of the last processed command
+    pub fn status(&self) -> AudioStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// load a new song
+    /// this method accepts String class file path. `in_album` tells whether it plays as part of
+    /// its album, which selects the album gain in `ReplayGainMode::Auto`. `measured` is used
+    /// when the file has no ReplayGain tags.
+    pub fn load(&self, path: String, in_album: bool, measured: ReplayGain) {
+        let _ = self
+            .command_tx
+            .send(AudioCommand::Load(path, in_album, measured));
+    }
+
+    /// Start or resume playback (non-blocking)
+    pub fn play(&self) {
+        let _ = self.command_tx.send(AudioCommand::Play);
+    }
+
+    /// Pause playback (non-blocking)
+    pub fn pause(&self) {
+        let _ = self.command_tx.send(AudioCommand::Pause);
+    }
+
+    /// Stop playback (non-blocking)
+    pub fn stop(&self) {
+        let _ = self.command_tx.send(AudioCommand::Stop);
+    }
+
+    /// Seek to a specific position (non-blocking)
+    pub fn seek_to(&self, position: Duration) {
+        let _ = self.command_tx.send(AudioCommand::SeekTo(position));
+    }
+
+    /// Seek forward by the configured seek step (non-blocking)
+    pub fn seek_forward(&self) {
+        let _ = self.command_tx.send(AudioCommand::SeekForward);
+    }
+
+    /// Seek backward by the configured seek step (non-blocking)
+    pub fn seek_backward(&self) {
+        let _ = self.command_tx.send(AudioCommand::SeekBackward);
+    }
+
+    /// Change the step used by `seek_forward`/`seek_backward` (non-blocking)
+    pub fn set_seek_step(&self, step: Duration) {
+        let _ = self.command_tx.send(AudioCommand::SetSeekStep(step));
+    }
+
+    /// Set the volume, 1.0 is the original loudness (non-blocking)
+    pub fn set_volume(&self, volume: f32) {
+        let _ = self.command_tx.send(AudioCommand::SetVolume(volume));
+    }
+
+    /// Change how ReplayGain tags adjust the volume, applied to the current track right away
+    /// (non-blocking)
+    pub fn set_replaygain(&self, options: ReplayGainOptions) {
+        let _ = self.command_tx.send(AudioCommand::SetReplayGain(options));
+    }
+
+    /// Change the equalizer, the playing track glides to the new curve (non-blocking)
+    pub fn set_equalizer(&self, curve: EqCurve) {
+        let _ = self.command_tx.send(AudioCommand::SetEqualizer(curve));
+    }
+
+    /// Play `speed` times faster without changing the pitch, the position follows (non-blocking)
+    pub fn set_speed(&self, speed: f32) {
+        let _ = self.command_tx.send(AudioCommand::SetSpeed(speed));
+    }
+
+    /// Shift the pitch by `semitones` without changing the speed (non-blocking)
+    pub fn set_pitch(&self, semitones: f32) {
+        let _ = self.command_tx.send(AudioCommand::SetPitch(semitones));
+    }
+
+    /// Loop between the start and end of `section` seamlessly, or play on when `None`. The loop
+    /// is cleared by the next `load` (non-blocking).
+    pub fn set_section(&self, section: Option<(Duration, Duration)>) {
+        let _ = self.command_tx.send(AudioCommand::SetSection(section));
+    }
+
+    /// Set how long the volume ramps take on pause, resume, stop, seek and track switch
+    /// (non-blocking)
+    pub fn set_ramp(&self, ramp: Duration) {
+        let _ = self.command_tx.send(AudioCommand::SetRamp(ramp));
+    }
+
+    /// Set how long a track that plays until its end crossfades with the next one, zero plays
+    /// them one after the other (non-blocking)
+    pub fn set_crossfade(&self, crossfade: Duration) {
+        let _ = self.command_tx.send(AudioCommand::SetCrossfade(crossfade));
+    }
+
+    /// Change the balance, mono downmix and channel swap, gliding within a few milliseconds
+    /// (non-blocking)
+    pub fn set_channels(&self, channels: ChannelSettings) {
+        let _ = self.command_tx.send(AudioCommand::SetChannels(channels));
+    }
+
+    /// Spectrum with `bands` bands, waveform and levels of the last frames played
+    pub fn spectrum(&self, bands: usize) -> Spectrum {
+        self.dsp.tap.spectrum(bands)
+    }
+
+    /// Move playback to the output described by `settings`, continuing the current track at
+    /// the same position (non-blocking)
+    pub fn set_output(&self, settings: AudioSettings) {
+        let _ = self.command_tx.send(AudioCommand::SetOutput(settings));
+    }
+
+    /// Detach the audio - it will continue playing until finished.
+    /// The background thread will keep running until the audio completes.
+    pub fn detach(self) {
+        let _ = self.command_tx.send(AudioCommand::Detach);
+        // Don't join the thread - let it run independently
+        // The thread handle is dropped, but the thread continues
+    }
+}
+
+/// Errors reported by the audio thread when loading a track
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum AudioPlayerError {
+    /// No audio output could be opened
+    OutputStreamError(String),
+    /// The file could not be opened
+    FileError(String),
+    /// The file is not a supported audio format
+    DecodeError(String),
+    /// The decoded track could not be played
+    SinkError(String),
+}
+
+impl std::fmt::Display for AudioPlayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioPlayerError::OutputStreamError(e) => write!(f, "Output stream error: {}", e),
+            AudioPlayerError::FileError(e) => write!(f, "File error: {}", e),
+            AudioPlayerError::DecodeError(e) => write!(f, "Decode error: {}", e),
+            AudioPlayerError::SinkError(e) => write!(f, "Sink error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AudioPlayerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn position_wraps_around_in_the_section() {
+        let section = Some((ms(1000), ms(3000)));
+        assert_eq!(looped(ms(500), section), ms(500));
+        assert_eq!(looped(ms(2999), section), ms(2999));
+        assert_eq!(looped(ms(3000), section), ms(1000));
+        assert_eq!(looped(ms(4500), section), ms(2500));
+        // played through the section several times
+        assert_eq!(looped(ms(7250), section), ms(1250));
+        assert_eq!(looped(ms(7250), None), ms(7250));
+    }
+
+    #[test]
+    fn position_stays_at_the_start_of_an_empty_section() {
+        let section = Some((ms(2000), ms(2000)));
+        assert_eq!(looped(ms(2000), section), ms(2000));
+        assert_eq!(looped(ms(9999), section), ms(2000));
+    }
+
+    #[test]
+    fn seeks_past_the_section_go_to_its_start() {
+        let section = Some((ms(1000), ms(3000)));
+        assert_eq!(seek_target(ms(500), section), ms(500));
+        assert_eq!(seek_target(ms(2000), section), ms(2000));
+        assert_eq!(seek_target(ms(3000), section), ms(1000));
+        assert_eq!(seek_target(ms(60_000), section), ms(1000));
+        assert_eq!(seek_target(ms(60_000), None), ms(60_000));
+    }
+}
@@ -0,0 +1,791 @@
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::error;
+
+use crate::equalizer::{EqCurve, Equalizer};
+use crate::settings::ChannelSettings;
+use crate::spectrum::{CHUNK_FRAMES, SampleTap};
+use crate::stretch::{Resampler, TimeStretch};
+
+/// Frames between two checks for new parameters, which is also the step of every glide
+const UPDATE_FRAMES: usize = 64;
+/// How long the end of an A–B loop is crossfaded with its start
+const LOOP_CROSSFADE: Duration = Duration::from_millis(10);
+/// How long a change of the channel mix glides, so it doesn't click
+const CHANNEL_GLIDE: Duration = Duration::from_millis(20);
+/// Left and right output as they are
+const IDENTITY_MIX: ChannelMix = [[1.0, 0.0], [0.0, 1.0]];
+
+/// How much of the left and right input go into the left and right output
+type ChannelMix = [[f32; 2]; 2];
+
+/// Everything the DSP stage applies
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DspParams {
+    /// The equalizer curve, flat when the equalizer is off
+    pub(crate) equalizer: EqCurve,
+    /// Tempo factor, 1.0 plays at the original speed
+    pub(crate) speed: f32,
+    /// Pitch shift in semitones, independent of the speed
+    pub(crate) pitch_semitones: f32,
+    /// Start and end of the A–B loop
+    pub(crate) section: Option<(Duration, Duration)>,
+    /// Balance, mono and channel swap
+    pub(crate) channels: ChannelSettings,
+    /// Volume the source ramps to, 0.0 before pausing and 1.0 while playing
+    pub(crate) gain: f32,
+    /// How long a ramp from silence to full volume takes
+    pub(crate) ramp: Duration,
+}
+
+impl Default for DspParams {
+    fn default() -> Self {
+        Self {
+            equalizer: EqCurve::default(),
+            speed: 1.0,
+            pitch_semitones: 0.0,
+            section: None,
+            channels: ChannelSettings::default(),
+            gain: 1.0,
+            ramp: Duration::ZERO,
+        }
+    }
+}
+
+impl DspParams {
+    /// How much faster the input is consumed by the time stretch and by the resampler: pitch
+    /// shifting resamples, and the time stretch makes up for the tempo change it causes
+    fn rates(&self) -> (f64, f64) {
+        let pitch = 2f64.powf(self.pitch_semitones as f64 / 12.0);
+        (self.speed as f64 / pitch, pitch)
+    }
+
+    /// The mix of the first two channels for the channel settings
+    fn channel_mix(&self) -> ChannelMix {
+        let ChannelSettings {
+            balance,
+            mono,
+            swap,
+        } = self.channels;
+        let mut mix = match (mono, swap) {
+            (true, _) => [[0.5, 0.5], [0.5, 0.5]],
+            (false, true) => [[0.0, 1.0], [1.0, 0.0]],
+            (false, false) => IDENTITY_MIX,
+        };
+        // the side turned towards keeps its full volume
+        let gains = [(1.0 - balance).min(1.0), (1.0 + balance).min(1.0)];
+        for (row, gain) in mix.iter_mut().zip(gains) {
+            row.iter_mut().for_each(|weight| *weight *= gain);
+        }
+        mix
+    }
+}
+
+/// Parameters shared between the audio thread, which changes them, and the playing source. The
+/// source only takes the lock when the generation changed.
+#[derive(Default)]
+pub(crate) struct DspControl {
+    generation: AtomicU64,
+    params: Mutex<DspParams>,
+    /// Set by the source once its gain ramped down to silence
+    silent: AtomicBool,
+    /// Number of sources created, the newest one owns `silent` and the tap. An older one still
+    /// playing out in a crossfade leaves them alone.
+    sources: AtomicU64,
+    /// The processed frames, for visualizers
+    pub(crate) tap: SampleTap,
+}
+
+impl DspControl {
+    /// Change the parameters, the playing source follows within a few milliseconds
+    pub(crate) fn update(&self, change: impl FnOnce(&mut DspParams)) {
+        change(&mut self.params.lock().unwrap());
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Whether the playing source faded out completely, it holds its input until the gain goes up
+    /// again
+    pub(crate) fn is_silent(&self) -> bool {
+        self.silent.load(Ordering::Acquire)
+    }
+
+    /// The parameters, when they changed since generation `seen`
+    fn changed(&self, seen: &mut u64) -> Option<DspParams> {
+        let generation = self.generation.load(Ordering::Acquire);
+        if generation == *seen {
+            return None;
+        }
+        *seen = generation;
+        Some(self.params.lock().unwrap().clone())
+    }
+}
+
+/// Runs between the decoder and the sink, processing one frame at a time: A–B loop, time
+/// stretch, pitch shift, the equalizer, the channel mix, then the volume ramp. What comes out is
+/// copied to the tap of the control for visualizers.
+pub(crate) struct DspSource<S> {
+    input: LoopingInput<S>,
+    control: Arc<DspControl>,
+    generation: u64,
+    /// Which of the sources of the control this is
+    id: u64,
+    /// Whether this is still the newest source of the control, checked with the parameters
+    newest: bool,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    params: DspParams,
+    /// Only present while stretching, or finishing to stretch
+    stretch: Option<TimeStretch>,
+    /// Created on the first pitch shift, then kept, as it holds some frames
+    resampler: Option<Resampler>,
+    equalizer: Equalizer,
+    /// Current mix of the first two channels, gliding towards the one of `params.channels`
+    mix: ChannelMix,
+    /// Current volume of the ramp towards `params.gain`
+    gain: f32,
+    /// Processed frames not handed to the tap yet
+    tap_chunk: Vec<[f32; 2]>,
+    /// The processed frame being handed out
+    frame: Vec<f32>,
+    frame_len: usize,
+    position: usize,
+    frames_until_update: usize,
+}
+
+impl<S: Source> DspSource<S> {
+    /// Process `input` with the parameters of `control`
+    pub(crate) fn new(input: S, control: Arc<DspControl>) -> Self {
+        let (channels, sample_rate) = (input.channels(), input.sample_rate());
+        let generation = control.generation.load(Ordering::Acquire);
+        let id = control.sources.fetch_add(1, Ordering::AcqRel) + 1;
+        let params = control.params.lock().unwrap().clone();
+        let mut input = LoopingInput::new(input);
+        input.set_section(params.section);
+        let mut source = Self {
+            equalizer: Equalizer::new(&params.equalizer, channels as usize, sample_rate),
+            mix: params.channel_mix(),
+            gain: params.gain,
+            input,
+            control,
+            generation,
+            id,
+            newest: true,
+            channels,
+            sample_rate,
+            params: DspParams::default(),
+            stretch: None,
+            resampler: None,
+            tap_chunk: Vec::with_capacity(CHUNK_FRAMES),
+            frame: vec![0.0; channels as usize],
+            frame_len: 0,
+            position: 0,
+            frames_until_update: 0,
+        };
+        source.set_rates(&params);
+        source
+            .control
+            .silent
+            .store(params.gain == 0.0, Ordering::Release);
+        source.params = params;
+        source
+    }
+
+    /// Start, change or finish the time stretch and pitch shift for `params`
+    fn set_rates(&mut self, params: &DspParams) {
+        let (stretch_rate, pitch_rate) = params.rates();
+        let (channels, sample_rate) = (self.channels as usize, self.sample_rate);
+        match &mut self.stretch {
+            Some(stretch) if stretch_rate == 1.0 => stretch.finish(),
+            Some(stretch) => stretch.set_rate(stretch_rate),
+            None if stretch_rate != 1.0 => {
+                self.stretch = Some(TimeStretch::new(channels, sample_rate, stretch_rate))
+            }
+            None => {}
+        }
+        match &mut self.resampler {
+            Some(resampler) => resampler.set_ratio(pitch_rate),
+            None if pitch_rate != 1.0 => {
+                self.resampler = Some(Resampler::new(channels, pitch_rate))
+            }
+            None => {}
+        }
+    }
+
+    /// Read and process the next frame, false at the end of the input
+    fn next_frame(&mut self) -> bool {
+        let (channels, sample_rate) = (
+            self.input.source.channels(),
+            self.input.source.sample_rate(),
+        );
+        if (channels, sample_rate) != (self.channels, self.sample_rate) {
+            self.channels = channels;
+            self.sample_rate = sample_rate;
+            self.frame = vec![0.0; channels as usize];
+            self.equalizer.set_format(channels as usize, sample_rate);
+            // buffered frames of the old format are dropped
+            self.stretch = None;
+            self.resampler = None;
+            let params = self.params.clone();
+            self.set_rates(&params);
+        }
+
+        if self.frames_until_update == 0 {
+            self.frames_until_update = UPDATE_FRAMES;
+            self.newest = self.control.sources.load(Ordering::Acquire) == self.id;
+            if let Some(params) = self.control.changed(&mut self.generation) {
+                self.equalizer.set_curve(&params.equalizer);
+                self.input.set_section(params.section);
+                self.set_rates(&params);
+                self.params = params;
+            }
+            if self.stretch.as_ref().is_some_and(TimeStretch::is_drained) {
+                self.stretch = None;
+            }
+            self.equalizer.update();
+        }
+        self.frames_until_update -= 1;
+
+        // faded out: silence, without using up the input until the gain goes up again
+        if self.gain == 0.0 && self.params.gain == 0.0 {
+            self.frame.fill(0.0);
+            self.tap();
+            self.frame_len = self.frame.len();
+            self.position = 0;
+            return true;
+        }
+
+        let Self {
+            input,
+            stretch,
+            resampler,
+            frame,
+            ..
+        } = self;
+        let mut read_input = |frame: &mut [f32]| input.read(frame);
+        let mut read_stretched = |frame: &mut [f32]| match stretch {
+            Some(stretch) => stretch.next_frame(frame, &mut read_input),
+            None => read_input(frame),
+        };
+        let read = match resampler {
+            Some(resampler) => resampler.next_frame(frame, &mut read_stretched),
+            None => read_stretched(frame),
+        };
+        if !read {
+            return false;
+        }
+
+        if !self.equalizer.is_flat() {
+            self.equalizer.process(&mut self.frame);
+        }
+        self.mix_channels();
+        self.ramp();
+        self.tap();
+        self.frame_len = self.frame.len();
+        self.position = 0;
+        true
+    }
+}
+
+impl<S> DspSource<S> {
+    /// Move the channel mix one frame closer to the settings and apply it to the first two
+    /// channels of the frame, mono tracks stay as they are
+    fn mix_channels(&mut self) {
+        let target = self.params.channel_mix();
+        if self.mix != target {
+            let frames = CHANNEL_GLIDE.as_secs_f32() * self.sample_rate as f32;
+            let step = 1.0 / frames.max(1.0);
+            for (row, target_row) in self.mix.iter_mut().zip(target) {
+                for (weight, target) in row.iter_mut().zip(target_row) {
+                    *weight = if target > *weight {
+                        (*weight + step).min(target)
+                    } else {
+                        (*weight - step).max(target)
+                    };
+                }
+            }
+        }
+        if self.mix == IDENTITY_MIX || self.frame.len() < 2 {
+            return;
+        }
+        let (left, right) = (self.frame[0], self.frame[1]);
+        for (sample, [from_left, from_right]) in self.frame.iter_mut().zip(self.mix) {
+            *sample = from_left * left + from_right * right;
+        }
+    }
+
+    /// Move the gain one frame closer to `params.gain` and apply it to the frame
+    fn ramp(&mut self) {
+        let target = self.params.gain;
+        if self.gain != target {
+            let frames = self.params.ramp.as_secs_f32() * self.sample_rate as f32;
+            let step = 1.0 / frames.max(1.0);
+            let was_silent = self.gain == 0.0;
+            self.gain = if target > self.gain {
+                (self.gain + step).min(target)
+            } else {
+                (self.gain - step).max(target)
+            };
+            if was_silent != (self.gain == 0.0) && self.newest {
+                self.control
+                    .silent
+                    .store(self.gain == 0.0, Ordering::Release);
+            }
+        }
+        if self.gain != 1.0 {
+            self.frame
+                .iter_mut()
+                .for_each(|sample| *sample *= self.gain);
+        }
+    }
+
+    /// Collect the frame for the tap, a mono frame goes to both channels
+    fn tap(&mut self) {
+        let Some(&left) = self.frame.first().filter(|_| self.newest) else {
+            return;
+        };
+        let right = self.frame.get(1).copied().unwrap_or(left);
+        self.tap_chunk.push([left, right]);
+        if self.tap_chunk.len() == CHUNK_FRAMES {
+            self.control.tap.write(&self.tap_chunk, self.sample_rate);
+            self.tap_chunk.clear();
+        }
+    }
+}
+
+impl<S: Source> Iterator for DspSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.frame_len && !self.next_frame() {
+            return None;
+        }
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for DspSource<S> {
+    // while stretching or looping the spans of the input can't be followed, the format stays
+    // the same
+    fn current_span_len(&self) -> Option<usize> {
+        if self.stretch.is_some() || self.resampler.is_some() || self.input.section.is_some() {
+            return None;
+        }
+        let buffered = self.frame_len - self.position;
+        self.input
+            .source
+            .current_span_len()
+            .map(|len| len + buffered)
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.source.total_duration()
+    }
+
+    // the rest of the buffered frame is still handed out, which keeps the channels aligned
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.input.seek(position)?;
+        if let Some(stretch) = &mut self.stretch {
+            stretch.clear();
+        }
+        if let Some(resampler) = &mut self.resampler {
+            resampler.clear();
+        }
+        Ok(())
+    }
+}
+
+/// The decoder, counting the frames read so it can jump back at the end of an A–B loop
+struct LoopingInput<S> {
+    source: S,
+    /// Frames read since the start of the track
+    position: u64,
+    /// Start and end of the loop, in frames
+    section: Option<(u64, u64)>,
+    /// The last frames before the loop end, faded out while the loop start fades in
+    tail: Vec<f32>,
+    /// Frames of `tail` mixed in so far
+    faded: usize,
+}
+
+impl<S: Source> LoopingInput<S> {
+    fn new(source: S) -> Self {
+        Self {
+            source,
+            position: 0,
+            section: None,
+            tail: Vec::new(),
+            faded: 0,
+        }
+    }
+
+    fn frames(&self, time: Duration) -> u64 {
+        (time.as_secs_f64() * self.source.sample_rate() as f64).round() as u64
+    }
+
+    /// Loop between the start and end of `section`, or play on without one
+    fn set_section(&mut self, section: Option<(Duration, Duration)>) {
+        self.section = section.map(|(start, end)| (self.frames(start), self.frames(end)));
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(position)?;
+        self.position = self.frames(position);
+        self.tail.clear();
+        self.faded = 0;
+        Ok(())
+    }
+
+    /// Read the next frame, false at the end of the input
+    fn read(&mut self, frame: &mut [f32]) -> bool {
+        let channels = frame.len();
+        let fading = self.tail.len() / channels;
+        if let Some((start, end)) = self.section
+            && self.faded == fading
+        {
+            // the frames before the end are mixed with those before the start, which keeps the
+            // loop exactly as long as the section
+            let crossfade = self
+                .frames(LOOP_CROSSFADE)
+                .min(end.saturating_sub(start) / 2)
+                .min(start);
+            if self.position >= end {
+                // nothing to fade out, e.g. right after seeking past the end
+                self.jump(start, end, channels);
+            } else if self.position + crossfade >= end {
+                self.jump(start - crossfade, end, channels);
+            }
+        }
+
+        let fading = self.tail.len() / channels;
+        if self.faded < fading {
+            // a loop start at the very end of the track fades in from silence
+            if !read_frame(&mut self.source, frame) {
+                frame.fill(0.0);
+            }
+            let weight = (self.faded + 1) as f32 / (fading + 1) as f32;
+            let tail = &self.tail[self.faded * channels..(self.faded + 1) * channels];
+            for (sample, old) in frame.iter_mut().zip(tail) {
+                *sample = *sample * weight + old * (1.0 - weight);
+            }
+            self.faded += 1;
+            self.position += 1;
+            return true;
+        }
+
+        if !read_frame(&mut self.source, frame) {
+            return false;
+        }
+        self.position += 1;
+        true
+    }
+
+    /// Read ahead up to `end` into the tail, then go back to `target`
+    fn jump(&mut self, target: u64, end: u64, channels: usize) {
+        self.tail.clear();
+        self.faded = 0;
+        let mut frame = vec![0.0; channels];
+        while self.position < end && read_frame(&mut self.source, &mut frame) {
+            self.tail.extend_from_slice(&frame);
+            self.position += 1;
+        }
+
+        let rate = self.source.sample_rate() as f64;
+        match self
+            .source
+            .try_seek(Duration::from_secs_f64(target as f64 / rate))
+        {
+            Ok(()) => self.position = target,
+            // the tail fades into what follows it instead
+            Err(e) => {
+                error!("Unable to jump back to the loop start: {}", e);
+                self.section = None;
+            }
+        }
+    }
+}
+
+/// Read a whole frame from `input`, a frame cut short at the end is completed with silence
+fn read_frame(input: &mut impl Source, frame: &mut [f32]) -> bool {
+    for (index, sample) in frame.iter_mut().enumerate() {
+        match input.next() {
+            Some(value) => *sample = value,
+            None if index == 0 => return false,
+            None => *sample = 0.0,
+        }
+    }
+    true
+}
+
+/// Second order IIR filter, transposed direct form II
+#[derive(Clone)]
+pub(crate) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// A filter with normalized coefficients, `a` without the leading 1
+    pub(crate) fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    /// Change the coefficients, keeping the state
+    pub(crate) fn set_coefficients(&mut self, b: [f64; 3], a: [f64; 2]) {
+        self.b = b;
+        self.a = a;
+    }
+
+    /// Filter the next sample
+    pub(crate) fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn mix(balance: f32, mono: bool, swap: bool) -> ChannelMix {
+        DspParams {
+            channels: ChannelSettings {
+                balance,
+                mono,
+                swap,
+            },
+            ..DspParams::default()
+        }
+        .channel_mix()
+    }
+
+    #[test]
+    fn channel_mix_follows_the_settings() {
+        assert_eq!(mix(0.0, false, false), IDENTITY_MIX);
+        assert_eq!(mix(0.0, true, false), [[0.5, 0.5], [0.5, 0.5]]);
+        assert_eq!(mix(0.0, false, true), [[0.0, 1.0], [1.0, 0.0]]);
+        // mono wins over swapping, which makes no difference then
+        assert_eq!(mix(0.0, true, true), mix(0.0, true, false));
+    }
+
+    #[test]
+    fn balance_turns_down_the_other_side() {
+        assert_eq!(mix(-1.0, false, false), [[1.0, 0.0], [0.0, 0.0]]);
+        assert_eq!(mix(1.0, false, false), [[0.0, 0.0], [0.0, 1.0]]);
+        assert_eq!(mix(0.5, false, false), [[0.5, 0.0], [0.0, 1.0]]);
+        // balance applies to the outputs, after swapping
+        assert_eq!(mix(-0.5, false, true), [[0.0, 1.0], [0.5, 0.0]]);
+        assert_eq!(mix(1.0, true, false), [[0.0, 0.0], [0.5, 0.5]]);
+    }
+
+    #[test]
+    fn only_the_newest_source_reports_silence_and_fills_the_tap() {
+        let sample_rate = 48_000;
+        let control = Arc::new(DspControl::default());
+        let input = || SamplesBuffer::new(2, sample_rate, vec![0.5; 2 * CHUNK_FRAMES * 4]);
+        let mut previous = DspSource::new(input(), Arc::clone(&control));
+        let mut current = DspSource::new(input(), Arc::clone(&control));
+
+        // the previous track of a crossfade fades out on its own
+        control.update(|params| params.gain = 0.0);
+        previous.by_ref().take(2 * CHUNK_FRAMES * 2).for_each(drop);
+        assert!(!control.is_silent());
+        assert!(control.tap.spectrum(4).waveform.is_empty());
+
+        current.by_ref().take(2 * CHUNK_FRAMES * 2).for_each(drop);
+        assert!(control.is_silent());
+    }
+
+    #[test]
+    fn source_glides_to_a_new_mix() {
+        let sample_rate = 48_000;
+        // left at 1.0, right at 0.25
+        let frames = sample_rate as usize;
+        let samples = [1.0, 0.25].repeat(frames);
+        let control = Arc::new(DspControl::default());
+        let input = SamplesBuffer::new(2, sample_rate, samples);
+        let mut source = DspSource::new(input, Arc::clone(&control));
+        let mut next = || [source.next().unwrap(), source.next().unwrap()];
+
+        assert_eq!(next(), [1.0, 0.25]);
+        control.update(|params| params.channels.swap = true);
+
+        // new parameters are picked up within `UPDATE_FRAMES`, then the mix moves a step per
+        // frame
+        let [left, right] = (0..=UPDATE_FRAMES)
+            .map(|_| next())
+            .find(|frame| frame[0] < 1.0)
+            .unwrap();
+        assert!(left > 0.9, "{}", left);
+        assert!(right > 0.25 && right < 0.35, "{}", right);
+
+        let glide = (CHANNEL_GLIDE.as_secs_f32() * sample_rate as f32) as usize;
+        for _ in 0..glide {
+            next();
+        }
+        assert_eq!(next(), [0.25, 1.0]);
+    }
+
+    #[test]
+    fn mono_sources_are_left_alone() {
+        let control = Arc::new(DspControl::default());
+        control.update(|params| {
+            params.channels = ChannelSettings {
+                balance: 1.0,
+                mono: false,
+                swap: true,
+            }
+        });
+        let source = DspSource::new(SamplesBuffer::new(1, 48_000, vec![0.5; 100]), control);
+        assert!(source.take(100).all(|sample| sample == 0.5));
+    }
+
+    /// A mono source at 1 kHz over `samples`, ramping over `ramp` milliseconds
+    fn ramping(samples: Vec<f32>, ramp: u64) -> (Arc<DspControl>, DspSource<SamplesBuffer>) {
+        let control = Arc::new(DspControl::default());
+        control.update(|params| params.ramp = Duration::from_millis(ramp));
+        let source = DspSource::new(SamplesBuffer::new(1, 1000, samples), Arc::clone(&control));
+        (control, source)
+    }
+
+    #[test]
+    fn gain_ramps_over_the_ramp_length() {
+        let (control, mut source) = ramping(vec![1.0; 1000], 10);
+        assert_eq!(source.next(), Some(1.0));
+        control.update(|params| params.gain = 0.0);
+
+        // picked up within `UPDATE_FRAMES`, then down a tenth per frame
+        let ramp = source
+            .by_ref()
+            .skip_while(|&sample| sample == 1.0)
+            .take_while(|&sample| sample > 0.0)
+            .collect::<Vec<_>>();
+        assert!((9..=10).contains(&ramp.len()), "{:?}", ramp);
+        assert!((ramp[0] - 0.9).abs() < 1e-6, "{:?}", ramp);
+        assert!(ramp.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", ramp);
+    }
+
+    #[test]
+    fn source_reports_when_it_faded_out_and_back_in() {
+        let (control, mut source) = ramping(vec![1.0; 1000], 10);
+        source.next();
+        assert!(!control.is_silent());
+
+        control.update(|params| params.gain = 0.0);
+        while source.next() != Some(0.0) {
+            assert!(!control.is_silent());
+        }
+        assert!(control.is_silent());
+
+        control.update(|params| params.gain = 1.0);
+        while source.next() == Some(0.0) {
+            assert!(control.is_silent());
+        }
+        assert!(!control.is_silent());
+    }
+
+    #[test]
+    fn faded_out_source_holds_its_input() {
+        // samples are their frame number, the gain switches without a ramp
+        let samples = (0..2000).map(|frame| frame as f32).collect();
+        let (control, mut source) = ramping(samples, 0);
+        let played = source.by_ref().take(UPDATE_FRAMES).collect::<Vec<_>>();
+        assert_eq!(played.last(), Some(&(UPDATE_FRAMES as f32 - 1.0)));
+
+        // the frame read when the gain drops is the last one used
+        control.update(|params| params.gain = 0.0);
+        assert!(source.by_ref().take(1500).all(|sample| sample == 0.0));
+        control.update(|params| params.gain = 1.0);
+        let resumed = source.by_ref().find(|&sample| sample != 0.0);
+        assert_eq!(resumed, Some(UPDATE_FRAMES as f32 + 1.0));
+    }
+
+    /// A mono input at 1 kHz whose samples are their frame number, looping over `section` in
+    /// milliseconds
+    fn looping(section: Option<(u64, u64)>) -> LoopingInput<SamplesBuffer> {
+        let samples = (0..1000).map(|frame| frame as f32).collect::<Vec<_>>();
+        let mut input = LoopingInput::new(SamplesBuffer::new(1, 1000, samples));
+        input.set_section(
+            section.map(|(start, end)| (Duration::from_millis(start), Duration::from_millis(end))),
+        );
+        input
+    }
+
+    fn read(input: &mut LoopingInput<SamplesBuffer>, frames: usize) -> Vec<f32> {
+        let mut frame = [0.0];
+        (0..frames)
+            .map(|_| {
+                assert!(input.read(&mut frame));
+                frame[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn looping_input_crossfades_back_to_the_section_start() {
+        let mut input = looping(Some((100, 200)));
+        let frames = read(&mut input, 400);
+        let crossfade = 10;
+
+        assert!(
+            frames[..200 - crossfade]
+                .iter()
+                .copied()
+                .eq((0..190).map(|f| f as f32))
+        );
+        // the end of the section fades into the frames before its start
+        for (index, &sample) in frames[190..200].iter().enumerate() {
+            let (new, old) = ((90 + index) as f32, (190 + index) as f32);
+            assert!(sample > new && sample < old, "{} {}", index, sample);
+        }
+        // so every pass through the loop is as long as the section
+        assert!(
+            frames[200..290]
+                .iter()
+                .copied()
+                .eq((100..190).map(|f| f as f32))
+        );
+        assert!(
+            frames[300..390]
+                .iter()
+                .copied()
+                .eq((100..190).map(|f| f as f32))
+        );
+    }
+
+    #[test]
+    fn looping_input_seeked_past_the_end_starts_over() {
+        let mut input = looping(Some((100, 200)));
+        input.seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(read(&mut input, 3), [100.0, 101.0, 102.0]);
+
+        input.set_section(None);
+        input.seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(read(&mut input, 1), [500.0]);
+    }
+
+    #[test]
+    fn looping_input_repeats_an_empty_section() {
+        let mut input = looping(Some((150, 150)));
+        let frames = read(&mut input, 200);
+        assert!(frames[..150].iter().copied().eq((0..150).map(|f| f as f32)));
+        assert!(frames[150..].iter().all(|&sample| sample == 150.0));
+    }
+}
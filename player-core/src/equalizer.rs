@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::dsp::Biquad;
+
+/// Centre frequencies of the graphic equalizer bands in Hz
+pub const GRAPHIC_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// Bandwidth of the graphic bands, about one octave
+const GRAPHIC_Q: f32 = 1.41;
+
+/// `(name, pre-amp, band gains)` of the presets that are always available
+const BUILTIN_PRESETS: &[(&str, f32, [f32; 10])] = &[
+    ("Flat", 0.0, [0.0; 10]),
+    (
+        "Rock",
+        -5.0,
+        [5.0, 4.0, 3.0, 1.0, -1.0, -1.0, 1.0, 3.0, 4.0, 5.0],
+    ),
+    (
+        "Pop",
+        -5.0,
+        [-1.0, 0.0, 2.0, 4.0, 5.0, 4.0, 2.0, 0.0, -1.0, -1.0],
+    ),
+    (
+        "Jazz",
+        -4.0,
+        [4.0, 3.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 3.0, 4.0],
+    ),
+    (
+        "Classical",
+        -5.0,
+        [5.0, 4.0, 3.0, 2.0, -1.0, -1.0, 0.0, 2.0, 3.0, 4.0],
+    ),
+    (
+        "Electronic",
+        -5.0,
+        [5.0, 4.0, 1.0, 0.0, -2.0, 2.0, 1.0, 1.0, 4.0, 5.0],
+    ),
+    (
+        "Bass Boost",
+        -6.0,
+        [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ),
+    (
+        "Treble Boost",
+        -6.0,
+        [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 5.0, 6.0],
+    ),
+    (
+        "Vocal",
+        -4.0,
+        [-2.0, -3.0, -3.0, 1.0, 4.0, 4.0, 3.0, 1.0, 0.0, -2.0],
+    ),
+];
+
+/// Name of the user preset a built-in preset is copied to when it is edited
+pub const CUSTOM_PRESET: &str = "Custom";
+
+/// How each new gain, frequency and Q is approached per update, changes sweep over ~20 ms
+/// instead of jumping, which would click
+const SMOOTHING: f32 = 0.08;
+/// Differences below these are snapped to the target
+const GAIN_EPSILON_DB: f32 = 0.01;
+const RATIO_EPSILON: f32 = 0.001;
+/// Filters are kept below the Nyquist frequency
+const MAX_FREQUENCY_RATIO: f32 = 0.45;
+
+/// Which controls a preset uses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EqMode {
+    /// Ten fixed bands, one gain each
+    #[default]
+    Graphic,
+    /// Freely placed filters
+    Parametric,
+}
+
+/// Shape of a parametric filter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    /// Boosts or cuts around the frequency
+    #[default]
+    Peak,
+    /// Boosts or cuts everything below the frequency
+    LowShelf,
+    /// Boosts or cuts everything above the frequency
+    HighShelf,
+}
+
+/// One filter of the equalizer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EqBand {
+    /// Centre or corner frequency in Hz
+    pub frequency: f32,
+    /// Boost, or cut when negative, in dB
+    pub gain_db: f32,
+    /// Bandwidth, higher is narrower
+    #[serde(default = "default_q")]
+    pub q: f32,
+    /// Shape of the filter
+    #[serde(default)]
+    pub kind: FilterKind,
+}
+
+fn default_q() -> f32 {
+    1.0
+}
+
+/// A named equalizer setting with its own pre-amp
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EqPreset {
+    /// Shown in the preset list, selected by `equalizer.preset`
+    pub name: String,
+    /// Gain applied before the filters in dB, usually negative to leave room for boosts
+    #[serde(default)]
+    pub preamp_db: f32,
+    /// Whether `gains_db` or `bands` apply
+    #[serde(default)]
+    pub mode: EqMode,
+    /// Gains of the graphic bands at `GRAPHIC_FREQUENCIES`, in dB
+    #[serde(default)]
+    pub gains_db: [f32; 10],
+    /// Filters of the parametric mode
+    #[serde(default)]
+    pub bands: Vec<EqBand>,
+}
+
+impl EqPreset {
+    /// The filters this preset applies in its mode
+    pub fn filters(&self) -> Vec<EqBand> {
+        match self.mode {
+            EqMode::Graphic => GRAPHIC_FREQUENCIES
+                .iter()
+                .zip(self.gains_db)
+                .map(|(&frequency, gain_db)| EqBand {
+                    frequency,
+                    gain_db,
+                    q: GRAPHIC_Q,
+                    kind: FilterKind::Peak,
+                })
+                .collect(),
+            EqMode::Parametric => self.bands.clone(),
+        }
+    }
+
+    /// The curve the audio thread applies for this preset
+    pub fn curve(&self) -> EqCurve {
+        EqCurve {
+            preamp_db: self.preamp_db,
+            bands: self.filters(),
+        }
+    }
+}
+
+/// The presets that are always available, starting with `Flat`
+pub fn builtin_presets() -> Vec<EqPreset> {
+    BUILTIN_PRESETS
+        .iter()
+        .map(|&(name, preamp_db, gains_db)| EqPreset {
+            name: String::from(name),
+            preamp_db,
+            mode: EqMode::Graphic,
+            gains_db,
+            bands: Vec::new(),
+        })
+        .collect()
+}
+
+/// What the equalizer stage of the audio thread applies, flat when there are no bands and no
+/// pre-amp
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EqCurve {
+    /// Gain applied before the filters in dB
+    pub preamp_db: f32,
+    /// The filters, applied one after the other
+    pub bands: Vec<EqBand>,
+}
+
+/// The equalizer stage of the DSP source. Every band has a slot whose current values glide to
+/// the wanted ones, so changes never click; slots whose band was removed fade to 0 dB first.
+pub(crate) struct Equalizer {
+    channels: usize,
+    sample_rate: u32,
+    preamp: f32,
+    target_preamp: f32,
+    slots: Vec<Slot>,
+}
+
+struct Slot {
+    kind: FilterKind,
+    frequency: f32,
+    gain_db: f32,
+    q: f32,
+    /// `None` once the band was removed
+    target: Option<EqBand>,
+    /// Coefficients are shared, the state is kept per channel
+    filters: Vec<Biquad>,
+}
+
+impl Equalizer {
+    /// An equalizer already applying `curve`
+    pub(crate) fn new(curve: &EqCurve, channels: usize, sample_rate: u32) -> Self {
+        let mut equalizer = Self {
+            channels,
+            sample_rate,
+            preamp: db_to_gain(curve.preamp_db),
+            target_preamp: db_to_gain(curve.preamp_db),
+            slots: Vec::new(),
+        };
+        equalizer.slots = curve
+            .bands
+            .iter()
+            .map(|&band| equalizer.slot(band, band.gain_db))
+            .collect();
+        equalizer
+    }
+
+    fn slot(&self, band: EqBand, gain_db: f32) -> Slot {
+        let mut slot = Slot {
+            kind: band.kind,
+            frequency: band.frequency,
+            gain_db,
+            q: band.q,
+            target: Some(band),
+            filters: vec![Biquad::new([1.0, 0.0, 0.0], [0.0, 0.0]); self.channels],
+        };
+        slot.update_coefficients(self.sample_rate);
+        slot
+    }
+
+    /// Glide to `curve` from the current settings
+    pub(crate) fn set_curve(&mut self, curve: &EqCurve) {
+        self.target_preamp = db_to_gain(curve.preamp_db);
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            slot.target = curve.bands.get(index).copied();
+        }
+        // new bands start out inaudible
+        for &band in curve.bands.iter().skip(self.slots.len()) {
+            let slot = self.slot(band, 0.0);
+            self.slots.push(slot);
+        }
+    }
+
+    /// Start over for a stream with another format, keeping the settings
+    pub(crate) fn set_format(&mut self, channels: usize, sample_rate: u32) {
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        for slot in &mut self.slots {
+            slot.filters = vec![Biquad::new([1.0, 0.0, 0.0], [0.0, 0.0]); channels];
+            slot.update_coefficients(sample_rate);
+        }
+    }
+
+    /// Move every value a step closer to its target, called every few frames
+    pub(crate) fn update(&mut self) {
+        self.preamp = approach(self.preamp, self.target_preamp, |a, b| {
+            (a / b - 1.0).abs() < RATIO_EPSILON
+        });
+        for slot in &mut self.slots {
+            if slot.glide() {
+                slot.update_coefficients(self.sample_rate);
+            }
+        }
+        // bands at 0 dB that stay there, removed ones included, are dropped, so a flat curve
+        // like the "Flat" preset costs nothing
+        while self.slots.last().is_some_and(|slot| {
+            slot.gain_db == 0.0 && slot.target.is_none_or(|target| target.gain_db == 0.0)
+        }) {
+            self.slots.pop();
+        }
+    }
+
+    /// Whether processing would leave the samples unchanged
+    pub(crate) fn is_flat(&self) -> bool {
+        self.slots.is_empty() && self.preamp == 1.0 && self.target_preamp == 1.0
+    }
+
+    /// Filter one frame in place
+    pub(crate) fn process(&mut self, frame: &mut [f32]) {
+        for (channel, sample) in frame.iter_mut().enumerate() {
+            let mut value = (*sample * self.preamp) as f64;
+            for slot in &mut self.slots {
+                value = slot.filters[channel].process(value);
+            }
+            *sample = value as f32;
+        }
+    }
+}
+
+impl Slot {
+    /// Step towards the target, returns whether anything changed
+    fn glide(&mut self) -> bool {
+        let (frequency, gain_db, q) = match self.target {
+            // a new shape can only be taken over while the filter is flat
+            Some(target) if target.kind == self.kind => {
+                (target.frequency, target.gain_db, target.q)
+            }
+            Some(target) if self.gain_db == 0.0 => {
+                self.kind = target.kind;
+                return true;
+            }
+            _ => (self.frequency, 0.0, self.q),
+        };
+        let before = (self.frequency, self.gain_db, self.q);
+        let close_ratio = |a: f32, b: f32| (a / b - 1.0).abs() < RATIO_EPSILON;
+        self.frequency = approach(self.frequency, frequency, close_ratio);
+        self.q = approach(self.q, q, close_ratio);
+        self.gain_db = approach(self.gain_db, gain_db, |a, b| {
+            (a - b).abs() < GAIN_EPSILON_DB
+        });
+        before != (self.frequency, self.gain_db, self.q)
+    }
+
+    /// RBJ audio EQ cookbook coefficients for the current values
+    fn update_coefficients(&mut self, sample_rate: u32) {
+        let rate = sample_rate as f64;
+        let frequency = (self.frequency as f64).clamp(1.0, rate * MAX_FREQUENCY_RATIO as f64);
+        let a = 10f64.powf(self.gain_db as f64 / 40.0);
+        let w0 = 2.0 * PI * frequency / rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q.max(0.01) as f64);
+
+        let (b, a0, a1, a2) = match self.kind {
+            FilterKind::Peak => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            FilterKind::LowShelf => {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + root),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - root),
+                    ],
+                    (a + 1.0) + (a - 1.0) * cos + root,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - root,
+                )
+            }
+            FilterKind::HighShelf => {
+                let root = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + root),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - root),
+                    ],
+                    (a + 1.0) - (a - 1.0) * cos + root,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - root,
+                )
+            }
+        };
+        let b = b.map(|b| b / a0);
+        let a = [a1 / a0, a2 / a0];
+        for filter in &mut self.filters {
+            filter.set_coefficients(b, a);
+        }
+    }
+}
+
+/// One smoothing step from `current` to `target`, snapping once `close`
+fn approach(current: f32, target: f32, close: impl Fn(f32, f32) -> bool) -> f32 {
+    if current == target || close(current, target) {
+        target
+    } else {
+        current + (target - current) * SMOOTHING
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Gain in dB of `equalizer` for a sine of `frequency`, once the filters settled
+    fn response_db(equalizer: &mut Equalizer, frequency: f32) -> f32 {
+        let frames = SAMPLE_RATE as usize;
+        let (mut input, mut output) = (0.0, 0.0);
+        for i in 0..frames {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let sample = (2.0 * std::f32::consts::PI * frequency * t).sin() * 0.25;
+            let mut frame = [sample];
+            equalizer.process(&mut frame);
+            // skip the first half while the filters settle
+            if i >= frames / 2 {
+                input += sample * sample;
+                output += frame[0] * frame[0];
+            }
+        }
+        10.0 * (output / input).log10()
+    }
+
+    fn curve(preamp_db: f32, bands: &[(FilterKind, f32, f32)]) -> EqCurve {
+        EqCurve {
+            preamp_db,
+            bands: bands
+                .iter()
+                .map(|&(kind, frequency, gain_db)| EqBand {
+                    frequency,
+                    gain_db,
+                    q: 0.71,
+                    kind,
+                })
+                .collect(),
+        }
+    }
+
+    fn assert_response(curve: &EqCurve, expected: &[(f32, f32)]) {
+        for &(frequency, gain_db) in expected {
+            let mut equalizer = Equalizer::new(curve, 1, SAMPLE_RATE);
+            let response = response_db(&mut equalizer, frequency);
+            assert!(
+                (response - gain_db).abs() < 0.3,
+                "{} dB at {} Hz instead of {} dB",
+                response,
+                frequency,
+                gain_db
+            );
+        }
+    }
+
+    #[test]
+    fn peak_boosts_around_its_frequency() {
+        let curve = curve(0.0, &[(FilterKind::Peak, 1000.0, 6.0)]);
+        assert_response(&curve, &[(1000.0, 6.0), (50.0, 0.0), (15000.0, 0.0)]);
+    }
+
+    #[test]
+    fn shelves_boost_and_cut_one_side() {
+        let low = curve(0.0, &[(FilterKind::LowShelf, 200.0, 6.0)]);
+        assert_response(&low, &[(30.0, 6.0), (200.0, 3.0), (5000.0, 0.0)]);
+        let high = curve(0.0, &[(FilterKind::HighShelf, 4000.0, -6.0)]);
+        assert_response(&high, &[(100.0, 0.0), (4000.0, -3.0), (18000.0, -6.0)]);
+    }
+
+    #[test]
+    fn preamp_scales_everything() {
+        let curve = curve(-6.0, &[]);
+        assert_response(&curve, &[(100.0, -6.0), (1000.0, -6.0), (10000.0, -6.0)]);
+    }
+
+    #[test]
+    fn graphic_presets_boost_their_bands() {
+        let rock = builtin_presets()
+            .into_iter()
+            .find(|preset| preset.name == "Rock")
+            .unwrap();
+        let mut equalizer = Equalizer::new(&rock.curve(), 1, SAMPLE_RATE);
+        let bass = response_db(&mut equalizer, 31.0);
+        let mids = response_db(&mut equalizer, 1000.0);
+        // the bass band is 6 dB above the mid band, neighbouring bands overlap a little
+        assert!(bass - mids > 5.0, "{} dB bass, {} dB mids", bass, mids);
+        assert!(
+            builtin_presets()[0]
+                .curve()
+                .bands
+                .iter()
+                .all(|band| band.gain_db == 0.0)
+        );
+    }
+
+    #[test]
+    fn a_curve_at_zero_decibels_is_flat() {
+        let flat = builtin_presets()
+            .into_iter()
+            .find(|preset| preset.name == "Flat")
+            .unwrap();
+        let mut equalizer = Equalizer::new(&flat.curve(), 2, SAMPLE_RATE);
+        equalizer.update();
+        assert!(equalizer.is_flat());
+
+        // a band at 0 dB before a boosted one has to stay in its place
+        let mut equalizer = Equalizer::new(
+            &curve(
+                0.0,
+                &[
+                    (FilterKind::Peak, 100.0, 0.0),
+                    (FilterKind::Peak, 1000.0, 3.0),
+                ],
+            ),
+            1,
+            SAMPLE_RATE,
+        );
+        equalizer.update();
+        assert_eq!(equalizer.slots.len(), 2);
+        assert!(!equalizer.is_flat());
+    }
+
+    #[test]
+    fn changes_glide_and_removed_bands_fade_out() {
+        let boost = curve(-3.0, &[(FilterKind::Peak, 1000.0, 6.0)]);
+        let mut equalizer = Equalizer::new(&boost, 1, SAMPLE_RATE);
+        assert!(!equalizer.is_flat());
+
+        equalizer.set_curve(&EqCurve::default());
+        equalizer.update();
+        // one step moves only part of the way
+        assert!(equalizer.slots[0].gain_db > 5.0);
+
+        for _ in 0..1000 {
+            equalizer.update();
+        }
+        assert!(equalizer.is_flat());
+        assert!(equalizer.slots.is_empty());
+        assert!(response_db(&mut equalizer, 1000.0).abs() < 0.01);
+    }
+}
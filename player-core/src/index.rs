@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::error;
+
+use crate::loudness::Loudness;
+use crate::waveform::Waveform;
+
+const CACHE_DIR_NAME: &str = "music-player";
+const INDEX_FILE_NAME: &str = "library.json";
+
+/// What was measured about one song
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Modification time of the file when it was measured, in seconds since the epoch
+    modified: u64,
+    loudness: Option<Loudness>,
+    waveform: Option<Waveform>,
+}
+
+/// Results of analysing the songs, kept between runs. An entry is only used while the file is
+/// unchanged.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    songs: HashMap<PathBuf, IndexEntry>,
+}
+
+impl LibraryIndex {
+    /// `$XDG_CACHE_HOME/music-player/library.json` on Linux
+    pub fn path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join(CACHE_DIR_NAME).join(INDEX_FILE_NAME))
+    }
+
+    /// The index saved by an earlier run, empty when there is none or it can't be read
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents)
+            .inspect_err(|e| error!("Ignoring invalid library index {}: {}", path.display(), e))
+            .unwrap_or_default()
+    }
+
+    /// Write the index to `path`, creating its directory
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no cache directory available")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(self)?)
+    }
+
+    /// The measured loudness of the song at `path`, unless it changed since
+    pub fn loudness(&self, path: &Path) -> Option<Loudness> {
+        self.songs
+            .get(path)
+            .filter(|entry| modified(path) == Some(entry.modified))
+            .and_then(|entry| entry.loudness)
+    }
+
+    /// Remember the loudness of the song at `path` as it is now
+    pub fn set_loudness(&mut self, path: &Path, loudness: Loudness) {
+        if let Some(entry) = self.entry(path) {
+            entry.loudness = Some(loudness);
+        }
+    }
+
+    /// The waveform overview of the song at `path`, unless it changed since
+    pub fn waveform(&self, path: &Path) -> Option<&Waveform> {
+        self.songs
+            .get(path)
+            .filter(|entry| modified(path) == Some(entry.modified))
+            .and_then(|entry| entry.waveform.as_ref())
+    }
+
+    /// Remember the waveform overview of the song at `path` as it is now
+    pub fn set_waveform(&mut self, path: &Path, waveform: Waveform) {
+        if let Some(entry) = self.entry(path) {
+            entry.waveform = Some(waveform);
+        }
+    }
+
+    /// The entry of the song at `path`, emptied when the file changed since it was written
+    fn entry(&mut self, path: &Path) -> Option<&mut IndexEntry> {
+        let modified = modified(path)?;
+        let entry = self
+            .songs
+            .entry(path.to_path_buf())
+            .or_insert_with(|| IndexEntry {
+                modified,
+                loudness: None,
+                waveform: None,
+            });
+        if entry.modified != modified {
+            *entry = IndexEntry {
+                modified,
+                loudness: None,
+                waveform: None,
+            };
+        }
+        Some(entry)
+    }
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| since.as_secs())
+}
@@ -0,0 +1,68 @@
+//! The UI-independent core of the music player: the audio engine, the play queue, the song
+//! library, playlists, settings and saved state. The GPUI window, the terminal UI and the remote
+//! control servers of `music-player-gpui` are all built on it.
+//!
+//! Most programs only need a [`Player`](player::Player), which owns the audio thread, the queue
+//! and the library and can be shared between threads:
+//!
+//! ```no_run
+//! use player_core::player::{Player, PlayerEvent};
+//! use player_core::settings::Settings;
+//!
+//! let settings = Settings::load_or_default();
+//! let player = Player::new(&settings);
+//! let events = player.subscribe();
+//!
+//! player.scan_library(&settings.library.roots);
+//! player.play_library(0);
+//! for event in events {
+//!     if let PlayerEvent::TrackChanged(Some(track)) = event {
+//!         println!("Playing {}", track.display());
+//!     }
+//! }
+//! ```
+//!
+//! The lower layers can be used on their own: [`AudioManager`](audio_manager::AudioManager) plays
+//! single files on a background thread, [`library`] scans folders and reads tags, [`playlist`]
+//! reads M3U files and [`Queue`](queue::Queue) implements the play order with shuffle.
+//!
+//! Without a sound card, set `audio.output` to `null` or `wav` (or pass your own
+//! [`AudioOutput`](output::AudioOutput) to [`AudioManager::with_output`](audio_manager::AudioManager::with_output))
+//! to run the whole playback pipeline, e.g. in CI. Both consume audio `audio.output_speed` times
+//! faster than real time.
+#![warn(missing_docs)]
+
+/// Playback of a single track on a background audio thread
+pub mod audio_manager;
+/// The processing stage between the decoder and the output
+mod dsp;
+/// Graphic and parametric equalizer presets
+pub mod equalizer;
+/// Measurements of the songs kept between runs
+pub mod index;
+/// Scanning folders for songs and reading their tags
+pub mod library;
+/// EBU R128 loudness measurement
+pub mod loudness;
+/// Where the audio goes: a sound card, nowhere or a WAV file
+pub mod output;
+/// The queue-driven player shared by every front-end
+pub mod player;
+/// Reading M3U playlists
+pub mod playlist;
+/// Play order of the queued tracks
+pub mod queue;
+/// Reading ReplayGain tags and turning them into a volume factor
+pub mod replaygain;
+/// User settings stored as TOML
+pub mod settings;
+/// Spectrum and levels of what is playing, for visualizers
+pub mod spectrum;
+/// Saving what was playing between runs
+pub mod state;
+/// Time stretching and pitch shifting for the DSP stage
+mod stretch;
+/// Settings remembered for single tracks
+pub mod track_state;
+/// Overviews of whole tracks for seek bars
+pub mod waveform;
@@ -0,0 +1,252 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, read_dir};
+use std::hash::{Hash, Hasher};
+use std::path::{self, Path, PathBuf};
+
+use id3::{Error, ErrorKind, Tag, TagLike};
+use log::error;
+use serde::{Deserialize, Serialize};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{self, MetadataOptions, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+use crate::playlist;
+
+/// Extensions of the files the decoders play, picked up when scanning folders. Opus is missing
+/// as symphonia has no decoder for it.
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "oga", "wav"];
+/// Album art picked up next to a song when it has no embedded picture
+const COVER_FILE_NAMES: &[&str] = &[
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+/// Parts of the genres of podcasts, audiobooks and other speech, in lowercase
+const SPOKEN_WORD_GENRES: &[&str] = &["podcast", "audiobook", "audio book", "speech", "spoken"];
+
+/// A song of the library with its tags
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SongInfo {
+    /// Location of the audio file
+    pub path: PathBuf,
+    /// Title tag
+    pub title: Option<String>,
+    /// Artist tag
+    pub artist: Option<String>,
+    /// Album tag
+    pub album: Option<String>,
+    /// Genre tag, with ID3v1 genre numbers resolved to their names
+    pub genre: Option<String>,
+}
+
+impl SongInfo {
+    /// Read the tags of the song at `path`
+    pub fn read(path: &Path) -> Self {
+        let tag = match Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            // FLAC and Ogg files keep their tags as Vorbis comments
+            Err(Error {
+                kind: ErrorKind::NoTag,
+                ..
+            }) => return Self::from_container_tags(path),
+            Err(err) => {
+                error!("Unable to read tags of {}: {}", path.display(), err);
+                Tag::new()
+            }
+        };
+
+        Self {
+            path: path.to_path_buf(),
+            title: tag.title().map(String::from),
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            genre: tag.genre_parsed().map(|genre| genre.into_owned()),
+        }
+    }
+
+    fn from_container_tags(path: &Path) -> Self {
+        let mut song = Self {
+            path: path.to_path_buf(),
+            title: None,
+            artist: None,
+            album: None,
+            genre: None,
+        };
+        for tag in container_tags(path) {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut song.title,
+                Some(StandardTagKey::Artist) => &mut song.artist,
+                Some(StandardTagKey::Album) => &mut song.album,
+                Some(StandardTagKey::Genre) => &mut song.genre,
+                _ => continue,
+            };
+            field.get_or_insert_with(|| tag.value.to_string());
+        }
+        song
+    }
+
+    /// Whether the genre marks the song as a podcast, audiobook or other speech
+    pub fn is_spoken_word(&self) -> bool {
+        self.genre.as_ref().is_some_and(|genre| {
+            let genre = genre.to_lowercase();
+            SPOKEN_WORD_GENRES
+                .iter()
+                .any(|spoken| genre.contains(spoken))
+        })
+    }
+
+    /// Title to show, the file path when the song has no title tag
+    pub fn name(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| self.path.to_string_lossy().into_owned())
+    }
+}
+
+/// The tags of the file at `path` as read by symphonia: ID3v2 frames in front of the stream and
+/// the container's own tags (Vorbis comments, RIFF INFO). Empty when the file can't be read.
+pub(crate) fn container_tags(path: &Path) -> Vec<meta::Tag> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let Ok(mut probed) = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) else {
+        return Vec::new();
+    };
+
+    // tags in front of the stream come with the probe, the container's own tags are read along
+    // with its headers
+    let mut tags = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.extend_from_slice(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend_from_slice(revision.tags());
+    }
+    tags
+}
+
+/// Whether `path` is a file with a supported audio extension
+pub fn is_audio_file(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().is_some_and(|ext| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|audio| ext.eq_ignore_ascii_case(audio))
+        })
+}
+
+/// Recursively collect the audio files below `dir`, sorted by path
+fn collect_audio_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Unable to list files at {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut paths = entries
+        .filter_map(|res| res.ok().map(|entry| entry.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            collect_audio_files(&path, files);
+        } else if is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// Scan the library roots (and their sub folders) for songs
+pub fn scan(roots: &[PathBuf]) -> Vec<SongInfo> {
+    let mut files = Vec::new();
+    for root in roots {
+        collect_audio_files(
+            &path::absolute(root).unwrap_or_else(|_| root.clone()),
+            &mut files,
+        );
+    }
+
+    files.iter().map(|path| SongInfo::read(path)).collect()
+}
+
+/// Turn files, folders and playlists given by the user into the list of tracks to play
+pub fn expand(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut tracks = Vec::new();
+
+    for path in paths {
+        // queued tracks outlive the working directory they were given in, e.g. for MPRIS urls
+        let path = &path::absolute(path).unwrap_or_else(|_| path.clone());
+        if path.is_dir() {
+            collect_audio_files(path, &mut tracks);
+        } else if playlist::is_playlist(path) {
+            match playlist::load(path) {
+                Ok(entries) => tracks.extend(entries.into_iter().filter(|p| is_audio_file(p))),
+                Err(e) => error!("Unable to read playlist {}: {}", path.display(), e),
+            }
+        } else if is_audio_file(path) {
+            tracks.push(path.clone());
+        } else {
+            error!("Skipping {}: not a supported audio file", path.display());
+        }
+    }
+
+    tracks
+}
+
+/// Find the album art of the song at `path`: its embedded picture, extracted once to the cache
+/// dir so it can be shared as a file, or else a cover image in the song's folder
+pub fn cover_art(path: &Path) -> Option<PathBuf> {
+    if let Some(art) = embedded_cover_art(path) {
+        return Some(art);
+    }
+    let dir = path.parent()?;
+    COVER_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|cover| cover.is_file())
+}
+
+fn embedded_cover_art(path: &Path) -> Option<PathBuf> {
+    let tag = Tag::read_from_path(path).ok()?;
+    let picture = tag.pictures().next()?;
+    let extension = match picture.mime_type.as_str() {
+        "image/png" => "png",
+        _ => "jpg",
+    };
+
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let art = dirs::cache_dir()?
+        .join("music-player")
+        .join("art")
+        .join(format!("{:016x}.{}", hasher.finish(), extension));
+
+    if !art.is_file() {
+        let written = art
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&art, &picture.data));
+        if let Err(e) = written {
+            error!("Unable to cache the album art of {}: {}", path.display(), e);
+            return None;
+        }
+    }
+    Some(art)
+}
@@ -0,0 +1,472 @@
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use id3::frame::ExtendedText;
+use id3::{ErrorKind, Tag, TagLike, Version};
+
+use crate::dsp::Biquad;
+use crate::replaygain::ReplayGain;
+
+/// Loudness ReplayGain 2.0 brings every track to
+const REPLAYGAIN_REFERENCE_LUFS: f32 = -18.0;
+/// Gating blocks are 400 ms long and start every 100 ms, i.e. span four sub-blocks
+const SUB_BLOCKS_PER_SECOND: u32 = 10;
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+/// Blocks quieter than this are silence and never count
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Blocks this much quieter than the ungated loudness don't count either
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Length of each polyphase filter used to find peaks between the samples
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// EBU R128 loudness of a track, and of its album when it was measured as part of one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS
+    pub integrated_lufs: f32,
+    /// Highest true peak, 1.0 is full scale
+    pub true_peak: f32,
+    /// Integrated loudness of the whole album in LUFS
+    pub album_lufs: Option<f32>,
+    /// Highest true peak of the album
+    pub album_peak: Option<f32>,
+}
+
+impl Loudness {
+    /// The ReplayGain 2.0 values for this loudness
+    pub fn replaygain(&self) -> ReplayGain {
+        ReplayGain {
+            track_gain: Some(REPLAYGAIN_REFERENCE_LUFS - self.integrated_lufs),
+            track_peak: Some(self.true_peak),
+            album_gain: self.album_lufs.map(|lufs| REPLAYGAIN_REFERENCE_LUFS - lufs),
+            album_peak: self.album_peak,
+        }
+    }
+}
+
+/// Why a track could not be measured or tagged
+#[derive(Debug, Clone)]
+pub enum LoudnessError {
+    /// The file could not be opened
+    FileError(String),
+    /// The file is not a supported audio format
+    DecodeError(String),
+    /// Nothing in the track is louder than the absolute gate
+    Silent,
+    /// The analysis was cancelled
+    Cancelled,
+    /// The tags could not be written
+    TagError(String),
+}
+
+impl std::fmt::Display for LoudnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoudnessError::FileError(e) => write!(f, "File error: {}", e),
+            LoudnessError::DecodeError(e) => write!(f, "Decode error: {}", e),
+            LoudnessError::Silent => write!(f, "Track is too quiet to measure"),
+            LoudnessError::Cancelled => write!(f, "Analysis cancelled"),
+            LoudnessError::TagError(e) => write!(f, "Tag error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoudnessError {}
+
+/// The gating block energies and true peak of one track, kept until its whole album is measured
+pub struct Measurement {
+    /// Mean square of the K-weighted samples of every 400 ms block
+    blocks: Vec<f64>,
+    true_peak: f32,
+}
+
+impl Measurement {
+    /// Decode and measure the file at `path`. Checks `cancel` regularly and stops once it is set.
+    pub fn of(path: &Path, cancel: &AtomicBool) -> Result<Self, LoudnessError> {
+        let file = File::open(path).map_err(|e| LoudnessError::FileError(e.to_string()))?;
+        let mut source = Decoder::new(BufReader::new(file))
+            .map_err(|e| LoudnessError::DecodeError(e.to_string()))?;
+        let channels = source.channels().max(1) as usize;
+        let sample_rate = source.sample_rate();
+
+        let weights = (0..channels)
+            .map(|channel| channel_weight(channel, channels))
+            .collect::<Vec<_>>();
+        let mut filters = vec![KWeighting::new(sample_rate); channels];
+        let mut peaks = vec![TruePeak::new(sample_rate); channels];
+        let frames_per_sub_block = (sample_rate / SUB_BLOCKS_PER_SECOND).max(1) as usize;
+
+        let mut sub_blocks = Vec::new();
+        let mut energy = 0.0;
+        let mut frames = 0;
+        let mut frame = vec![0.0; channels];
+        while let Some(first) = source.next() {
+            frame[0] = first;
+            for sample in frame.iter_mut().skip(1) {
+                *sample = source.next().unwrap_or(0.0);
+            }
+
+            for (channel, &sample) in frame.iter().enumerate() {
+                let filtered = filters[channel].process(sample as f64);
+                energy += weights[channel] * filtered * filtered;
+                peaks[channel].process(sample);
+            }
+            frames += 1;
+            if frames == frames_per_sub_block {
+                sub_blocks.push(energy / frames as f64);
+                energy = 0.0;
+                frames = 0;
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(LoudnessError::Cancelled);
+                }
+            }
+        }
+
+        let blocks = sub_blocks
+            .windows(SUB_BLOCKS_PER_BLOCK)
+            .map(|window| window.iter().sum::<f64>() / SUB_BLOCKS_PER_BLOCK as f64)
+            .collect();
+        let true_peak = peaks.iter().map(|peak| peak.max).fold(0.0, f32::max);
+        Ok(Self { blocks, true_peak })
+    }
+
+    /// Integrated loudness in LUFS, `None` when the track is silent
+    pub fn integrated_lufs(&self) -> Option<f32> {
+        gated_loudness(&self.blocks)
+    }
+
+    /// Highest true peak, 1.0 is full scale
+    pub fn true_peak(&self) -> f32 {
+        self.true_peak
+    }
+}
+
+/// Integrated loudness in LUFS of the tracks of an album played one after the other, `None` when
+/// they are all silent
+pub fn album_loudness<'a>(measurements: impl IntoIterator<Item = &'a Measurement>) -> Option<f32> {
+    let blocks = measurements
+        .into_iter()
+        .flat_map(|measurement| measurement.blocks.iter().copied())
+        .collect::<Vec<_>>();
+    gated_loudness(&blocks)
+}
+
+/// Store the ReplayGain of `loudness` as ID3v2 `TXXX:REPLAYGAIN_*` frames of the MP3 file at
+/// `path`, replacing older values
+pub fn write_tags(path: &Path, loudness: &Loudness) -> Result<(), LoudnessError> {
+    if !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+    {
+        return Err(LoudnessError::TagError(String::from(
+            "only MP3 files can be tagged",
+        )));
+    }
+    let mut tag = match Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => Tag::new(),
+        Err(e) => return Err(LoudnessError::TagError(e.to_string())),
+    };
+
+    let gain = loudness.replaygain();
+    let values = [
+        ("REPLAYGAIN_TRACK_GAIN", gain.track_gain.map(format_db)),
+        ("REPLAYGAIN_TRACK_PEAK", gain.track_peak.map(format_peak)),
+        ("REPLAYGAIN_ALBUM_GAIN", gain.album_gain.map(format_db)),
+        ("REPLAYGAIN_ALBUM_PEAK", gain.album_peak.map(format_peak)),
+    ];
+    for (description, value) in values {
+        match value {
+            Some(value) => {
+                tag.add_frame(ExtendedText {
+                    description: String::from(description),
+                    value,
+                });
+            }
+            None => tag.remove_extended_text(Some(description), None),
+        }
+    }
+
+    tag.write_to_path(path, Version::Id3v24)
+        .map_err(|e| LoudnessError::TagError(e.to_string()))
+}
+
+fn format_db(gain: f32) -> String {
+    format!("{:+.2} dB", gain)
+}
+
+fn format_peak(peak: f32) -> String {
+    format!("{:.6}", peak)
+}
+
+/// BS.1770 channel weights: the surround channels of 5.1 count more, the LFE not at all
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Loudness of the blocks that pass the absolute and the relative gate
+fn gated_loudness(blocks: &[f64]) -> Option<f32> {
+    let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = blocks.fold((0.0, 0), |(sum, count), z| (sum + z, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    };
+
+    let absolute = lufs_to_energy(ABSOLUTE_GATE_LUFS);
+    let ungated = mean(&mut blocks.iter().copied().filter(|&z| z > absolute))?;
+    let relative = ungated * 10f64.powf(RELATIVE_GATE_LU / 10.0);
+    let gated = mean(
+        &mut blocks
+            .iter()
+            .copied()
+            .filter(|&z| z > absolute && z > relative),
+    )?;
+    Some(energy_to_lufs(gated) as f32)
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// The two BS.1770 pre-filters of one channel: a high shelf modelling the head, then a high pass
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    /// Coefficients for `sample_rate`, derived from the analog prototypes of BS.1770 so that
+    /// rates other than 48 kHz are weighted the same way
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Highest peak of one channel including the ones between samples, found by oversampling to at
+/// least 192 kHz with a windowed sinc interpolator
+#[derive(Clone)]
+struct TruePeak {
+    /// One filter per interpolated position between two samples
+    phases: Vec<[f32; TRUE_PEAK_TAPS]>,
+    /// The last samples, newest first
+    history: [f32; TRUE_PEAK_TAPS],
+    max: f32,
+}
+
+impl TruePeak {
+    fn new(sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            0..96_000 => 4,
+            96_000..192_000 => 2,
+            _ => 1,
+        };
+        let len = TRUE_PEAK_TAPS * factor;
+        let centre = (len - 1) as f64 / 2.0;
+        let coefficient = |n: usize| {
+            let t = (n as f64 - centre) / factor as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / len as f64).cos();
+            sinc * window
+        };
+
+        let phases = (0..factor)
+            .map(|phase| {
+                let taps = (0..TRUE_PEAK_TAPS)
+                    .map(|tap| coefficient(tap * factor + phase))
+                    .collect::<Vec<_>>();
+                // every phase passes DC unchanged
+                let sum = taps.iter().sum::<f64>();
+                let mut normalized = [0.0; TRUE_PEAK_TAPS];
+                normalized
+                    .iter_mut()
+                    .zip(taps)
+                    .for_each(|(c, tap)| *c = (tap / sum) as f32);
+                normalized
+            })
+            .collect();
+
+        Self {
+            phases,
+            history: [0.0; TRUE_PEAK_TAPS],
+            max: 0.0,
+        }
+    }
+
+    fn process(&mut self, sample: f32) {
+        self.history.rotate_right(1);
+        self.history[0] = sample;
+        self.max = self.max.max(sample.abs());
+        for phase in &self.phases {
+            let interpolated = phase
+                .iter()
+                .zip(&self.history)
+                .map(|(c, x)| c * x)
+                .sum::<f32>();
+            self.max = self.max.max(interpolated.abs());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+    use std::fs;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Measure a stereo WAV file with the same `frame(i)` sample on both channels
+    fn measure(name: &str, frames: u32, frame: impl Fn(u32) -> f32) -> Measurement {
+        let path = std::env::temp_dir().join(format!(
+            "player-core-loudness-{}-{}.wav",
+            name,
+            std::process::id()
+        ));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..frames {
+            writer.write_sample(frame(i)).unwrap();
+            writer.write_sample(frame(i)).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let measurement = Measurement::of(&path, &AtomicBool::new(false));
+        let _ = fs::remove_file(&path);
+        measurement.unwrap()
+    }
+
+    /// A sine of `frequency` Hz peaking at `dbfs`
+    fn sine(frequency: f32, dbfs: f32, phase: f32) -> impl Fn(u32) -> f32 {
+        let amplitude = 10f32.powf(dbfs / 20.0);
+        move |i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            amplitude * (2.0 * std::f32::consts::PI * frequency * t + phase).sin()
+        }
+    }
+
+    #[test]
+    fn stereo_sine_at_minus_23_dbfs_is_minus_23_lufs() {
+        // EBU Tech 3341, test case 1
+        let measurement = measure("sine", 5 * SAMPLE_RATE, sine(1000.0, -23.0, 0.0));
+        let lufs = measurement.integrated_lufs().unwrap();
+        assert!((lufs + 23.0).abs() < 0.1, "{} LUFS", lufs);
+
+        let loudness = Loudness {
+            integrated_lufs: lufs,
+            true_peak: measurement.true_peak(),
+            album_lufs: None,
+            album_peak: None,
+        };
+        let gain = loudness.replaygain().track_gain.unwrap();
+        assert!((gain - 5.0).abs() < 0.1, "{} dB", gain);
+    }
+
+    #[test]
+    fn quiet_passages_are_gated() {
+        // 3 s at -20 dBFS then 3 s 30 dB quieter, below the relative gate
+        let loud = sine(1000.0, -20.0, 0.0);
+        let quiet = sine(1000.0, -50.0, 0.0);
+        let measurement = measure("gated", 6 * SAMPLE_RATE, |i| {
+            if i < 3 * SAMPLE_RATE {
+                loud(i)
+            } else {
+                quiet(i)
+            }
+        });
+        // 27 blocks are loud and the 3 spanning the change 3/4, 1/2 and 1/4 loud, the quiet ones
+        // don't count
+        let expected = -20.0 + 10.0 * (28.5f32 / 30.0).log10();
+        let lufs = measurement.integrated_lufs().unwrap();
+        assert!((lufs - expected).abs() < 0.05, "{} LUFS", lufs);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let measurement = measure("silence", 2 * SAMPLE_RATE, |_| 0.0);
+        assert_eq!(measurement.integrated_lufs(), None);
+        assert_eq!(album_loudness([&measurement]), None);
+    }
+
+    #[test]
+    fn true_peak_finds_peaks_between_samples() {
+        // at a quarter of the sample rate and 45° the samples miss the peaks by 3 dB
+        let measurement = measure(
+            "true-peak",
+            SAMPLE_RATE,
+            sine(SAMPLE_RATE as f32 / 4.0, -6.0, std::f32::consts::FRAC_PI_4),
+        );
+        let peak = 10f32.powf(-6.0 / 20.0);
+        let true_peak = measurement.true_peak();
+        assert!(
+            true_peak > peak * 0.95 && true_peak < peak * 1.05,
+            "{} instead of {}",
+            true_peak,
+            peak
+        );
+    }
+
+    #[test]
+    fn album_loudness_weighs_tracks_by_length() {
+        let loud = measure("album-loud", 2 * SAMPLE_RATE, sine(1000.0, -20.0, 0.0));
+        let soft = measure("album-soft", 6 * SAMPLE_RATE, sine(1000.0, -26.0, 0.0));
+        let album = album_loudness([&loud, &soft]).unwrap();
+        // the blocks of both tracks averaged, 17 of 400 ms in 2 s and 57 in 6 s
+        let expected = 10.0 * ((17.0 * 0.01 + 57.0 * 10f32.powf(-2.6)) / 74.0).log10();
+        assert!(
+            (album - expected).abs() < 0.05,
+            "{} instead of {}",
+            album,
+            expected
+        );
+    }
+}
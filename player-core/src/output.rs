@@ -0,0 +1,249 @@
+use rodio::cpal::traits::HostTrait;
+use rodio::mixer::{self, Mixer, MixerSource};
+use rodio::source::Zero;
+use rodio::{DeviceTrait, OutputStream, OutputStreamBuilder, cpal};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::{error, info};
+
+use crate::audio_manager::AudioPlayerError;
+use crate::settings::{AudioSettings, OutputKind};
+
+/// Format of the mix produced by the null and WAV outputs
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 44_100;
+/// How much audio the null and WAV outputs consume at once
+const CHUNK_DURATION: Duration = Duration::from_millis(10);
+
+/// Where the audio thread sends its samples. The playback sink is connected to `mixer`, the
+/// output pulls the mixed samples from it for as long as it is alive.
+pub trait AudioOutput {
+    /// Mixer feeding this output
+    fn mixer(&self) -> &Mixer;
+
+    /// Why the output stopped working, e.g. because the device was unplugged. The audio thread
+    /// checks it regularly and moves playback to another output once it is set.
+    fn failure(&self) -> Option<String> {
+        None
+    }
+}
+
+impl AudioOutput for OutputStream {
+    fn mixer(&self) -> &Mixer {
+        OutputStream::mixer(self)
+    }
+}
+
+/// Open the output selected by `settings`
+pub fn open(settings: &AudioSettings) -> Result<Box<dyn AudioOutput>, AudioPlayerError> {
+    match settings.output {
+        OutputKind::Device => Ok(Box::new(DeviceOutput::open(
+            settings.output_device.as_deref(),
+        )?)),
+        OutputKind::Null => Ok(Box::new(NullOutput::new(settings.output_speed))),
+        OutputKind::Wav => {
+            let path = settings.wav_file.as_deref().ok_or_else(|| {
+                AudioPlayerError::OutputStreamError(String::from("audio.wav_file is not set"))
+            })?;
+            Ok(Box::new(WavOutput::create(path, settings.output_speed)?))
+        }
+    }
+}
+
+/// Names of all output devices of the default host
+pub fn output_device_names() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// A sound card, remembering when its stream reported that the device is gone
+struct DeviceOutput {
+    stream: OutputStream,
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl DeviceOutput {
+    /// Open the output device named `device_name`, or the system default when it is `None`
+    fn open(device_name: Option<&str>) -> Result<Self, AudioPlayerError> {
+        let builder = match device_name {
+            Some(name) => {
+                let device = cpal::default_host()
+                    .output_devices()
+                    .ok()
+                    .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)))
+                    .ok_or_else(|| {
+                        AudioPlayerError::OutputStreamError(format!(
+                            "output device {:?} not found",
+                            name
+                        ))
+                    })?;
+                OutputStreamBuilder::from_device(device)
+            }
+            None => OutputStreamBuilder::from_default_device(),
+        }
+        .map_err(|e| AudioPlayerError::OutputStreamError(e.to_string()))?;
+
+        let failure = Arc::new(Mutex::new(None));
+        let stream_failure = Arc::clone(&failure);
+        let stream = builder
+            .with_error_callback(move |e| match e {
+                cpal::StreamError::DeviceNotAvailable => {
+                    *stream_failure.lock().unwrap() = Some(e.to_string())
+                }
+                cpal::StreamError::BackendSpecific { .. } => error!("Output stream error: {}", e),
+            })
+            .open_stream()
+            .map_err(|e| AudioPlayerError::OutputStreamError(e.to_string()))?;
+        info!(
+            "Opened output device {}",
+            device_name.unwrap_or("(default)")
+        );
+
+        Ok(Self { stream, failure })
+    }
+}
+
+impl AudioOutput for DeviceOutput {
+    fn mixer(&self) -> &Mixer {
+        self.stream.mixer()
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+}
+
+/// Discards every sample, consuming them `speed` times faster than real time
+pub struct NullOutput {
+    mixer: Mixer,
+    _pump: Pump,
+}
+
+impl NullOutput {
+    /// Start consuming samples on a background thread
+    pub fn new(speed: f32) -> Self {
+        let (mixer, source) = silent_mixer();
+        Self {
+            mixer,
+            _pump: Pump::start(source, speed, |_| {}),
+        }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+}
+
+/// Records everything played, silence included, as a 32-bit float WAV file. Samples are
+/// consumed `speed` times faster than real time.
+pub struct WavOutput {
+    mixer: Mixer,
+    _pump: Pump,
+}
+
+impl WavOutput {
+    /// Create (or truncate) the file at `path` and start recording into it
+    pub fn create(path: &Path, speed: f32) -> Result<Self, AudioPlayerError> {
+        let spec = WavSpec {
+            channels: CHANNELS,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(path, spec).map_err(|e| {
+            AudioPlayerError::OutputStreamError(format!("{}: {}", path.display(), e))
+        })?;
+        info!("Writing audio output to {}", path.display());
+
+        let (mixer, source) = silent_mixer();
+        let pump = Pump::start(source, speed, move |samples| {
+            write_samples(&mut writer, samples)
+        });
+        Ok(Self { mixer, _pump: pump })
+    }
+}
+
+impl AudioOutput for WavOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+}
+
+/// Append `samples` and update the header, so the file is valid even if the player is killed
+fn write_samples(writer: &mut WavWriter<BufWriter<File>>, samples: &[f32]) {
+    let result = samples
+        .iter()
+        .try_for_each(|&sample| writer.write_sample(sample))
+        .and_then(|_| writer.flush());
+    if let Err(e) = result {
+        error!("Unable to write audio output: {}", e);
+    }
+}
+
+/// A mixer that keeps producing silence while nothing is playing
+fn silent_mixer() -> (Mixer, MixerSource) {
+    let (mixer, source) = mixer::mixer(CHANNELS, SAMPLE_RATE);
+    mixer.add(Zero::new(CHANNELS, SAMPLE_RATE));
+    (mixer, source)
+}
+
+/// Background thread pulling samples from a mixer in chunks, like a sound card would
+struct Pump {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Pump {
+    fn start(
+        mut source: MixerSource,
+        speed: f32,
+        mut consume: impl FnMut(&[f32]) + Send + 'static,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let chunk_len =
+            (SAMPLE_RATE as f32 * CHANNELS as f32 * CHUNK_DURATION.as_secs_f32()) as usize;
+        let chunk_interval = CHUNK_DURATION.div_f32(speed);
+
+        let thread = thread::spawn(move || {
+            let mut chunk = vec![0.0; chunk_len];
+            let start = Instant::now();
+            let mut chunks: u32 = 0;
+            while !thread_stop.load(Ordering::Relaxed) {
+                chunk
+                    .iter_mut()
+                    .for_each(|sample| *sample = source.next().unwrap_or(0.0));
+                consume(&chunk);
+                chunks += 1;
+                if let Some(ahead) = (chunk_interval * chunks).checked_sub(start.elapsed()) {
+                    thread::sleep(ahead);
+                }
+            }
+        });
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Pump {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
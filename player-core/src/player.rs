@@ -0,0 +1,1486 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use crate::audio_manager::{AudioEvent, AudioManager};
+use crate::equalizer::EqCurve;
+use crate::index::LibraryIndex;
+use crate::library::{self, SongInfo};
+use crate::loudness::{self, Loudness, LoudnessError, Measurement};
+use crate::queue::Queue;
+use crate::replaygain::{ReplayGain, ReplayGainOptions};
+use crate::settings::{AudioSettings, BALANCE_RANGE, ChannelSettings, Settings, SpeedMemory};
+use crate::spectrum::Spectrum;
+use crate::state::SavedState;
+use crate::track_state::{LoopRegion, TrackState, TrackStates};
+use crate::waveform::Waveform;
+
+/// How often the volume is lowered while fading out
+const FADE_STEP: Duration = Duration::from_millis(20);
+/// How often the volume is lowered while the sleep timer fades out
+const SLEEP_FADE_STEP: Duration = Duration::from_millis(100);
+/// How often the sleep timer checks the time left before it starts fading
+const SLEEP_POLL: Duration = Duration::from_millis(250);
+/// Sleep timer lengths `cycle_sleep_timer` steps through, in minutes
+const SLEEP_STEPS_MINUTES: [u64; 4] = [15, 30, 45, 60];
+/// Waveform overviews computed in the background between two saves of the library index
+const WAVEFORM_SAVE_EVERY: usize = 50;
+/// Lowest and highest playback speed
+pub const SPEED_RANGE: (f32, f32) = (0.5, 3.0);
+/// Largest pitch shift down and up, in semitones
+pub const PITCH_RANGE_SEMITONES: (f32, f32) = (-12.0, 12.0);
+/// Shortest A–B loop
+pub const MIN_LOOP_LENGTH: Duration = Duration::from_millis(100);
+
+/// Whether a track is playing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackState {
+    /// Nothing is loaded
+    #[default]
+    Stopped,
+    /// The current track is audible
+    Playing,
+    /// The current track is loaded but halted
+    Paused,
+}
+
+/// What happens at the end of a track, named after the MPRIS `LoopStatus`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoopMode {
+    /// Stop at the end of the queue
+    #[default]
+    None,
+    /// Repeat the current track
+    Track,
+    /// Start the queue over at its end
+    Playlist,
+}
+
+/// Tempo and pitch of playback
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlaybackRate {
+    /// 1.0 is the original speed, the pitch stays the same
+    pub speed: f32,
+    /// Pitch shift in semitones, independent of the speed
+    pub pitch_semitones: f32,
+}
+
+impl Default for PlaybackRate {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            pitch_semitones: 0.0,
+        }
+    }
+}
+
+/// Section of the current track played over and over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbLoop {
+    /// Point A in milliseconds
+    pub start_ms: u64,
+    /// Point B in milliseconds, the loop only plays once it is set
+    pub end_ms: Option<u64>,
+}
+
+impl AbLoop {
+    /// Start and end, once both are set
+    pub fn section(&self) -> Option<(Duration, Duration)> {
+        self.end_ms.map(|end_ms| {
+            (
+                Duration::from_millis(self.start_ms),
+                Duration::from_millis(end_ms),
+            )
+        })
+    }
+}
+
+/// What the sleep timer waits for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepMode {
+    /// A set time, then playback pauses
+    Timer,
+    /// The end of the current track, then playback stops
+    EndOfTrack,
+    /// The end of the last track of the queue, then playback stops
+    EndOfQueue,
+}
+
+/// The running sleep timer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SleepTimer {
+    /// What it waits for
+    pub mode: SleepMode,
+    /// Time until playback ends in milliseconds, unknown until the track it waits for plays
+    pub remaining_ms: Option<u64>,
+}
+
+/// A sleep timer and the flag stopping its thread
+struct Sleep {
+    mode: SleepMode,
+    /// When a `SleepMode::Timer` goes off
+    deadline: Option<Instant>,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Tracks whose waveform overview the background thread computes
+enum WaveformJob {
+    /// The track that just started, before anything else
+    Now(PathBuf),
+    /// The songs of the library, one after the other
+    Later(Vec<PathBuf>),
+}
+
+/// Everything a front-end needs to show what is playing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    /// Playing, paused or stopped
+    pub state: PlaybackState,
+    /// The current track of the queue
+    pub track: Option<PathBuf>,
+    /// Playback position in milliseconds, 0 when stopped
+    pub position_ms: u64,
+    /// Length of the current track in milliseconds, when known
+    pub duration_ms: Option<u64>,
+    /// 0.0 - 1.0
+    pub volume: f32,
+    /// Whether the queue plays in random order
+    pub shuffle: bool,
+    /// What happens at the end of a track
+    pub loop_mode: LoopMode,
+    /// Index of the current track in the queue
+    pub queue_position: Option<usize>,
+    /// Number of tracks in the queue
+    pub queue_length: usize,
+    /// 1.0 is the original speed
+    pub speed: f32,
+    /// Pitch shift in semitones
+    pub pitch_semitones: f32,
+    /// The A–B loop of the current track
+    pub ab_loop: Option<AbLoop>,
+    /// When playback ends by itself
+    pub sleep_timer: Option<SleepTimer>,
+    /// Balance, mono downmix and channel swap
+    pub channels: ChannelSettings,
+}
+
+/// Changes broadcast to every subscriber, serialized as e.g.
+/// `{"event":"state_changed","data":"playing"}`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum PlayerEvent {
+    /// Playback started, paused or stopped
+    StateChanged(PlaybackState),
+    /// Another track was loaded, `None` when the queue ran out
+    TrackChanged(Option<PathBuf>),
+    /// 0.0 - 1.0
+    VolumeChanged(f32),
+    /// The position jumped, in milliseconds
+    Seeked(u64),
+    /// Tracks were added or removed, or the order was shuffled
+    QueueChanged,
+    /// The loop mode was changed
+    LoopChanged(LoopMode),
+    /// The speed or pitch changed, by a call or by a track with its own
+    RateChanged(PlaybackRate),
+    /// A point of the A–B loop was set or the loop was cleared
+    AbLoopChanged(Option<AbLoop>),
+    /// A loop of the current track was saved or deleted
+    LoopsChanged,
+    /// The sleep timer was set, cancelled or ran out
+    SleepTimerChanged(Option<SleepTimer>),
+    /// The balance, mono downmix or channel swap changed
+    ChannelsChanged(ChannelSettings),
+    /// The library was scanned again
+    LibraryChanged,
+    /// The selected output failed or could not be opened, the message says why and what plays
+    /// instead
+    OutputFallback(String),
+    /// Another track of a loudness analysis was measured
+    AnalysisProgress {
+        /// Tracks measured so far
+        done: usize,
+        /// Tracks to measure
+        total: usize,
+    },
+    /// The loudness analysis finished or was cancelled, with the number of tracks it stored
+    AnalysisFinished(usize),
+}
+
+/// The player core shared by all front-ends: the audio thread plus the queue of tracks.
+/// Every method is non-blocking and can be called from any thread.
+pub struct Player {
+    audio_manager: AudioManager,
+    queue: Mutex<Queue>,
+    state: Mutex<PlaybackState>,
+    loop_mode: Mutex<LoopMode>,
+    /// Kept here so it is up to date before the audio thread applied it
+    volume: Mutex<f32>,
+    /// The rate of the current track
+    rate: Mutex<PlaybackRate>,
+    /// The rate of tracks that don't remember their own
+    default_rate: Mutex<PlaybackRate>,
+    speed_memory: Mutex<SpeedMemory>,
+    track_states: Mutex<TrackStates>,
+    ab_loop: Mutex<Option<AbLoop>>,
+    sleep: Mutex<Option<Sleep>>,
+    /// How long playback fades out before the sleep timer ends it
+    sleep_fade: Mutex<Duration>,
+    channels: Mutex<ChannelSettings>,
+    library_roots: Mutex<Vec<PathBuf>>,
+    library: Mutex<Vec<SongInfo>>,
+    index: Mutex<LibraryIndex>,
+    /// Cancels the running loudness analysis
+    analysis: Mutex<Option<Arc<AtomicBool>>>,
+    waveform_tx: Sender<WaveformJob>,
+    subscribers: Mutex<Vec<Sender<PlayerEvent>>>,
+    /// Handed to background jobs
+    this: Weak<Player>,
+}
+
+impl Player {
+    /// Creates the player and its audio thread. Finished tracks advance the queue automatically.
+    pub fn new(settings: &Settings) -> Arc<Self> {
+        let (event_tx, event_rx) = mpsc::channel::<AudioEvent>();
+        let (waveform_tx, waveform_rx) = mpsc::channel::<WaveformJob>();
+
+        let player = Arc::new_cyclic(|player: &Weak<Player>| {
+            let this = player.clone();
+            let waveform_player = player.clone();
+            thread::spawn(move || compute_waveforms(waveform_player, waveform_rx));
+
+            Self {
+                audio_manager: AudioManager::new(settings, event_tx),
+                queue: Mutex::new(Queue::default()),
+                state: Mutex::new(PlaybackState::Stopped),
+                loop_mode: Mutex::new(LoopMode::None),
+                volume: Mutex::new(1.0),
+                rate: Mutex::new(PlaybackRate::default()),
+                default_rate: Mutex::new(PlaybackRate::default()),
+                speed_memory: Mutex::new(settings.playback.remember_speed),
+                track_states: Mutex::new(TrackStates::load()),
+                ab_loop: Mutex::new(None),
+                sleep: Mutex::new(None),
+                sleep_fade: Mutex::new(settings.sleep_fade()),
+                channels: Mutex::new(settings.channels),
+                library_roots: Mutex::new(settings.library.roots.clone()),
+                library: Mutex::new(Vec::new()),
+                index: Mutex::new(LibraryIndex::load()),
+                analysis: Mutex::new(None),
+                waveform_tx,
+                subscribers: Mutex::new(Vec::new()),
+                this,
+            }
+        });
+
+        // Started only now, the audio thread may report a fallback output while the player is
+        // still being built and the event must not find it missing
+        let weak = Arc::downgrade(&player);
+        thread::spawn(move || {
+            for event in event_rx {
+                let Some(player) = weak.upgrade() else {
+                    break;
+                };
+                match event {
+                    AudioEvent::TrackFinished => player.track_finished(),
+                    AudioEvent::TrackEnding => player.track_ending(),
+                    AudioEvent::Seeked(position) => {
+                        player.emit(PlayerEvent::Seeked(position.as_millis() as u64))
+                    }
+                    AudioEvent::OutputFallback(message) => {
+                        player.emit(PlayerEvent::OutputFallback(message))
+                    }
+                }
+            }
+        });
+        player
+    }
+
+    /// Apply the settings that changed from `old` to `new`, rescanning the library when its roots
+    /// changed
+    pub fn apply_settings(&self, old: &Settings, new: &Settings) {
+        if new.playback.seek_step_secs != old.playback.seek_step_secs {
+            self.set_seek_step(new.seek_step());
+        }
+        if new.replaygain() != old.replaygain() {
+            self.set_replaygain(new.replaygain());
+        }
+        if new.equalizer != old.equalizer {
+            self.set_equalizer(new.equalizer.curve());
+        }
+        if new.channels != old.channels {
+            self.set_channels(new.channels);
+        }
+        if new.playback.remember_speed != old.playback.remember_speed {
+            self.set_speed_memory(new.playback.remember_speed);
+        }
+        if new.playback.ramp_ms != old.playback.ramp_ms {
+            self.set_ramp(new.ramp());
+        }
+        if new.playback.crossfade_secs != old.playback.crossfade_secs {
+            self.set_crossfade(new.crossfade());
+        }
+        if new.playback.sleep_fade_secs != old.playback.sleep_fade_secs {
+            self.set_sleep_fade(new.sleep_fade());
+        }
+        if new.audio != old.audio {
+            self.set_output(new.audio.clone());
+        }
+        if new.library.roots != old.library.roots {
+            self.scan_library(&new.library.roots);
+        }
+    }
+
+    /// Receive every `PlayerEvent` from now on
+    pub fn subscribe(&self) -> Receiver<PlayerEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    fn emit(&self, event: PlayerEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn set_state(&self, state: PlaybackState) {
+        let mut current = self.state.lock().unwrap();
+        if *current != state {
+            *current = state;
+            drop(current);
+            self.emit(PlayerEvent::StateChanged(state));
+        }
+    }
+
+    /// Load and start `track`, or stop when there is none
+    fn start(&self, track: Option<PathBuf>) {
+        self.forget_ab_loop();
+        match &track {
+            Some(path) => {
+                self.apply_rate(self.rate_for(path));
+                self.audio_manager.load(
+                    path.to_string_lossy().into_owned(),
+                    self.playing_album_in_order(),
+                    self.measured_gain(path),
+                );
+                self.audio_manager.play();
+                self.request_waveform(path);
+                info!("Playing {}", path.display());
+            }
+            None => self.audio_manager.stop(),
+        }
+        self.emit(PlayerEvent::TrackChanged(track.clone()));
+        self.set_state(if track.is_some() {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Stopped
+        });
+    }
+
+    /// Whether the current track plays as part of its album: the queue is not shuffled and the
+    /// track before or after it is from the same album
+    fn playing_album_in_order(&self) -> bool {
+        let (current, neighbours) = {
+            let queue = self.queue.lock().unwrap();
+            let Some(index) = queue.current_index().filter(|_| !queue.is_shuffled()) else {
+                return false;
+            };
+            let tracks = queue.tracks();
+            let neighbours = index
+                .checked_sub(1)
+                .into_iter()
+                .chain(Some(index + 1))
+                .filter_map(|i| tracks.get(i).cloned())
+                .collect::<Vec<_>>();
+            (tracks[index].clone(), neighbours)
+        };
+
+        let Some(album) = self.album_of(&current) else {
+            return false;
+        };
+        neighbours
+            .iter()
+            .any(|track| self.album_of(track).as_ref() == Some(&album))
+    }
+
+    /// Album tag of `track`
+    fn album_of(&self, track: &Path) -> Option<String> {
+        self.song(track).album
+    }
+
+    /// Tags of `track`, from the library when it is part of it
+    fn song(&self, track: &Path) -> SongInfo {
+        let song = self
+            .library
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|song| song.path == track)
+            .cloned();
+        song.unwrap_or_else(|| SongInfo::read(track))
+    }
+
+    /// Whether `track` keeps its own speed and pitch
+    fn remembers_rate(&self, track: &Path) -> bool {
+        match *self.speed_memory.lock().unwrap() {
+            SpeedMemory::Off => false,
+            SpeedMemory::SpokenWord => self.song(track).is_spoken_word(),
+            SpeedMemory::All => true,
+        }
+    }
+
+    /// The rate `track` plays at: its own when it has one, otherwise the default rate
+    fn rate_for(&self, track: &Path) -> PlaybackRate {
+        let default = *self.default_rate.lock().unwrap();
+        if !self.remembers_rate(track) {
+            return default;
+        }
+        let state = self.track_states.lock().unwrap().get(track);
+        PlaybackRate {
+            speed: state.speed.unwrap_or(default.speed),
+            pitch_semitones: state.pitch_semitones.unwrap_or(default.pitch_semitones),
+        }
+    }
+
+    /// Hand `rate` to the audio thread when it differs from the current one
+    fn apply_rate(&self, rate: PlaybackRate) {
+        let previous = std::mem::replace(&mut *self.rate.lock().unwrap(), rate);
+        if previous.speed != rate.speed {
+            self.audio_manager.set_speed(rate.speed);
+        }
+        if previous.pitch_semitones != rate.pitch_semitones {
+            self.audio_manager.set_pitch(rate.pitch_semitones);
+        }
+        if previous != rate {
+            self.emit(PlayerEvent::RateChanged(rate));
+        }
+    }
+
+    /// Change the rate with `change`, for the current track only when it keeps its own
+    fn change_rate(&self, change: impl FnOnce(&mut PlaybackRate)) {
+        let mut rate = self.rate();
+        change(&mut rate);
+
+        let current = self.queue.lock().unwrap().current().cloned();
+        match current.filter(|track| self.remembers_rate(track)) {
+            Some(track) => {
+                let default = *self.default_rate.lock().unwrap();
+                self.edit_track_state(&track, |state| {
+                    state.speed = Some(rate.speed).filter(|&speed| speed != default.speed);
+                    state.pitch_semitones = Some(rate.pitch_semitones)
+                        .filter(|&pitch| pitch != default.pitch_semitones);
+                });
+            }
+            None => *self.default_rate.lock().unwrap() = rate,
+        }
+        self.apply_rate(rate);
+    }
+
+    /// Change what is remembered about `track` and save it
+    fn edit_track_state(&self, track: &Path, edit: impl FnOnce(&mut TrackState)) {
+        let mut states = self.track_states.lock().unwrap();
+        let mut state = states.get(track);
+        edit(&mut state);
+        states.set(track, state);
+        if let Err(e) = states.save() {
+            error!("Unable to save the track states: {}", e);
+        }
+    }
+
+    /// Replace the A–B loop, handing it to the audio thread once complete
+    fn change_ab_loop(&self, ab_loop: Option<AbLoop>) {
+        let previous = std::mem::replace(&mut *self.ab_loop.lock().unwrap(), ab_loop);
+        if previous == ab_loop {
+            return;
+        }
+        let section = ab_loop.and_then(|ab_loop| ab_loop.section());
+        if previous.and_then(|previous| previous.section()) != section {
+            self.audio_manager.set_section(section);
+        }
+        self.emit(PlayerEvent::AbLoopChanged(ab_loop));
+    }
+
+    /// Drop the A–B loop of a track that is no longer played, the audio thread clears its own
+    /// when loading the next one
+    fn forget_ab_loop(&self) {
+        if self.ab_loop.lock().unwrap().take().is_some() {
+            self.emit(PlayerEvent::AbLoopChanged(None));
+        }
+    }
+
+    /// ReplayGain of `track` from the library index, used when the file has no gain tags
+    fn measured_gain(&self, track: &Path) -> ReplayGain {
+        self.loudness(track)
+            .map(|loudness| loudness.replaygain())
+            .unwrap_or_default()
+    }
+
+    fn track_finished(&self) {
+        let sleep = self.sleep.lock().unwrap().as_ref().map(|sleep| sleep.mode);
+        let asleep = match sleep {
+            Some(SleepMode::EndOfTrack) => true,
+            Some(SleepMode::EndOfQueue) => self.queue.lock().unwrap().is_last(),
+            _ => false,
+        };
+        if asleep {
+            // the next track is current afterwards, so `play` continues with it
+            let next = self.advance();
+            self.audio_manager.stop();
+            self.stop_sleep_timer();
+            info!("Sleep timer stopped playback");
+            self.emit(PlayerEvent::TrackChanged(next));
+            self.set_state(PlaybackState::Stopped);
+            self.emit(PlayerEvent::SleepTimerChanged(None));
+            return;
+        }
+
+        let next = match self.loop_mode() {
+            LoopMode::Track => self.queue.lock().unwrap().current().cloned(),
+            _ => self.advance(),
+        };
+        if next.is_none() {
+            info!("Reached the end of the queue");
+        }
+        self.start(next);
+    }
+
+    /// The current track is about to end: start the next one so they crossfade, unless
+    /// playback stops or the track repeats afterwards. Those wait for the end of the track.
+    fn track_ending(&self) {
+        let sleep = self.sleep.lock().unwrap().as_ref().map(|sleep| sleep.mode);
+        let last = self.queue.lock().unwrap().is_last();
+        let stops = match sleep {
+            Some(SleepMode::EndOfTrack) => true,
+            Some(SleepMode::EndOfQueue) => last,
+            _ => false,
+        };
+        let crossfades = match self.loop_mode() {
+            LoopMode::None => !last,
+            LoopMode::Track => false,
+            LoopMode::Playlist => true,
+        };
+        if crossfades && !stops {
+            self.track_finished();
+        }
+    }
+
+    /// Playing time left of the current track at the current speed, `None` while stopped or
+    /// while an A–B loop keeps the track from ending
+    fn track_remaining(&self) -> Option<Duration> {
+        if self.state() == PlaybackState::Stopped
+            || self
+                .ab_loop()
+                .and_then(|ab_loop| ab_loop.section())
+                .is_some()
+        {
+            return None;
+        }
+        let audio = self.audio_manager.status();
+        let remaining = audio.duration?.saturating_sub(audio.position());
+        Some(remaining.div_f32(self.rate().speed))
+    }
+
+    /// Replace the sleep timer and start the thread fading out when it runs out
+    fn start_sleep_timer(&self, mode: SleepMode, deadline: Option<Instant>) {
+        let Some(player) = self.this.upgrade() else {
+            return;
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+        let previous = self.sleep.lock().unwrap().replace(Sleep {
+            mode,
+            deadline,
+            cancel: Arc::clone(&cancel),
+        });
+        if let Some(previous) = previous {
+            previous.cancel.store(true, Ordering::Relaxed);
+            self.audio_manager.set_volume(*self.volume.lock().unwrap());
+        }
+        thread::spawn(move || player.run_sleep_timer(&cancel));
+        info!("Sleep timer set: {:?}", mode);
+        self.emit(PlayerEvent::SleepTimerChanged(self.sleep_timer()));
+    }
+
+    /// Stop the sleep timer's thread and undo its fade, returns false when none was running
+    fn stop_sleep_timer(&self) -> bool {
+        let mut sleep = self.sleep.lock().unwrap();
+        let Some(previous) = sleep.take() else {
+            return false;
+        };
+        previous.cancel.store(true, Ordering::Relaxed);
+        // under the lock, so the thread can't lower the volume again
+        self.audio_manager.set_volume(*self.volume.lock().unwrap());
+        true
+    }
+
+    /// Lower the volume as the sleep timer runs out and pause when a `SleepMode::Timer` is up.
+    /// The end of a track is left to `track_finished`.
+    fn run_sleep_timer(&self, cancel: &AtomicBool) {
+        let mut faded = false;
+        while !cancel.load(Ordering::Relaxed) {
+            let Some(timer) = self.sleep_timer() else {
+                return;
+            };
+            let remaining = timer.remaining_ms.map(Duration::from_millis);
+            let fade = *self.sleep_fade.lock().unwrap();
+
+            if timer.mode == SleepMode::Timer && remaining.is_some_and(|r| r.is_zero()) {
+                {
+                    let mut sleep = self.sleep.lock().unwrap();
+                    if cancel.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    sleep.take();
+                }
+                // paused before the volume comes back
+                self.pause();
+                self.audio_manager.set_volume(*self.volume.lock().unwrap());
+                info!("Sleep timer paused playback");
+                self.emit(PlayerEvent::SleepTimerChanged(None));
+                return;
+            }
+
+            let level = match remaining {
+                Some(remaining) if remaining < fade && self.state() == PlaybackState::Playing => {
+                    remaining.as_secs_f32() / fade.as_secs_f32()
+                }
+                _ => 1.0,
+            };
+            {
+                let _sleep = self.sleep.lock().unwrap();
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                // also once more at full volume, e.g. after skipping to a longer track
+                if level < 1.0 || faded {
+                    self.audio_manager
+                        .set_volume(*self.volume.lock().unwrap() * level);
+                }
+            }
+            faded = level < 1.0;
+
+            let wait = match remaining {
+                Some(remaining) if !faded => remaining.saturating_sub(fade),
+                Some(_) => SLEEP_FADE_STEP,
+                None => SLEEP_POLL,
+            };
+            thread::sleep(wait.clamp(SLEEP_FADE_STEP, SLEEP_POLL));
+        }
+    }
+
+    /// Move the queue to the next track, wrapping around when looping the playlist
+    fn advance(&self) -> Option<PathBuf> {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.advance() {
+            Some(track) => Some(track.clone()),
+            None if self.loop_mode() == LoopMode::Playlist => queue.advance().cloned(),
+            None => None,
+        }
+    }
+
+    /// Replace the queue with `tracks` and start playing the one at `start`
+    pub fn play_tracks(&self, tracks: Vec<PathBuf>, start: usize) {
+        let current = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.replace(tracks, start);
+            queue.current().cloned()
+        };
+        self.emit(PlayerEvent::QueueChanged);
+        self.start(current);
+    }
+
+    /// Replace the queue with `tracks` and play them from the first one, or from a random one when
+    /// shuffling
+    pub fn open(&self, tracks: Vec<PathBuf>) {
+        let current = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.replace_shuffled(tracks);
+            queue.current().cloned()
+        };
+        self.emit(PlayerEvent::QueueChanged);
+        self.start(current);
+    }
+
+    /// Add tracks to the end of the queue without interrupting playback
+    pub fn enqueue(&self, tracks: Vec<PathBuf>) {
+        self.queue.lock().unwrap().append(tracks);
+        self.emit(PlayerEvent::QueueChanged);
+    }
+
+    /// Stop playback and empty the queue
+    pub fn clear_queue(&self) {
+        self.queue.lock().unwrap().clear();
+        self.emit(PlayerEvent::QueueChanged);
+        self.start(None);
+    }
+
+    /// Start the track at `index` of the queue, returns false when there is none
+    pub fn jump(&self, index: usize) -> bool {
+        let track = self.queue.lock().unwrap().jump(index).cloned();
+        if track.is_none() {
+            return false;
+        }
+        self.start(track);
+        true
+    }
+
+    /// The tracks of the queue, in the order they were added
+    pub fn queue(&self) -> Vec<PathBuf> {
+        self.queue.lock().unwrap().tracks().to_vec()
+    }
+
+    /// Resume playback, or start the current (or first) track of the queue when stopped
+    pub fn play(&self) {
+        match self.state() {
+            PlaybackState::Playing => {}
+            PlaybackState::Paused => {
+                self.audio_manager.play();
+                self.set_state(PlaybackState::Playing);
+            }
+            PlaybackState::Stopped => {
+                let track = {
+                    let mut queue = self.queue.lock().unwrap();
+                    match queue.current() {
+                        Some(track) => Some(track.clone()),
+                        None => queue.advance().cloned(),
+                    }
+                };
+                if track.is_some() {
+                    self.start(track);
+                }
+            }
+        }
+    }
+
+    /// Pause playback, does nothing unless playing
+    pub fn pause(&self) {
+        if self.state() == PlaybackState::Playing {
+            self.audio_manager.pause();
+            self.set_state(PlaybackState::Paused);
+        }
+    }
+
+    /// Pause when playing, play otherwise
+    pub fn toggle(&self) {
+        if self.state() == PlaybackState::Playing {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Stop playback, keeping the queue
+    pub fn stop(&self) {
+        self.audio_manager.stop();
+        self.set_state(PlaybackState::Stopped);
+    }
+
+    /// Skip to the next track of the queue, stopping at its end
+    pub fn next(&self) {
+        let next = self.advance();
+        self.start(next);
+    }
+
+    /// Go back to the previous track of the queue
+    pub fn previous(&self) {
+        let previous = self.queue.lock().unwrap().back().cloned();
+        if previous.is_some() {
+            self.start(previous);
+        }
+    }
+
+    /// Jump to `position` in the current track
+    pub fn seek_to(&self, position: Duration) {
+        self.audio_manager.seek_to(position);
+    }
+
+    /// Jump ahead by the seek step
+    pub fn seek_forward(&self) {
+        self.audio_manager.seek_forward();
+    }
+
+    /// Jump back by the seek step
+    pub fn seek_backward(&self) {
+        self.audio_manager.seek_backward();
+    }
+
+    /// Set how far `seek_forward` and `seek_backward` jump
+    pub fn set_seek_step(&self, step: Duration) {
+        self.audio_manager.set_seek_step(step);
+    }
+
+    /// Switch to the output described by `settings` without interrupting the current track
+    pub fn set_output(&self, settings: AudioSettings) {
+        self.audio_manager.set_output(settings);
+    }
+
+    /// Change how ReplayGain tags adjust the volume, including for the current track
+    pub fn set_replaygain(&self, options: ReplayGainOptions) {
+        self.audio_manager.set_replaygain(options);
+    }
+
+    /// Change the equalizer curve, the current track follows without a gap
+    pub fn set_equalizer(&self, curve: EqCurve) {
+        self.audio_manager.set_equalizer(curve);
+    }
+
+    /// Play faster or slower without changing the pitch, clamped to `SPEED_RANGE`. Tracks that
+    /// keep their own speed (see `SpeedMemory`) remember it, others change the default speed.
+    pub fn set_speed(&self, speed: f32) {
+        let (min, max) = SPEED_RANGE;
+        self.change_rate(|rate| rate.speed = speed.clamp(min, max));
+    }
+
+    /// Shift the pitch by `semitones` without changing the speed, clamped to
+    /// `PITCH_RANGE_SEMITONES`, remembered like the speed
+    pub fn set_pitch(&self, semitones: f32) {
+        let (min, max) = PITCH_RANGE_SEMITONES;
+        self.change_rate(|rate| rate.pitch_semitones = semitones.clamp(min, max));
+    }
+
+    /// The speed and pitch of the current track
+    pub fn rate(&self) -> PlaybackRate {
+        *self.rate.lock().unwrap()
+    }
+
+    /// Set point A of the A–B loop to the current position. A point B before it is cleared; the
+    /// loop plays once B is set too.
+    pub fn set_loop_start(&self) {
+        if self.state() == PlaybackState::Stopped {
+            return;
+        }
+        let position = self.status().position_ms;
+        let end_ms = self
+            .ab_loop()
+            .and_then(|ab_loop| ab_loop.end_ms)
+            .filter(|&end| end >= position + MIN_LOOP_LENGTH.as_millis() as u64);
+        self.change_ab_loop(Some(AbLoop {
+            start_ms: position,
+            end_ms,
+        }));
+    }
+
+    /// Set point B of the A–B loop to the current position and start looping, from the start of
+    /// the track when no point A was set. Returns false when the loop would be shorter than
+    /// `MIN_LOOP_LENGTH`.
+    pub fn set_loop_end(&self) -> bool {
+        if self.state() == PlaybackState::Stopped {
+            return false;
+        }
+        let position = self.status().position_ms;
+        let start_ms = self.ab_loop().map_or(0, |ab_loop| ab_loop.start_ms);
+        if position < start_ms + MIN_LOOP_LENGTH.as_millis() as u64 {
+            return false;
+        }
+        self.change_ab_loop(Some(AbLoop {
+            start_ms,
+            end_ms: Some(position),
+        }));
+        true
+    }
+
+    /// Loop between `start` and `end` of the current track, jumping to `start` unless playback
+    /// is already in between. Returns false when nothing plays or the loop would be shorter than
+    /// `MIN_LOOP_LENGTH`.
+    pub fn set_ab_loop(&self, start: Duration, end: Duration) -> bool {
+        if self.state() == PlaybackState::Stopped || end < start + MIN_LOOP_LENGTH {
+            return false;
+        }
+        let position = Duration::from_millis(self.status().position_ms);
+        self.change_ab_loop(Some(AbLoop {
+            start_ms: start.as_millis() as u64,
+            end_ms: Some(end.as_millis() as u64),
+        }));
+        if !(start..end).contains(&position) {
+            self.seek_to(start);
+        }
+        true
+    }
+
+    /// Stop looping and play on from where playback is
+    pub fn clear_ab_loop(&self) {
+        self.change_ab_loop(None);
+    }
+
+    /// The A–B loop of the current track
+    pub fn ab_loop(&self) -> Option<AbLoop> {
+        *self.ab_loop.lock().unwrap()
+    }
+
+    /// Save the A–B loop for the current track under `name`, replacing a loop of that name.
+    /// Without a name it is called `Loop 1`, `Loop 2`, ... Returns the name, or `None` when no
+    /// complete loop is set.
+    pub fn save_loop(&self, name: Option<&str>) -> Option<String> {
+        let (start_ms, end_ms) = self
+            .ab_loop()
+            .and_then(|ab_loop| Some((ab_loop.start_ms, ab_loop.end_ms?)))?;
+        let track = self.queue.lock().unwrap().current().cloned()?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                let loops = self.track_states.lock().unwrap().get(&track).loops;
+                (1..)
+                    .map(|n| format!("Loop {}", n))
+                    .find(|name| loops.iter().all(|region| &region.name != name))
+                    .unwrap()
+            }
+        };
+
+        self.edit_track_state(&track, |state| {
+            state.loops.retain(|region| region.name != name);
+            state.loops.push(LoopRegion {
+                name: name.clone(),
+                start_ms,
+                end_ms,
+            });
+            state.loops.sort_by_key(|region| region.start_ms);
+        });
+        info!("Saved loop {} of {}", name, track.display());
+        self.emit(PlayerEvent::LoopsChanged);
+        Some(name)
+    }
+
+    /// The loops saved for the current track, ordered by their start
+    pub fn loops(&self) -> Vec<LoopRegion> {
+        let Some(track) = self.queue.lock().unwrap().current().cloned() else {
+            return Vec::new();
+        };
+        self.track_states.lock().unwrap().get(&track).loops
+    }
+
+    /// Loop the saved loop `name` of the current track from its start, false when there is none
+    pub fn play_loop(&self, name: &str) -> bool {
+        let Some(region) = self.loops().into_iter().find(|region| region.name == name) else {
+            return false;
+        };
+        let start = Duration::from_millis(region.start_ms);
+        if !self.set_ab_loop(start, Duration::from_millis(region.end_ms)) {
+            return false;
+        }
+        self.seek_to(start);
+        true
+    }
+
+    /// Forget the saved loop `name` of the current track, false when there is none
+    pub fn delete_loop(&self, name: &str) -> bool {
+        let Some(track) = self.queue.lock().unwrap().current().cloned() else {
+            return false;
+        };
+        let mut deleted = false;
+        self.edit_track_state(&track, |state| {
+            let before = state.loops.len();
+            state.loops.retain(|region| region.name != name);
+            deleted = state.loops.len() != before;
+        });
+        if deleted {
+            self.emit(PlayerEvent::LoopsChanged);
+        }
+        deleted
+    }
+
+    /// Change which tracks keep their own speed and pitch, from the next track on
+    pub fn set_speed_memory(&self, memory: SpeedMemory) {
+        *self.speed_memory.lock().unwrap() = memory;
+    }
+
+    /// Pause playback after `duration`, fading out over the sleep fade before
+    pub fn sleep_after(&self, duration: Duration) {
+        self.start_sleep_timer(SleepMode::Timer, Some(Instant::now() + duration));
+    }
+
+    /// Fade out towards the end of the current track and stop there, with the next track of the
+    /// queue current
+    pub fn sleep_at_end_of_track(&self) {
+        self.start_sleep_timer(SleepMode::EndOfTrack, None);
+    }
+
+    /// Fade out towards the end of the last track of the queue and stop there, also when the
+    /// playlist loops
+    pub fn sleep_at_end_of_queue(&self) {
+        self.start_sleep_timer(SleepMode::EndOfQueue, None);
+    }
+
+    /// Stop the sleep timer, undoing the fade if it started
+    pub fn cancel_sleep_timer(&self) {
+        if self.stop_sleep_timer() {
+            info!("Sleep timer cancelled");
+            self.emit(PlayerEvent::SleepTimerChanged(None));
+        }
+    }
+
+    /// Step the sleep timer on, as a sleep key does: off, 15 to 60 minutes (the next step longer
+    /// than the time left), the end of the track, the end of the queue and off again
+    pub fn cycle_sleep_timer(&self) {
+        let Some(timer) = self.sleep_timer() else {
+            self.sleep_after(Duration::from_secs(SLEEP_STEPS_MINUTES[0] * 60));
+            return;
+        };
+        match timer.mode {
+            SleepMode::Timer => {
+                let remaining = Duration::from_millis(timer.remaining_ms.unwrap_or(0));
+                match SLEEP_STEPS_MINUTES
+                    .iter()
+                    .map(|minutes| Duration::from_secs(minutes * 60))
+                    .find(|&step| step > remaining)
+                {
+                    Some(step) => self.sleep_after(step),
+                    None => self.sleep_at_end_of_track(),
+                }
+            }
+            SleepMode::EndOfTrack => self.sleep_at_end_of_queue(),
+            SleepMode::EndOfQueue => self.cancel_sleep_timer(),
+        }
+    }
+
+    /// The running sleep timer with the time it has left
+    pub fn sleep_timer(&self) -> Option<SleepTimer> {
+        let (mode, deadline) = self
+            .sleep
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|sleep| (sleep.mode, sleep.deadline))?;
+        let remaining = match mode {
+            SleepMode::Timer => {
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+            }
+            SleepMode::EndOfTrack => self.track_remaining(),
+            SleepMode::EndOfQueue => {
+                let last = self.queue.lock().unwrap().is_last();
+                self.track_remaining().filter(|_| last)
+            }
+        };
+        Some(SleepTimer {
+            mode,
+            remaining_ms: remaining.map(|remaining| remaining.as_millis() as u64),
+        })
+    }
+
+    /// Set how long the volume ramps on pause, resume, stop, seek and track switch take
+    pub fn set_ramp(&self, ramp: Duration) {
+        self.audio_manager.set_ramp(ramp);
+    }
+
+    /// Set how long a track crossfades with the next one when it plays until its end
+    pub fn set_crossfade(&self, crossfade: Duration) {
+        self.audio_manager.set_crossfade(crossfade);
+    }
+
+    /// Set how long playback fades out before the sleep timer ends it, from the next fade on
+    pub fn set_sleep_fade(&self, fade: Duration) {
+        *self.sleep_fade.lock().unwrap() = fade;
+    }
+
+    /// Set the balance, mono downmix and channel swap, the balance clamped to `BALANCE_RANGE`
+    pub fn set_channels(&self, channels: ChannelSettings) {
+        let (min, max) = BALANCE_RANGE;
+        let channels = ChannelSettings {
+            balance: channels.balance.clamp(min, max),
+            ..channels
+        };
+        {
+            let mut current = self.channels.lock().unwrap();
+            if *current == channels {
+                return;
+            }
+            *current = channels;
+        }
+        self.audio_manager.set_channels(channels);
+        self.emit(PlayerEvent::ChannelsChanged(channels));
+    }
+
+    /// Balance, mono downmix and channel swap
+    pub fn channels(&self) -> ChannelSettings {
+        *self.channels.lock().unwrap()
+    }
+
+    /// Set the volume, clamped to 0.0 - 1.0
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        *self.volume.lock().unwrap() = volume;
+        self.audio_manager.set_volume(volume);
+        self.emit(PlayerEvent::VolumeChanged(volume));
+    }
+
+    /// Play the queue in random order, or back in the order the tracks were added
+    pub fn set_shuffle(&self, shuffle: bool) {
+        self.queue.lock().unwrap().set_shuffle(shuffle);
+        self.emit(PlayerEvent::QueueChanged);
+    }
+
+    /// Set what happens at the end of a track
+    pub fn set_loop_mode(&self, mode: LoopMode) {
+        let mut current = self.loop_mode.lock().unwrap();
+        if *current != mode {
+            *current = mode;
+            drop(current);
+            self.emit(PlayerEvent::LoopChanged(mode));
+        }
+    }
+
+    /// What happens at the end of a track
+    pub fn loop_mode(&self) -> LoopMode {
+        *self.loop_mode.lock().unwrap()
+    }
+
+    /// Scan `roots` for songs and make them the library
+    pub fn scan_library(&self, roots: &[PathBuf]) {
+        let songs = library::scan(roots);
+        let _ = self.waveform_tx.send(WaveformJob::Later(
+            songs.iter().map(|song| song.path.clone()).collect(),
+        ));
+        *self.library_roots.lock().unwrap() = roots.to_vec();
+        *self.library.lock().unwrap() = songs;
+        self.emit(PlayerEvent::LibraryChanged);
+    }
+
+    /// Scan the current library roots again, e.g. after files were added
+    pub fn rescan_library(&self) {
+        let roots = self.library_roots.lock().unwrap().clone();
+        self.scan_library(&roots);
+    }
+
+    /// The folders the library was scanned from
+    pub fn library_roots(&self) -> Vec<PathBuf> {
+        self.library_roots.lock().unwrap().clone()
+    }
+
+    /// The songs of the library, in the order they were scanned
+    pub fn library(&self) -> Vec<SongInfo> {
+        self.library.lock().unwrap().clone()
+    }
+
+    /// Replace the queue with the whole library and start playing the song at `start`
+    pub fn play_library(&self, start: usize) {
+        let tracks = self
+            .library
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|song| song.path.clone())
+            .collect();
+        self.play_tracks(tracks, start);
+    }
+
+    /// Measured loudness of `track`, if it was analysed and is unchanged since
+    pub fn loudness(&self, track: &Path) -> Option<Loudness> {
+        self.index.lock().unwrap().loudness(track)
+    }
+
+    /// Waveform overview of `track`, once the background thread computed it. The current track
+    /// comes first, then the songs of the library.
+    pub fn waveform(&self, track: &Path) -> Option<Waveform> {
+        self.index.lock().unwrap().waveform(track).cloned()
+    }
+
+    /// Compute the waveform overview of `track` next, unless it is known
+    fn request_waveform(&self, track: &Path) {
+        if self.index.lock().unwrap().waveform(track).is_none() {
+            let _ = self.waveform_tx.send(WaveformJob::Now(track.to_path_buf()));
+        }
+    }
+
+    /// Measure the EBU R128 loudness of `tracks` on a background thread. Tracks sharing an album
+    /// tag and a folder are measured as an album too. The results are stored in the library
+    /// index, which playback uses for files without ReplayGain tags, and with `write_tags` in
+    /// the files' tags as well. Progress is reported through `PlayerEvent::AnalysisProgress`.
+    /// Returns false when an analysis is already running.
+    pub fn analyze_loudness(&self, tracks: Vec<PathBuf>, write_tags: bool) -> bool {
+        let cancel = Arc::new(AtomicBool::new(false));
+        {
+            let mut analysis = self.analysis.lock().unwrap();
+            if analysis.is_some() {
+                return false;
+            }
+            *analysis = Some(Arc::clone(&cancel));
+        }
+        let Some(player) = self.this.upgrade() else {
+            return false;
+        };
+
+        info!("Analysing the loudness of {} tracks", tracks.len());
+        thread::spawn(move || {
+            let stored = player.measure_albums(&tracks, write_tags, &cancel);
+            if let Err(e) = player.index.lock().unwrap().save() {
+                error!("Unable to save the library index: {}", e);
+            }
+            *player.analysis.lock().unwrap() = None;
+            info!("Loudness analysis stored {} tracks", stored);
+            player.emit(PlayerEvent::AnalysisFinished(stored));
+        });
+        true
+    }
+
+    /// `analyze_loudness` for every song of the library
+    pub fn analyze_library(&self, write_tags: bool) -> bool {
+        let tracks = self
+            .library
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|song| song.path.clone())
+            .collect();
+        self.analyze_loudness(tracks, write_tags)
+    }
+
+    /// Stop the running loudness analysis, the tracks measured so far are kept
+    pub fn cancel_analysis(&self) {
+        if let Some(cancel) = &*self.analysis.lock().unwrap() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Measure `tracks` album by album and store the results, returns how many were stored
+    fn measure_albums(&self, tracks: &[PathBuf], write_tags: bool, cancel: &AtomicBool) -> usize {
+        // (whether it is an album, its tracks), tracks without an album tag are on their own
+        let mut albums: Vec<(bool, Vec<PathBuf>)> = Vec::new();
+        let mut album_indices: HashMap<_, usize> = HashMap::new();
+        for track in tracks {
+            let Some(album) = self.album_of(track) else {
+                albums.push((false, vec![track.clone()]));
+                continue;
+            };
+            let key = (track.parent().map(Path::to_path_buf), album);
+            match album_indices.get(&key) {
+                Some(&index) => albums[index].1.push(track.clone()),
+                None => {
+                    album_indices.insert(key, albums.len());
+                    albums.push((true, vec![track.clone()]));
+                }
+            }
+        }
+
+        let total = tracks.len();
+        let mut done = 0;
+        let mut stored = 0;
+        for (is_album, album) in albums {
+            let mut measurements = Vec::new();
+            for track in album {
+                match Measurement::of(&track, cancel) {
+                    Ok(measurement) => measurements.push((track, measurement)),
+                    Err(LoudnessError::Cancelled) => return stored,
+                    Err(e) => error!("Unable to measure {}: {}", track.display(), e),
+                }
+                done += 1;
+                self.emit(PlayerEvent::AnalysisProgress { done, total });
+            }
+
+            let album_lufs = is_album
+                .then(|| loudness::album_loudness(measurements.iter().map(|(_, m)| m)))
+                .flatten();
+            let album_peak = album_lufs.and_then(|_| {
+                measurements
+                    .iter()
+                    .map(|(_, m)| m.true_peak())
+                    .reduce(f32::max)
+            });
+            for (track, measurement) in measurements {
+                let Some(integrated_lufs) = measurement.integrated_lufs() else {
+                    error!(
+                        "Unable to measure {}: {}",
+                        track.display(),
+                        LoudnessError::Silent
+                    );
+                    continue;
+                };
+                let loudness = Loudness {
+                    integrated_lufs,
+                    true_peak: measurement.true_peak(),
+                    album_lufs,
+                    album_peak,
+                };
+                info!(
+                    "{}: {:.1} LUFS, true peak {:.3}",
+                    track.display(),
+                    integrated_lufs,
+                    loudness.true_peak
+                );
+                // before indexing, as it changes the modification time
+                if write_tags && let Err(e) = loudness::write_tags(&track, &loudness) {
+                    error!("Unable to tag {}: {}", track.display(), e);
+                }
+                self.index.lock().unwrap().set_loudness(&track, loudness);
+                stored += 1;
+            }
+        }
+        stored
+    }
+
+    /// Everything needed to continue later with `restore`
+    pub fn saved_state(&self) -> SavedState {
+        let status = self.status();
+        SavedState {
+            queue: self.queue(),
+            queue_position: status.queue_position,
+            position_ms: status.position_ms,
+            state: status.state,
+            volume: status.volume,
+            shuffle: status.shuffle,
+            loop_mode: status.loop_mode,
+        }
+    }
+
+    /// Pick up where `saved` left off: the queue, modes and volume are restored and the current
+    /// track is loaded at its old position, playing only if it was playing before
+    pub fn restore(&self, saved: SavedState) {
+        let current = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.set_shuffle(saved.shuffle);
+            queue.replace(saved.queue, saved.queue_position.unwrap_or(usize::MAX));
+            queue.current().cloned()
+        };
+        self.set_volume(saved.volume);
+        self.set_loop_mode(saved.loop_mode);
+        self.emit(PlayerEvent::QueueChanged);
+
+        let Some(track) = current.filter(|_| saved.state != PlaybackState::Stopped) else {
+            return;
+        };
+        info!("Restoring {} at {}ms", track.display(), saved.position_ms);
+        // a freshly loaded track stays paused until `play`
+        self.forget_ab_loop();
+        self.apply_rate(self.rate_for(&track));
+        self.audio_manager.load(
+            track.to_string_lossy().into_owned(),
+            self.playing_album_in_order(),
+            self.measured_gain(&track),
+        );
+        self.request_waveform(&track);
+        if saved.position_ms > 0 {
+            self.audio_manager
+                .seek_to(Duration::from_millis(saved.position_ms));
+        }
+        self.emit(PlayerEvent::TrackChanged(Some(track)));
+        self.set_state(PlaybackState::Paused);
+        if saved.state == PlaybackState::Playing {
+            self.play();
+        }
+    }
+
+    /// Lower the volume to silence over `duration`, blocking until done. The volume setting is
+    /// left alone, so the next track plays at the old volume again.
+    pub fn fade_out(&self, duration: Duration) {
+        if self.state() != PlaybackState::Playing {
+            return;
+        }
+        let volume = *self.volume.lock().unwrap();
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            let remaining = 1.0 - start.elapsed().as_secs_f32() / duration.as_secs_f32();
+            self.audio_manager.set_volume(volume * remaining);
+            thread::sleep(FADE_STEP);
+        }
+        self.audio_manager.set_volume(0.0);
+    }
+
+    /// Spectrum with `bands` bands, waveform and levels of what is playing, before the volume is
+    /// applied. `None` while paused or stopped.
+    pub fn spectrum(&self, bands: usize) -> Option<Spectrum> {
+        (self.state() == PlaybackState::Playing).then(|| self.audio_manager.spectrum(bands))
+    }
+
+    /// Whether a track is playing
+    pub fn state(&self) -> PlaybackState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Snapshot of everything a front-end shows
+    pub fn status(&self) -> PlayerStatus {
+        // before locking the queue, which it needs too
+        let sleep_timer = self.sleep_timer();
+        let audio = self.audio_manager.status();
+        let queue = self.queue.lock().unwrap();
+        let state = self.state();
+
+        PlayerStatus {
+            state,
+            track: queue.current().cloned(),
+            position_ms: if state == PlaybackState::Stopped {
+                0
+            } else {
+                audio.position().as_millis() as u64
+            },
+            duration_ms: audio.duration.map(|d| d.as_millis() as u64),
+            volume: *self.volume.lock().unwrap(),
+            shuffle: queue.is_shuffled(),
+            loop_mode: self.loop_mode(),
+            queue_position: queue.current_index(),
+            queue_length: queue.len(),
+            speed: self.rate().speed,
+            pitch_semitones: self.rate().pitch_semitones,
+            ab_loop: self.ab_loop(),
+            sleep_timer,
+            channels: self.channels(),
+        }
+    }
+}
+
+/// Compute the waveform overviews asked for through `jobs` and store them in the library index,
+/// until the player is gone
+fn compute_waveforms(player: Weak<Player>, jobs: Receiver<WaveformJob>) {
+    let mut pending = VecDeque::new();
+    // tracks that can't be decoded aren't tried again
+    let mut failed = HashSet::new();
+    let mut unsaved = 0;
+    loop {
+        let job = if pending.is_empty() {
+            jobs.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            jobs.try_recv()
+        };
+        // every job that arrived in the meantime is queued before the next track is computed
+        match job {
+            Ok(WaveformJob::Now(track)) => {
+                pending.push_front(track);
+                continue;
+            }
+            Ok(WaveformJob::Later(tracks)) => {
+                pending.extend(tracks);
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => break,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        let Some(track) = pending.pop_front() else {
+            continue;
+        };
+        let Some(player) = player.upgrade() else {
+            break;
+        };
+        if failed.contains(&track) || player.index.lock().unwrap().waveform(&track).is_some() {
+            continue;
+        }
+        match Waveform::of(&track) {
+            Ok(waveform) => {
+                player.index.lock().unwrap().set_waveform(&track, waveform);
+                unsaved += 1;
+            }
+            Err(e) => {
+                error!(
+                    "Unable to compute the waveform of {}: {}",
+                    track.display(),
+                    e
+                );
+                failed.insert(track);
+            }
+        }
+        if unsaved > 0 && (pending.is_empty() || unsaved >= WAVEFORM_SAVE_EVERY) {
+            if let Err(e) = player.index.lock().unwrap().save() {
+                error!("Unable to save the library index: {}", e);
+            }
+            unsaved = 0;
+        }
+    }
+}
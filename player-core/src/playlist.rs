@@ -0,0 +1,37 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extensions recognised as M3U playlists
+const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+
+/// Whether `path` is a file with an M3U playlist extension
+pub fn is_playlist(path: &Path) -> bool {
+    path.is_file()
+        && path.extension().is_some_and(|ext| {
+            PLAYLIST_EXTENSIONS
+                .iter()
+                .any(|playlist| ext.eq_ignore_ascii_case(playlist))
+        })
+}
+
+/// Read an M3U/M3U8 playlist. Comment and `#EXT` lines are skipped, relative entries are resolved
+/// against the playlist's directory.
+pub fn load(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let entry = Path::new(line);
+            if entry.is_absolute() {
+                entry.to_path_buf()
+            } else {
+                base.join(entry)
+            }
+        })
+        .collect())
+}
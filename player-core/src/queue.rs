@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+
+/// The tracks lined up for playback. With shuffle enabled the tracks keep their order but are
+/// played following a shuffled `order`.
+#[derive(Clone, Debug, Default)]
+pub struct Queue {
+    tracks: Vec<PathBuf>,
+    /// Playback order, indices into `tracks`
+    order: Vec<usize>,
+    /// Current position in `order`
+    position: Option<usize>,
+    shuffle: bool,
+}
+
+impl Queue {
+    /// Replace the queue with `tracks`, making `start` the current track
+    pub fn replace(&mut self, tracks: Vec<PathBuf>, start: usize) {
+        self.tracks = tracks;
+        self.order = (0..self.tracks.len()).collect();
+        self.position = None;
+        if start < self.tracks.len() {
+            self.position = Some(start);
+        }
+        self.reorder();
+    }
+
+    /// Replace the queue with `tracks`, making the first track of the play order current: a random
+    /// one when shuffling
+    pub fn replace_shuffled(&mut self, tracks: Vec<PathBuf>) {
+        self.replace(tracks, usize::MAX);
+        if !self.order.is_empty() {
+            self.position = Some(0);
+        }
+    }
+
+    /// Add tracks to the end of the queue
+    pub fn append(&mut self, tracks: Vec<PathBuf>) {
+        let first_new = self.tracks.len();
+        self.tracks.extend(tracks);
+        let mut new_indices = (first_new..self.tracks.len()).collect::<Vec<_>>();
+        if self.shuffle {
+            fastrand::shuffle(&mut new_indices);
+        }
+        self.order.extend(new_indices);
+    }
+
+    /// Remove every track, keeping the shuffle setting
+    pub fn clear(&mut self) {
+        *self = Self {
+            shuffle: self.shuffle,
+            ..Self::default()
+        };
+    }
+
+    /// The tracks, in the order they were added
+    pub fn tracks(&self) -> &[PathBuf] {
+        &self.tracks
+    }
+
+    /// Number of tracks
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Whether there are no tracks
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Index into `tracks` of the current track
+    pub fn current_index(&self) -> Option<usize> {
+        self.position.map(|position| self.order[position])
+    }
+
+    /// The current track, `None` before the first or after the last one
+    pub fn current(&self) -> Option<&PathBuf> {
+        self.current_index().map(|index| &self.tracks[index])
+    }
+
+    /// Whether the current track is the last one to play
+    pub fn is_last(&self) -> bool {
+        self.position
+            .is_some_and(|position| position + 1 == self.order.len())
+    }
+
+    /// Move to the next track, `None` at the end of the queue
+    pub fn advance(&mut self) -> Option<&PathBuf> {
+        let next = self.position.map_or(0, |position| position + 1);
+        if next >= self.order.len() {
+            self.position = None;
+            return None;
+        }
+        self.position = Some(next);
+        self.current()
+    }
+
+    /// Move to the previous track, staying on the first one
+    pub fn back(&mut self) -> Option<&PathBuf> {
+        if self.order.is_empty() {
+            return None;
+        }
+        self.position = Some(
+            self.position
+                .map_or(0, |position| position.saturating_sub(1)),
+        );
+        self.current()
+    }
+
+    /// Make the track at `index` (into `tracks`) the current one, the current track is kept when
+    /// there is no such track
+    pub fn jump(&mut self, index: usize) -> Option<&PathBuf> {
+        let position = self.order.iter().position(|&i| i == index)?;
+        self.position = Some(position);
+        self.current()
+    }
+
+    /// Whether the tracks play in random order
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Play in random order, or in the order the tracks were added
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if self.shuffle != shuffle {
+            self.shuffle = shuffle;
+            self.reorder();
+        }
+    }
+
+    /// Rebuild `order`, keeping the current track current. When shuffling the current track
+    /// moves to the front so every other track is still ahead of it.
+    fn reorder(&mut self) {
+        let current = self.current_index();
+        self.order = (0..self.tracks.len()).collect();
+
+        if self.shuffle {
+            fastrand::shuffle(&mut self.order);
+            if let Some(current) = current {
+                let at = self.order.iter().position(|&i| i == current).unwrap_or(0);
+                self.order.swap(0, at);
+            }
+        }
+
+        self.position = current.and_then(|current| self.order.iter().position(|&i| i == current));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks(count: usize) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| PathBuf::from(format!("{}.mp3", i)))
+            .collect()
+    }
+
+    #[test]
+    fn replace_keeps_the_start_track_current_when_shuffling() {
+        let mut queue = Queue::default();
+        queue.set_shuffle(true);
+        queue.replace(tracks(10), 3);
+        assert_eq!(queue.current_index(), Some(3));
+        assert!(!queue.is_last());
+    }
+
+    #[test]
+    fn replace_shuffled_starts_from_a_random_track() {
+        let mut queue = Queue::default();
+        queue.set_shuffle(true);
+        let starts = (0..50)
+            .filter_map(|_| {
+                queue.replace_shuffled(tracks(10));
+                queue.current_index()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(starts.len(), 50);
+        assert!(starts.iter().any(|&start| start != 0));
+    }
+
+    #[test]
+    fn replace_shuffled_starts_from_the_first_track_in_order() {
+        let mut queue = Queue::default();
+        queue.replace_shuffled(tracks(3));
+        assert_eq!(queue.current_index(), Some(0));
+        queue.replace_shuffled(Vec::new());
+        assert_eq!(queue.current_index(), None);
+    }
+}
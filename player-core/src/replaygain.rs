@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use symphonia::core::meta::{StandardTagKey, Tag};
+
+use crate::library;
+use crate::settings::ReplayGainMode;
+
+/// `R128_*_GAIN` tags are relative to -23 LUFS, ReplayGain to its -18 LUFS reference level
+const R128_TO_REPLAYGAIN_DB: f32 = 5.0;
+
+/// The ReplayGain tags of a track, gains in dB and peaks as linear sample values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayGain {
+    /// Adjustment bringing the track to the reference loudness
+    pub track_gain: Option<f32>,
+    /// Loudest sample of the track, 1.0 is full scale
+    pub track_peak: Option<f32>,
+    /// Adjustment bringing the whole album to the reference loudness
+    pub album_gain: Option<f32>,
+    /// Loudest sample of the album
+    pub album_peak: Option<f32>,
+}
+
+/// How the gain of the playing track is chosen, see `Settings::replaygain`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplayGainOptions {
+    /// Which gain applies
+    pub mode: ReplayGainMode,
+    /// Added to the gain of every tagged track, in dB
+    pub preamp_db: f32,
+    /// Lower the gain where the peak would otherwise exceed full scale
+    pub prevent_clipping: bool,
+}
+
+impl ReplayGain {
+    /// Read the tags of the file at `path`: ID3v2 `TXXX:REPLAYGAIN_*` frames, Vorbis comments
+    /// and `R128_*_GAIN` tags as written for Opus by some taggers. Files without any (or that
+    /// can't be read) have no gain.
+    pub fn read(path: &Path) -> Self {
+        let mut gain = Self::default();
+        library::container_tags(path)
+            .iter()
+            .for_each(|tag| gain.apply(tag));
+        gain
+    }
+
+    /// These tags, or `other` when there is no gain among them
+    pub fn or(self, other: Self) -> Self {
+        if self.track_gain.is_none() && self.album_gain.is_none() {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn apply(&mut self, tag: &Tag) {
+        let value = tag.value.to_string();
+        match tag.std_key {
+            Some(StandardTagKey::ReplayGainTrackGain) => self.track_gain = parse_db(&value),
+            Some(StandardTagKey::ReplayGainTrackPeak) => {
+                self.track_peak = value.trim().parse().ok()
+            }
+            Some(StandardTagKey::ReplayGainAlbumGain) => self.album_gain = parse_db(&value),
+            Some(StandardTagKey::ReplayGainAlbumPeak) => {
+                self.album_peak = value.trim().parse().ok()
+            }
+            _ if tag.key.eq_ignore_ascii_case("R128_TRACK_GAIN") => {
+                self.track_gain = self.track_gain.or(parse_r128(&value))
+            }
+            _ if tag.key.eq_ignore_ascii_case("R128_ALBUM_GAIN") => {
+                self.album_gain = self.album_gain.or(parse_r128(&value))
+            }
+            _ => {}
+        }
+    }
+
+    /// Volume factor for the track. `in_album` tells whether it plays as part of its album, which
+    /// makes `Auto` use the album gain. Tracks without the wanted gain fall back to the other one,
+    /// untagged tracks play unchanged.
+    pub fn factor(&self, options: &ReplayGainOptions, in_album: bool) -> f32 {
+        let album = match options.mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => false,
+            ReplayGainMode::Album => true,
+            ReplayGainMode::Auto => in_album,
+        };
+        let (gain, peak) = match (album, self.track_gain, self.album_gain) {
+            (true, _, Some(gain)) | (false, None, Some(gain)) => (gain, self.album_peak),
+            (_, Some(gain), _) => (gain, self.track_peak),
+            (_, None, None) => return 1.0,
+        };
+
+        let factor = 10f32.powf((gain + options.preamp_db) / 20.0);
+        match peak {
+            Some(peak) if options.prevent_clipping && peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parse a ReplayGain value like `-6.54 dB`
+fn parse_db(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .len()
+        .checked_sub(2)
+        .filter(|&end| value.is_char_boundary(end) && value[end..].eq_ignore_ascii_case("db"))
+        .map_or(value, |end| &value[..end]);
+    number.trim().parse().ok()
+}
+
+/// Parse an R128 gain, a Q7.8 fixed point number of dB relative to -23 LUFS, as ReplayGain dB
+fn parse_r128(value: &str) -> Option<f32> {
+    let q78 = value.trim().parse::<i16>().ok()?;
+    Some(q78 as f32 / 256.0 + R128_TO_REPLAYGAIN_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::meta::Value;
+
+    fn options(mode: ReplayGainMode, preamp_db: f32, prevent_clipping: bool) -> ReplayGainOptions {
+        ReplayGainOptions {
+            mode,
+            preamp_db,
+            prevent_clipping,
+        }
+    }
+
+    fn db(factor: f32) -> f32 {
+        20.0 * factor.log10()
+    }
+
+    fn assert_db(factor: f32, expected: f32) {
+        assert!(
+            (db(factor) - expected).abs() < 0.001,
+            "{} dB instead of {} dB",
+            db(factor),
+            expected
+        );
+    }
+
+    const TAGGED: ReplayGain = ReplayGain {
+        track_gain: Some(-6.0),
+        track_peak: Some(0.9),
+        album_gain: Some(-4.0),
+        album_peak: Some(0.95),
+    };
+
+    #[test]
+    fn parses_db_values() {
+        assert_eq!(parse_db("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_db(" +2.1 db "), Some(2.1));
+        assert_eq!(parse_db("-3.5dB"), Some(-3.5));
+        assert_eq!(parse_db("1.25"), Some(1.25));
+        assert_eq!(parse_db("loud"), None);
+        assert_eq!(parse_db("dB"), None);
+        assert_eq!(parse_db(""), None);
+    }
+
+    #[test]
+    fn parses_r128_q78_relative_to_replaygain() {
+        assert_eq!(parse_r128("0"), Some(5.0));
+        // -1 dB in Q7.8, 5 dB louder at the ReplayGain reference
+        assert_eq!(parse_r128("-256"), Some(4.0));
+        assert_eq!(parse_r128(" -2688 "), Some(-5.5));
+        assert_eq!(parse_r128("-6.5"), None);
+        assert_eq!(parse_r128("40000"), None);
+    }
+
+    #[test]
+    fn applies_replaygain_and_r128_tags() {
+        let mut gain = ReplayGain::default();
+        let tag = |key: Option<StandardTagKey>, name: &str, value: &str| {
+            Tag::new(key, name, Value::String(String::from(value)))
+        };
+        gain.apply(&tag(None, "R128_TRACK_GAIN", "-256"));
+        gain.apply(&tag(
+            Some(StandardTagKey::ReplayGainTrackGain),
+            "REPLAYGAIN_TRACK_GAIN",
+            "-7.5 dB",
+        ));
+        gain.apply(&tag(
+            Some(StandardTagKey::ReplayGainTrackPeak),
+            "REPLAYGAIN_TRACK_PEAK",
+            "0.988",
+        ));
+        gain.apply(&tag(None, "r128_album_gain", "512"));
+        assert_eq!(
+            gain,
+            ReplayGain {
+                track_gain: Some(-7.5),
+                track_peak: Some(0.988),
+                album_gain: Some(7.0),
+                album_peak: None,
+            }
+        );
+
+        // R128 gains don't replace ReplayGain ones read before
+        gain.apply(&tag(None, "R128_TRACK_GAIN", "0"));
+        assert_eq!(gain.track_gain, Some(-7.5));
+    }
+
+    #[test]
+    fn factor_follows_the_mode() {
+        for in_album in [false, true] {
+            assert_eq!(
+                TAGGED.factor(&options(ReplayGainMode::Off, 3.0, false), in_album),
+                1.0
+            );
+            assert_db(
+                TAGGED.factor(&options(ReplayGainMode::Track, 0.0, false), in_album),
+                -6.0,
+            );
+            assert_db(
+                TAGGED.factor(&options(ReplayGainMode::Album, 0.0, false), in_album),
+                -4.0,
+            );
+        }
+        let auto = options(ReplayGainMode::Auto, 0.0, false);
+        assert_db(TAGGED.factor(&auto, false), -6.0);
+        assert_db(TAGGED.factor(&auto, true), -4.0);
+    }
+
+    #[test]
+    fn factor_falls_back_to_the_other_gain() {
+        let track_only = ReplayGain {
+            album_gain: None,
+            ..TAGGED
+        };
+        assert_db(
+            track_only.factor(&options(ReplayGainMode::Album, 0.0, false), true),
+            -6.0,
+        );
+        let album_only = ReplayGain {
+            track_gain: None,
+            ..TAGGED
+        };
+        assert_db(
+            album_only.factor(&options(ReplayGainMode::Track, 0.0, false), false),
+            -4.0,
+        );
+        let untagged = ReplayGain::default();
+        assert_eq!(
+            untagged.factor(&options(ReplayGainMode::Track, 6.0, true), false),
+            1.0
+        );
+    }
+
+    #[test]
+    fn factor_adds_the_preamp_and_prevents_clipping() {
+        assert_db(
+            TAGGED.factor(&options(ReplayGainMode::Track, 2.5, false), false),
+            -3.5,
+        );
+
+        let quiet = ReplayGain {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..ReplayGain::default()
+        };
+        let boost = options(ReplayGainMode::Track, 0.0, false);
+        assert_db(quiet.factor(&boost, false), 6.0);
+        // +6 dB would take the 0.8 peak to 1.6, so the gain stops at 1 / 0.8
+        let limited = options(ReplayGainMode::Track, 0.0, true);
+        assert!((quiet.factor(&limited, false) - 1.25).abs() < 1e-6);
+        // a gain that doesn't clip is left alone
+        assert_db(
+            TAGGED.factor(&options(ReplayGainMode::Track, 0.0, true), false),
+            -6.0,
+        );
+    }
+}
@@ -0,0 +1,816 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use log::{error, info, warn};
+
+use crate::equalizer::{self, CUSTOM_PRESET, EqCurve, EqMode, EqPreset};
+use crate::replaygain::ReplayGainOptions;
+
+/// Directory name used inside the XDG config dir
+const CONFIG_DIR_NAME: &str = "music-player";
+
+/// File name of the settings file inside the config dir
+const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+/// How often the settings file is checked for modifications
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Theme name that follows the desktop's light/dark preference, the default
+pub const SYSTEM_THEME: &str = "system";
+
+/// Fully left and fully right of `channels.balance`
+pub const BALANCE_RANGE: (f32, f32) = (-1.0, 1.0);
+
+/// Bounds used when validating user supplied values
+const SEEK_STEP_RANGE_SECS: (u64, u64) = (1, 600);
+const CROSSFADE_RANGE_SECS: (f32, f32) = (0.0, 12.0);
+const SLEEP_FADE_RANGE_SECS: (f32, f32) = (0.0, 300.0);
+const RAMP_RANGE_MS: (u64, u64) = (0, 500);
+const MIN_WINDOW_SIZE: f32 = 200.0;
+const OUTPUT_SPEED_RANGE: (f32, f32) = (1.0, 100.0);
+const REPLAYGAIN_PREAMP_RANGE_DB: (f32, f32) = (-15.0, 15.0);
+const EQ_GAIN_RANGE_DB: (f32, f32) = (-24.0, 24.0);
+const EQ_FREQUENCY_RANGE_HZ: (f32, f32) = (20.0, 20000.0);
+const EQ_Q_RANGE: (f32, f32) = (0.1, 10.0);
+const MAX_EQ_BANDS: usize = 16;
+
+/// All user configurable settings, stored as TOML in `$XDG_CONFIG_HOME/music-player/settings.toml`.
+/// Every field has a default so a partial (or missing) file is always valid.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// `[library]`
+    pub library: LibrarySettings,
+    /// `[playback]`
+    pub playback: PlaybackSettings,
+    /// `[audio]`
+    pub audio: AudioSettings,
+    /// `[equalizer]`
+    pub equalizer: EqualizerSettings,
+    /// `[channels]`
+    pub channels: ChannelSettings,
+    /// `[appearance]`
+    pub appearance: AppearanceSettings,
+    /// `[shortcuts]`
+    pub shortcuts: ShortcutSettings,
+    /// `[mpd]`
+    pub mpd: MpdSettings,
+    /// `[http]`
+    pub http: HttpSettings,
+}
+
+/// Where songs are looked for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibrarySettings {
+    /// Directories scanned for songs
+    pub roots: Vec<PathBuf>,
+    /// Store the results of the loudness analysis as ReplayGain tags in the files too
+    pub write_gain_tags: bool,
+}
+
+/// How tracks are played
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlaybackSettings {
+    /// The seek step in seconds for forward/backward seeking
+    pub seek_step_secs: u64,
+    /// Crossfade duration between tracks, 0 disables crossfading
+    pub crossfade_secs: f32,
+    /// Which ReplayGain tags adjust the volume
+    pub replaygain: ReplayGainMode,
+    /// Added to the ReplayGain of every tagged track, in dB
+    pub replaygain_preamp_db: f32,
+    /// Lower the ReplayGain of tracks whose peak would clip
+    pub replaygain_prevent_clipping: bool,
+    /// Which tracks keep their own speed and pitch
+    pub remember_speed: SpeedMemory,
+    /// How long playback fades out before the sleep timer ends it
+    pub sleep_fade_secs: f32,
+    /// Length of the volume ramps on pause, resume, stop, seek and track switch, 0 cuts
+    /// right away
+    pub ramp_ms: u64,
+}
+
+/// How the left and right channel are mixed, for one-eared listeners and broken headphones
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelSettings {
+    /// -1.0 plays only the left channel, 1.0 only the right one, 0.0 both at full volume
+    pub balance: f32,
+    /// Play both channels mixed down to mono on both sides
+    pub mono: bool,
+    /// Play the left channel on the right and the other way round
+    pub swap: bool,
+}
+
+/// The audio output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings {
+    /// Where the audio goes
+    pub output: OutputKind,
+    /// Name of the output device, `None` uses the system default
+    pub output_device: Option<String>,
+    /// File written by the `wav` output
+    pub wav_file: Option<PathBuf>,
+    /// How many times faster than real time the `null` and `wav` outputs consume audio
+    pub output_speed: f32,
+}
+
+/// The equalizer applied between the decoder and the output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EqualizerSettings {
+    /// Apply the selected preset
+    pub enabled: bool,
+    /// Name of the selected preset, a built-in one or one of `presets`
+    pub preset: String,
+    /// User presets, `[[equalizer.presets]]`
+    pub presets: Vec<EqPreset>,
+}
+
+/// Look of the window
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppearanceSettings {
+    /// Name of the theme, `system` follows the light or dark system appearance
+    pub theme: String,
+    /// Initial window width in pixels
+    pub window_width: f32,
+    /// Initial window height in pixels
+    pub window_height: f32,
+    /// What the visualizer above the seek bar shows
+    pub visualizer: VisualizerMode,
+}
+
+/// Keyboard shortcuts
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShortcutSettings {
+    /// Optional keymap file whose bindings are added on top of the defaults
+    pub keymap: Option<PathBuf>,
+}
+
+/// The MPD protocol server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MpdSettings {
+    /// Serve the MPD protocol so MPD clients can control the player
+    pub enabled: bool,
+    /// Address to listen on, e.g. `0.0.0.0` to accept clients from other machines
+    pub bind_address: String,
+    /// TCP port to listen on
+    pub port: u16,
+}
+
+/// The HTTP API and web remote
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpSettings {
+    /// Serve the HTTP API and the remote control page
+    pub enabled: bool,
+    /// Address to listen on, e.g. `0.0.0.0` to accept clients from other machines
+    pub bind_address: String,
+    /// TCP port to listen on
+    pub port: u16,
+    /// Secret every API request has to present, `None` allows anyone who can connect
+    pub token: Option<String>,
+}
+
+/// Which ReplayGain tags adjust the volume
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    /// Play everything at its stored level
+    #[default]
+    Off,
+    /// Use the track gain
+    Track,
+    /// Use the album gain, falling back to the track gain
+    Album,
+    /// Album gain while a whole album plays in order, track gain otherwise
+    Auto,
+}
+
+/// Which tracks keep the speed and pitch they were last played at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedMemory {
+    /// Every track plays at the current speed
+    Off,
+    /// Podcasts, audiobooks and other speech, recognized by their genre tag
+    #[default]
+    SpokenWord,
+    /// Every track
+    All,
+}
+
+/// How the window visualizes what is playing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerMode {
+    /// No visualizer
+    Off,
+    /// Bar spectrum
+    #[default]
+    Bars,
+    /// Oscilloscope of the waveform
+    Scope,
+}
+
+/// Where the audio thread sends its samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputKind {
+    /// A sound card, see `AudioSettings::output_device`
+    #[default]
+    Device,
+    /// Nowhere, e.g. to run without a sound card
+    Null,
+    /// A WAV file, see `AudioSettings::wav_file`
+    Wav,
+}
+
+impl Default for LibrarySettings {
+    fn default() -> Self {
+        Self {
+            roots: vec![PathBuf::from(".")],
+            write_gain_tags: false,
+        }
+    }
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            seek_step_secs: 10,
+            crossfade_secs: 0.0,
+            replaygain: ReplayGainMode::Off,
+            replaygain_preamp_db: 0.0,
+            replaygain_prevent_clipping: true,
+            remember_speed: SpeedMemory::SpokenWord,
+            sleep_fade_secs: 30.0,
+            ramp_ms: 20,
+        }
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            output: OutputKind::Device,
+            output_device: None,
+            wav_file: None,
+            output_speed: 1.0,
+        }
+    }
+}
+
+impl Default for EqualizerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preset: String::from("Flat"),
+            presets: Vec::new(),
+        }
+    }
+}
+
+impl EqualizerSettings {
+    /// The built-in presets followed by the user presets
+    pub fn all_presets(&self) -> Vec<EqPreset> {
+        let mut presets = equalizer::builtin_presets();
+        presets.extend(self.presets.iter().cloned());
+        presets
+    }
+
+    /// The selected preset, `None` when there is none by that name
+    pub fn active(&self) -> Option<EqPreset> {
+        self.all_presets()
+            .into_iter()
+            .find(|preset| preset.name == self.preset)
+    }
+
+    /// What the audio thread applies, flat while disabled
+    pub fn curve(&self) -> EqCurve {
+        match self.active() {
+            Some(preset) if self.enabled => preset.curve(),
+            _ => EqCurve::default(),
+        }
+    }
+
+    /// Change the selected preset. Built-in presets stay as they are, they are copied to the
+    /// user preset `Custom` first, which then becomes the selected one.
+    pub fn edit_active(&mut self, edit: impl FnOnce(&mut EqPreset)) {
+        if !self.presets.iter().any(|preset| preset.name == self.preset) {
+            let mut copy = self
+                .active()
+                .unwrap_or_else(|| equalizer::builtin_presets().remove(0));
+            copy.name = String::from(CUSTOM_PRESET);
+            self.presets.retain(|preset| preset.name != CUSTOM_PRESET);
+            self.presets.push(copy);
+            self.preset = String::from(CUSTOM_PRESET);
+        }
+        if let Some(preset) = self
+            .presets
+            .iter_mut()
+            .find(|preset| preset.name == self.preset)
+        {
+            edit(preset);
+        }
+    }
+
+    fn validate(&self) -> Result<(), SettingsError> {
+        let builtin = equalizer::builtin_presets();
+        for (index, preset) in self.presets.iter().enumerate() {
+            let invalid = |message: String| {
+                Err(SettingsError::Invalid(format!(
+                    "equalizer.presets `{}`: {}",
+                    preset.name, message
+                )))
+            };
+            if preset.name.is_empty() {
+                return Err(SettingsError::Invalid(String::from(
+                    "equalizer.presets: every preset needs a name",
+                )));
+            }
+            if builtin.iter().any(|other| other.name == preset.name)
+                || self.presets[..index]
+                    .iter()
+                    .any(|other| other.name == preset.name)
+            {
+                return invalid(String::from("the name is already taken"));
+            }
+
+            let (min_gain, max_gain) = EQ_GAIN_RANGE_DB;
+            let gains = preset.gains_db.iter().chain(
+                std::iter::once(&preset.preamp_db)
+                    .chain(preset.bands.iter().map(|band| &band.gain_db)),
+            );
+            for gain in gains {
+                if !(min_gain..=max_gain).contains(gain) {
+                    return invalid(format!(
+                        "gains must be between {} and {} dB, got {}",
+                        min_gain, max_gain, gain
+                    ));
+                }
+            }
+
+            if preset.mode == EqMode::Parametric && preset.bands.len() > MAX_EQ_BANDS {
+                return invalid(format!("at most {} bands are allowed", MAX_EQ_BANDS));
+            }
+            let (min_frequency, max_frequency) = EQ_FREQUENCY_RANGE_HZ;
+            let (min_q, max_q) = EQ_Q_RANGE;
+            for band in &preset.bands {
+                if !(min_frequency..=max_frequency).contains(&band.frequency) {
+                    return invalid(format!(
+                        "frequencies must be between {} and {} Hz, got {}",
+                        min_frequency, max_frequency, band.frequency
+                    ));
+                }
+                if !(min_q..=max_q).contains(&band.q) {
+                    return invalid(format!(
+                        "q must be between {} and {}, got {}",
+                        min_q, max_q, band.q
+                    ));
+                }
+            }
+        }
+
+        if self.active().is_none() {
+            return Err(SettingsError::Invalid(format!(
+                "equalizer.preset: there is no preset `{}`",
+                self.preset
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for AppearanceSettings {
+    fn default() -> Self {
+        Self {
+            theme: String::from(SYSTEM_THEME),
+            window_width: 800.0,
+            window_height: 800.0,
+            visualizer: VisualizerMode::Bars,
+        }
+    }
+}
+
+impl Default for MpdSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: String::from("127.0.0.1"),
+            port: 6600,
+        }
+    }
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: String::from("127.0.0.1"),
+            port: 8080,
+            token: None,
+        }
+    }
+}
+
+impl Settings {
+    /// Directory holding the settings file and user themes, `None` when the platform has no
+    /// config dir
+    pub fn config_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME))
+    }
+
+    /// Path of the settings file
+    pub fn path() -> Option<PathBuf> {
+        Self::config_dir().map(|dir| dir.join(SETTINGS_FILE_NAME))
+    }
+
+    /// Load the settings file from the default location.
+    /// A missing file yields the defaults, an invalid one yields an error describing the problem.
+    pub fn load() -> Result<Self, SettingsError> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Load and validate the settings stored at `path`
+    pub fn load_from(path: &Path) -> Result<Self, SettingsError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| SettingsError::Io(path.to_path_buf(), e.to_string()))?;
+        let settings: Settings = toml::from_str(&contents)
+            .map_err(|e| SettingsError::Parse(path.to_path_buf(), e.to_string()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Load the settings, logging the error and falling back to the defaults when invalid
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            error!("{}; using default settings", e);
+            Self::default()
+        })
+    }
+
+    /// Write the settings back to the default location, creating the config dir if needed
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = Self::path()
+            .ok_or_else(|| SettingsError::Invalid(String::from("no config directory available")))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| SettingsError::Write(dir.to_path_buf(), e.to_string()))?;
+        }
+        let contents =
+            toml::to_string_pretty(self).map_err(|e| SettingsError::Invalid(e.to_string()))?;
+        fs::write(&path, contents).map_err(|e| SettingsError::Write(path.clone(), e.to_string()))
+    }
+
+    /// Check the values that can't be expressed through the types alone. The theme and keymap
+    /// are left to the front-ends that use them.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.library.roots.is_empty() {
+            return Err(SettingsError::Invalid(String::from(
+                "library.roots must contain at least one directory",
+            )));
+        }
+        // e.g. an unmounted drive, which must not throw away every other setting
+        for root in &self.library.roots {
+            if !root.is_dir() {
+                warn!(
+                    "library.roots: `{}` is not a directory, skipping it",
+                    root.display()
+                );
+            }
+        }
+
+        let (min_step, max_step) = SEEK_STEP_RANGE_SECS;
+        if !(min_step..=max_step).contains(&self.playback.seek_step_secs) {
+            return Err(SettingsError::Invalid(format!(
+                "playback.seek_step_secs must be between {} and {}, got {}",
+                min_step, max_step, self.playback.seek_step_secs
+            )));
+        }
+
+        let (min_fade, max_fade) = CROSSFADE_RANGE_SECS;
+        if !(min_fade..=max_fade).contains(&self.playback.crossfade_secs) {
+            return Err(SettingsError::Invalid(format!(
+                "playback.crossfade_secs must be between {} and {}, got {}",
+                min_fade, max_fade, self.playback.crossfade_secs
+            )));
+        }
+
+        let (min_fade, max_fade) = SLEEP_FADE_RANGE_SECS;
+        if !(min_fade..=max_fade).contains(&self.playback.sleep_fade_secs) {
+            return Err(SettingsError::Invalid(format!(
+                "playback.sleep_fade_secs must be between {} and {}, got {}",
+                min_fade, max_fade, self.playback.sleep_fade_secs
+            )));
+        }
+
+        let (min_ramp, max_ramp) = RAMP_RANGE_MS;
+        if !(min_ramp..=max_ramp).contains(&self.playback.ramp_ms) {
+            return Err(SettingsError::Invalid(format!(
+                "playback.ramp_ms must be between {} and {}, got {}",
+                min_ramp, max_ramp, self.playback.ramp_ms
+            )));
+        }
+
+        let (min_preamp, max_preamp) = REPLAYGAIN_PREAMP_RANGE_DB;
+        if !(min_preamp..=max_preamp).contains(&self.playback.replaygain_preamp_db) {
+            return Err(SettingsError::Invalid(format!(
+                "playback.replaygain_preamp_db must be between {} and {}, got {}",
+                min_preamp, max_preamp, self.playback.replaygain_preamp_db
+            )));
+        }
+
+        let (min_speed, max_speed) = OUTPUT_SPEED_RANGE;
+        if !(min_speed..=max_speed).contains(&self.audio.output_speed) {
+            return Err(SettingsError::Invalid(format!(
+                "audio.output_speed must be between {} and {}, got {}",
+                min_speed, max_speed, self.audio.output_speed
+            )));
+        }
+        if self.audio.output == OutputKind::Wav && self.audio.wav_file.is_none() {
+            return Err(SettingsError::Invalid(String::from(
+                "audio.wav_file is required by the wav output",
+            )));
+        }
+
+        self.equalizer.validate()?;
+
+        let (min_balance, max_balance) = BALANCE_RANGE;
+        if !(min_balance..=max_balance).contains(&self.channels.balance) {
+            return Err(SettingsError::Invalid(format!(
+                "channels.balance must be between {} and {}, got {}",
+                min_balance, max_balance, self.channels.balance
+            )));
+        }
+
+        // written so NaN fails too
+        if !(self.appearance.window_width >= MIN_WINDOW_SIZE
+            && self.appearance.window_height >= MIN_WINDOW_SIZE)
+        {
+            return Err(SettingsError::Invalid(format!(
+                "appearance.window_width and appearance.window_height must be at least {}",
+                MIN_WINDOW_SIZE
+            )));
+        }
+
+        if self.mpd.bind_address.parse::<IpAddr>().is_err() {
+            return Err(SettingsError::Invalid(format!(
+                "mpd.bind_address: `{}` is not an IP address",
+                self.mpd.bind_address
+            )));
+        }
+        if self.mpd.port == 0 {
+            return Err(SettingsError::Invalid(String::from(
+                "mpd.port must not be 0",
+            )));
+        }
+
+        if self.http.bind_address.parse::<IpAddr>().is_err() {
+            return Err(SettingsError::Invalid(format!(
+                "http.bind_address: `{}` is not an IP address",
+                self.http.bind_address
+            )));
+        }
+        if self.http.port == 0 {
+            return Err(SettingsError::Invalid(String::from(
+                "http.port must not be 0",
+            )));
+        }
+        if self
+            .http
+            .token
+            .as_ref()
+            .is_some_and(|token| token.is_empty())
+        {
+            return Err(SettingsError::Invalid(String::from(
+                "http.token must not be empty, omit it to disable authentication",
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// `playback.seek_step_secs` as a duration
+    pub fn seek_step(&self) -> Duration {
+        Duration::from_secs(self.playback.seek_step_secs)
+    }
+
+    /// `playback.crossfade_secs` as a duration
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs_f32(self.playback.crossfade_secs)
+    }
+
+    /// `playback.sleep_fade_secs` as a duration
+    pub fn sleep_fade(&self) -> Duration {
+        Duration::from_secs_f32(self.playback.sleep_fade_secs)
+    }
+
+    /// `playback.ramp_ms` as a duration
+    pub fn ramp(&self) -> Duration {
+        Duration::from_millis(self.playback.ramp_ms)
+    }
+
+    /// The `playback.replaygain*` settings
+    pub fn replaygain(&self) -> ReplayGainOptions {
+        ReplayGainOptions {
+            mode: self.playback.replaygain,
+            preamp_db: self.playback.replaygain_preamp_db,
+            prevent_clipping: self.playback.replaygain_prevent_clipping,
+        }
+    }
+}
+
+/// Watch the settings file for modifications on a background thread.
+/// Every successfully validated edit is sent through the returned channel, invalid edits are logged
+/// and the previous settings stay in effect.
+pub fn watch() -> Receiver<Settings> {
+    let (tx, rx) = mpsc::channel::<Settings>();
+
+    if let Some(path) = Settings::path() {
+        thread::spawn(move || {
+            let modified = |path: &Path| -> Option<SystemTime> {
+                fs::metadata(path).and_then(|m| m.modified()).ok()
+            };
+            let mut last_modified = modified(&path);
+
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let current = modified(&path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+
+                let result = if current.is_some() {
+                    Settings::load_from(&path)
+                } else {
+                    Ok(Settings::default())
+                };
+                match result {
+                    Ok(settings) => {
+                        info!("Settings reloaded from {}", path.display());
+                        if tx.send(settings).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("{}; keeping previous settings", e),
+                }
+            }
+        });
+    }
+
+    rx
+}
+
+/// Why settings could not be loaded or saved
+#[derive(Debug, Clone)]
+pub enum SettingsError {
+    /// The file could not be read
+    Io(PathBuf, String),
+    /// The file or its directory could not be written
+    Write(PathBuf, String),
+    /// The file is not valid TOML for `Settings`
+    Parse(PathBuf, String),
+    /// A value is out of range
+    Invalid(String),
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::Io(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            SettingsError::Write(path, e) => {
+                write!(f, "Unable to write {}: {}", path.display(), e)
+            }
+            SettingsError::Parse(path, e) => {
+                write!(f, "Invalid settings in {}: {}", path.display(), e)
+            }
+            SettingsError::Invalid(e) => write!(f, "Invalid settings: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message of the validation error, panics when the settings are valid
+    fn invalid(change: impl FnOnce(&mut Settings)) -> String {
+        let mut settings = Settings::default();
+        change(&mut settings);
+        match settings.validate() {
+            Err(SettingsError::Invalid(message)) => message,
+            result => panic!("expected an invalid setting, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        Settings::default().validate().unwrap();
+    }
+
+    #[test]
+    fn missing_roots_are_skipped() {
+        let mut settings = Settings::default();
+        settings.library.roots = vec![PathBuf::from("/nonexistent/music-player-test")];
+        settings.validate().unwrap();
+
+        let message = invalid(|settings| settings.library.roots.clear());
+        assert!(message.starts_with("library.roots"), "{}", message);
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        type Change = fn(&mut Settings);
+        let cases: [(&str, Change); 8] = [
+            ("playback.seek_step_secs", |s| s.playback.seek_step_secs = 0),
+            ("playback.crossfade_secs", |s| {
+                s.playback.crossfade_secs = 1000.0
+            }),
+            ("playback.crossfade_secs", |s| {
+                s.playback.crossfade_secs = f32::NAN
+            }),
+            ("playback.ramp_ms", |s| s.playback.ramp_ms = u64::MAX),
+            ("audio.output_speed", |s| s.audio.output_speed = 0.0),
+            ("channels.balance", |s| s.channels.balance = -1.5),
+            ("mpd.bind_address", |s| {
+                s.mpd.bind_address = String::from("localhost")
+            }),
+            ("http.token", |s| s.http.token = Some(String::new())),
+        ];
+        for (name, change) in cases {
+            let message = invalid(change);
+            assert!(message.starts_with(name), "{}: {}", name, message);
+        }
+    }
+
+    #[test]
+    fn window_size_rejects_nan() {
+        for size in [f32::NAN, 0.0, MIN_WINDOW_SIZE - 1.0] {
+            let message = invalid(|settings| settings.appearance.window_height = size);
+            assert!(
+                message.starts_with("appearance.window_width"),
+                "{}",
+                message
+            );
+        }
+        let mut settings = Settings::default();
+        settings.appearance.window_width = MIN_WINDOW_SIZE;
+        settings.validate().unwrap();
+    }
+
+    #[test]
+    fn wav_output_needs_a_file() {
+        let message = invalid(|settings| settings.audio.output = OutputKind::Wav);
+        assert!(message.starts_with("audio.wav_file"), "{}", message);
+    }
+
+    #[test]
+    fn equalizer_presets_are_checked() {
+        let preset = |name: &str| EqPreset {
+            name: String::from(name),
+            preamp_db: 0.0,
+            mode: EqMode::Graphic,
+            gains_db: [0.0; 10],
+            bands: Vec::new(),
+        };
+        let message = invalid(|settings| settings.equalizer.presets = vec![preset("Rock")]);
+        assert!(message.contains("already taken"), "{}", message);
+        let message = invalid(|settings| {
+            let mut loud = preset("Loud");
+            loud.gains_db[0] = 100.0;
+            settings.equalizer.presets = vec![loud];
+        });
+        assert!(message.contains("gains must be between"), "{}", message);
+        let message = invalid(|settings| settings.equalizer.preset = String::from("Missing"));
+        assert!(message.starts_with("equalizer.preset"), "{}", message);
+    }
+
+    #[test]
+    fn toml_round_trips() {
+        let mut settings = Settings::default();
+        settings.playback.crossfade_secs = 3.0;
+        settings.channels.mono = true;
+        settings.http.token = Some(String::from("secret"));
+        let parsed: Settings = toml::from_str(&toml::to_string_pretty(&settings).unwrap()).unwrap();
+        assert_eq!(parsed, settings);
+        assert!(toml::from_str::<Settings>("[playback]\nunknown = 1\n").is_err());
+    }
+}
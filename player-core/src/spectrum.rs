@@ -0,0 +1,288 @@
+use std::f64::consts::PI;
+use std::sync::Mutex;
+
+/// Frames analyzed at once, about 46 ms at 44.1 kHz
+const WINDOW_FRAMES: usize = 2048;
+/// Frames the DSP stage collects before handing them over, so it rarely touches the lock
+pub(crate) const CHUNK_FRAMES: usize = 256;
+/// Level at the bottom of the bands and meters
+const FLOOR_DB: f32 = -60.0;
+/// Lowest and highest frequency of the bands
+const FREQUENCY_RANGE_HZ: (f32, f32) = (20.0, 20_000.0);
+
+/// Peak and RMS level of one channel, 1.0 is full scale
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Level {
+    /// Largest sample
+    pub peak: f32,
+    /// Root mean square of the samples
+    pub rms: f32,
+}
+
+/// The last few milliseconds played, analyzed for visualizers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Spectrum {
+    /// Magnitude of logarithmically spaced bands from 20 Hz to 20 kHz, on the scale of [`meter`]
+    pub bands: Vec<f32>,
+    /// The analyzed frames mixed down to mono, oldest first
+    pub waveform: Vec<f32>,
+    /// Level of the left and right channel, mono tracks show on both
+    pub levels: [Level; 2],
+}
+
+/// Position of a linear level on a meter going from 0.0 at -60 dB to 1.0 at full scale
+pub fn meter(level: f32) -> f32 {
+    if level <= 0.0 {
+        return 0.0;
+    }
+    ((20.0 * level.log10() - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// The frames last handed to the output, written by the DSP stage and read by front-ends
+#[derive(Default)]
+pub(crate) struct SampleTap {
+    buffer: Mutex<TapBuffer>,
+}
+
+#[derive(Default)]
+struct TapBuffer {
+    /// Ring of the last `WINDOW_FRAMES` frames, left and right
+    frames: Vec<[f32; 2]>,
+    /// Where the next frame goes, which is also the oldest frame
+    next: usize,
+    sample_rate: u32,
+}
+
+impl SampleTap {
+    /// Add frames that were played. They are dropped while a front-end reads, so the audio
+    /// thread never waits.
+    pub(crate) fn write(&self, frames: &[[f32; 2]], sample_rate: u32) {
+        let Ok(mut buffer) = self.buffer.try_lock() else {
+            return;
+        };
+        if buffer.frames.is_empty() {
+            buffer.frames = vec![[0.0; 2]; WINDOW_FRAMES];
+        }
+        buffer.sample_rate = sample_rate;
+        for &frame in frames {
+            let next = buffer.next;
+            buffer.frames[next] = frame;
+            buffer.next = (next + 1) % WINDOW_FRAMES;
+        }
+    }
+
+    /// Forget the frames, e.g. after stopping
+    pub(crate) fn clear(&self) {
+        *self.buffer.lock().unwrap() = TapBuffer::default();
+    }
+
+    /// Analyze the last frames into `bands` bands, all zero when nothing was played
+    pub(crate) fn spectrum(&self, bands: usize) -> Spectrum {
+        let (frames, sample_rate) = {
+            let buffer = self.buffer.lock().unwrap();
+            let (newest, oldest) = buffer.frames.split_at(buffer.next);
+            (
+                oldest.iter().chain(newest).copied().collect::<Vec<_>>(),
+                buffer.sample_rate,
+            )
+        };
+        if frames.is_empty() {
+            return Spectrum {
+                bands: vec![0.0; bands],
+                ..Spectrum::default()
+            };
+        }
+        analyze(&frames, sample_rate, bands)
+    }
+}
+
+/// Levels, waveform and spectrum of `frames`, whose length is a power of two
+fn analyze(frames: &[[f32; 2]], sample_rate: u32, bands: usize) -> Spectrum {
+    let len = frames.len();
+    let mut levels = [Level::default(); 2];
+    for (channel, level) in levels.iter_mut().enumerate() {
+        let mut sum = 0.0;
+        for frame in frames {
+            level.peak = level.peak.max(frame[channel].abs());
+            sum += frame[channel] * frame[channel];
+        }
+        level.rms = (sum / len as f32).sqrt();
+    }
+    let waveform: Vec<f32> = frames
+        .iter()
+        .map(|[left, right]| (left + right) / 2.0)
+        .collect();
+
+    // Hann window, whose sum is half the length, scaled so a full scale sine reads 1.0
+    let mut re: Vec<f32> = waveform
+        .iter()
+        .enumerate()
+        .map(|(ix, sample)| {
+            let window = 0.5 - 0.5 * (2.0 * PI * ix as f64 / len as f64).cos();
+            sample * window as f32
+        })
+        .collect();
+    let mut im = vec![0.0; len];
+    fft(&mut re, &mut im);
+    let scale = 4.0 / len as f32;
+
+    let bin_hz = sample_rate as f32 / len as f32;
+    let (lowest, highest) = FREQUENCY_RANGE_HZ;
+    let band_edge = |band: usize| lowest * (highest / lowest).powf(band as f32 / bands as f32);
+    let bands = (0..bands)
+        .map(|band| {
+            let first = (band_edge(band) / bin_hz).floor() as usize;
+            // every band gets at least one bin, the lowest ones share them
+            let last = ((band_edge(band + 1) / bin_hz).ceil() as usize).max(first + 1);
+            let magnitude = (first..last.min(len / 2))
+                .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt())
+                .fold(0.0, f32::max);
+            meter(magnitude * scale)
+        })
+        .collect();
+
+    Spectrum {
+        bands,
+        waveform,
+        levels,
+    }
+}
+
+/// In-place radix-2 FFT of a signal whose length is a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let len = re.len();
+    // bit reversed order
+    let mut target = 0;
+    for ix in 1..len {
+        let mut bit = len >> 1;
+        while target & bit != 0 {
+            target ^= bit;
+            bit >>= 1;
+        }
+        target |= bit;
+        if ix < target {
+            re.swap(ix, target);
+            im.swap(ix, target);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let (sin, cos) = (-2.0 * PI / size as f64).sin_cos();
+        for start in (0..len).step_by(size) {
+            let (mut w_re, mut w_im) = (1.0f64, 0.0f64);
+            for k in 0..size / 2 {
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * w_re as f32 - im[b] * w_im as f32;
+                let t_im = re[b] * w_im as f32 + im[b] * w_re as f32;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+                (w_re, w_im) = (w_re * cos - w_im * sin, w_re * sin + w_im * cos);
+            }
+        }
+        size <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// `WINDOW_FRAMES` frames of a sine at `hz` with the `amplitude`, on both channels
+    fn sine(hz: f32, amplitude: f32) -> Vec<[f32; 2]> {
+        (0..WINDOW_FRAMES)
+            .map(|frame| {
+                let phase = 2.0 * std::f32::consts::PI * hz * frame as f32 / SAMPLE_RATE as f32;
+                [amplitude * phase.sin(); 2]
+            })
+            .collect()
+    }
+
+    fn db(level: f32) -> f32 {
+        20.0 * level.log10()
+    }
+
+    #[test]
+    fn fft_finds_the_bin_of_a_sine() {
+        let len = 64;
+        let mut re = (0..len)
+            .map(|ix| (2.0 * std::f32::consts::PI * 5.0 * ix as f32 / len as f32).sin())
+            .collect::<Vec<_>>();
+        let mut im = vec![0.0; len];
+        fft(&mut re, &mut im);
+
+        let magnitudes = re
+            .iter()
+            .zip(&im)
+            .map(|(re, im)| (re * re + im * im).sqrt())
+            .collect::<Vec<_>>();
+        // half of the energy in the bin, the other half in its mirror image
+        for (bin, magnitude) in magnitudes.iter().enumerate() {
+            let expected = if bin == 5 || bin == len - 5 {
+                32.0
+            } else {
+                0.0
+            };
+            assert!((magnitude - expected).abs() < 1e-3, "{} {}", bin, magnitude);
+        }
+    }
+
+    #[test]
+    fn meter_spans_sixty_decibels() {
+        assert_eq!(meter(1.0), 1.0);
+        assert_eq!(meter(2.0), 1.0);
+        assert!((meter(10f32.powf(-30.0 / 20.0)) - 0.5).abs() < 1e-6);
+        assert_eq!(meter(0.001), 0.0);
+        assert_eq!(meter(0.0), 0.0);
+    }
+
+    #[test]
+    fn a_tone_peaks_in_its_band() {
+        let tap = SampleTap::default();
+        tap.write(&sine(1000.0, 1.0), SAMPLE_RATE);
+        let spectrum = tap.spectrum(32);
+
+        // 1 kHz is at log(1000 / 20) / log(20000 / 20) = 0.57 of the range
+        let loudest = (0..32)
+            .max_by(|&a, &b| spectrum.bands[a].total_cmp(&spectrum.bands[b]))
+            .unwrap();
+        assert_eq!(loudest, 18, "{:?}", spectrum.bands);
+        // full scale, less the window's loss between two bins
+        assert!(spectrum.bands[18] > meter(0.8), "{:?}", spectrum.bands);
+        // the window keeps the tone out of the bands further away
+        for band in (0..14).chain(23..32) {
+            assert!(spectrum.bands[band] < 0.5, "{} {:?}", band, spectrum.bands);
+        }
+    }
+
+    #[test]
+    fn a_full_scale_sine_has_its_peak_and_rms_level() {
+        let tap = SampleTap::default();
+        tap.write(&sine(1000.0, 1.0), SAMPLE_RATE);
+        for level in tap.spectrum(8).levels {
+            assert!((level.peak - 1.0).abs() < 1e-3, "{:?}", level);
+            assert!((db(level.rms) + 3.01).abs() < 0.05, "{:?}", level);
+        }
+
+        tap.write(&sine(1000.0, 0.5), SAMPLE_RATE);
+        let level = tap.spectrum(8).levels[0];
+        assert!((db(level.peak) + 6.02).abs() < 0.05, "{:?}", level);
+        assert!((db(level.rms) + 9.03).abs() < 0.05, "{:?}", level);
+    }
+
+    #[test]
+    fn nothing_played_reads_silence() {
+        let tap = SampleTap::default();
+        let spectrum = tap.spectrum(16);
+        assert_eq!(spectrum.bands, vec![0.0; 16]);
+        assert_eq!(spectrum.levels, [Level::default(); 2]);
+
+        tap.write(&sine(1000.0, 1.0), SAMPLE_RATE);
+        tap.clear();
+        assert_eq!(tap.spectrum(16).bands, vec![0.0; 16]);
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use log::error;
+
+use crate::player::{LoopMode, PlaybackState};
+
+const STATE_DIR_NAME: &str = "music-player";
+const STATE_FILE_NAME: &str = "state.json";
+
+/// What the player was doing when it was shut down, so the next start picks up from there
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedState {
+    /// The tracks of the queue, in the order they were added
+    pub queue: Vec<PathBuf>,
+    /// Index of the current track in `queue`
+    pub queue_position: Option<usize>,
+    /// Position in the current track in milliseconds
+    pub position_ms: u64,
+    /// Whether the track was playing or paused
+    pub state: PlaybackState,
+    /// 0.0 - 1.0
+    pub volume: f32,
+    /// Whether the queue played in random order
+    pub shuffle: bool,
+    /// What happens at the end of a track
+    pub loop_mode: LoopMode,
+}
+
+impl SavedState {
+    /// `$XDG_STATE_HOME/music-player/state.json` on Linux, the local data dir elsewhere
+    pub fn path() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join(STATE_DIR_NAME).join(STATE_FILE_NAME))
+    }
+
+    /// The state saved by the last run, `None` when there is none or it can't be read
+    pub fn load() -> Option<Self> {
+        let path = Self::path()?;
+        let contents = fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&contents)
+            .inspect_err(|e| error!("Ignoring invalid state file {}: {}", path.display(), e))
+            .ok()
+    }
+
+    /// Write the state to `path`, creating its directory
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no state directory available")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+    }
+}
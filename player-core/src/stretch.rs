@@ -0,0 +1,432 @@
+/// Length of the overlapping segments in seconds
+const WINDOW_SECS: f32 = 0.04;
+/// How far a segment may be moved to line up with the previous one, in seconds
+const SEEK_SECS: f32 = 0.012;
+/// Only every n-th frame and candidate is correlated, the best candidate is refined afterwards
+const CORRELATION_STEP: usize = 4;
+
+/// Changes the tempo without changing the pitch (WSOLA): Hann windowed segments are taken from
+/// the input `rate` times faster than they are laid out, each one moved a little to continue the
+/// waveform of the previous one.
+///
+/// Starting and finishing are seamless: the first segment is completed by a virtual one right
+/// before it, and after `finish` the input continues exactly where the last segment left off.
+pub(crate) struct TimeStretch {
+    channels: usize,
+    window: Vec<f32>,
+    hop: usize,
+    seek: usize,
+    /// Input frames per output frame
+    rate: f64,
+    /// Buffered input frames, interleaved
+    input: Vec<f32>,
+    /// Nominal start of the next segment, in frames of `input`
+    position: f64,
+    /// Where the last segment would continue, `None` before the first one
+    natural: Option<usize>,
+    /// Overlap-add of the segments, the first `hop` frames are complete after each segment
+    output: Vec<f32>,
+    /// Frames of the complete part handed out
+    emitted: usize,
+    /// Once finished, the frame of `input` handed out next
+    drain: Option<usize>,
+}
+
+impl TimeStretch {
+    pub(crate) fn new(channels: usize, sample_rate: u32, rate: f64) -> Self {
+        let hop = ((WINDOW_SECS * sample_rate as f32) as usize / 2).max(1);
+        // periodic Hann window, two halves overlapping by `hop` add up to 1
+        let window = (0..2 * hop)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / hop as f32).cos())
+            .collect();
+        Self {
+            channels,
+            window,
+            hop,
+            seek: (SEEK_SECS * sample_rate as f32) as usize,
+            rate,
+            input: Vec::new(),
+            position: 0.0,
+            natural: None,
+            output: vec![0.0; 2 * hop * channels],
+            emitted: hop,
+            drain: None,
+        }
+    }
+
+    /// Change the tempo, a finishing stretch starts over where it is
+    pub(crate) fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        if let Some(drain) = self.drain.take() {
+            self.input.drain(..drain * self.channels);
+            self.restart();
+        }
+    }
+
+    /// Stop stretching, once the buffered input is handed out frames pass through unchanged
+    pub(crate) fn finish(&mut self) {
+        if self.drain.is_none() {
+            self.drain = Some(self.natural.unwrap_or(0));
+        }
+    }
+
+    /// Whether `finish` was called and everything buffered was handed out
+    pub(crate) fn is_drained(&self) -> bool {
+        self.drain
+            .is_some_and(|drain| drain * self.channels >= self.input.len())
+    }
+
+    /// Forget the buffered input, e.g. after seeking
+    pub(crate) fn clear(&mut self) {
+        self.input.clear();
+        self.drain = None;
+        self.restart();
+    }
+
+    fn restart(&mut self) {
+        self.position = 0.0;
+        self.natural = None;
+        self.emitted = self.hop;
+    }
+
+    /// The next output frame, pulling input frames from `read`. False at the end of the input.
+    pub(crate) fn next_frame(
+        &mut self,
+        frame: &mut [f32],
+        read: &mut impl FnMut(&mut [f32]) -> bool,
+    ) -> bool {
+        loop {
+            if let Some(drain) = self.drain {
+                if (drain + 1) * self.channels <= self.input.len() {
+                    let start = drain * self.channels;
+                    frame.copy_from_slice(&self.input[start..start + self.channels]);
+                    self.drain = Some(drain + 1);
+                    return true;
+                }
+                return read(frame);
+            }
+            if self.emitted < self.hop {
+                let start = self.emitted * self.channels;
+                frame.copy_from_slice(&self.output[start..start + self.channels]);
+                self.emitted += 1;
+                return true;
+            }
+            if !self.next_segment(read) {
+                // the input ran out, what is left plays unstretched
+                self.finish();
+            }
+        }
+    }
+
+    /// Add the next segment, false when there is not enough input left
+    fn next_segment(&mut self, read: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        let (hop, channels) = (self.hop, self.channels);
+
+        // the second half of the last segment moves to the front
+        self.output.copy_within(hop * channels.., 0);
+        self.output[hop * channels..].fill(0.0);
+
+        let start = match self.natural {
+            None => {
+                if !self.fill(2 * hop, read) {
+                    return false;
+                }
+                // a virtual segment one hop earlier completes the first half of the first one
+                for i in 0..hop {
+                    for c in 0..channels {
+                        self.output[i * channels + c] =
+                            self.input[i * channels + c] * self.window[hop + i];
+                    }
+                }
+                0
+            }
+            Some(natural) => {
+                let nominal = self.position.round() as usize;
+                let first = nominal.saturating_sub(self.seek);
+                let last = nominal + self.seek;
+                if !self.fill(last.max(natural) + 2 * hop, read) {
+                    return false;
+                }
+                self.best_match(natural, first, last)
+            }
+        };
+
+        for i in 0..2 * hop {
+            let weight = self.window[i];
+            for c in 0..channels {
+                self.output[i * channels + c] += self.input[(start + i) * channels + c] * weight;
+            }
+        }
+        self.emitted = 0;
+        self.position += hop as f64 * self.rate;
+
+        // input before both the next candidates and the next natural continuation is done with
+        let natural = start + hop;
+        let used = (self.position as usize)
+            .saturating_sub(self.seek)
+            .min(natural);
+        self.input.drain(..used * channels);
+        self.position -= used as f64;
+        self.natural = Some(natural - used);
+        true
+    }
+
+    /// Read input until `frames` are buffered
+    fn fill(&mut self, frames: usize, read: &mut impl FnMut(&mut [f32]) -> bool) -> bool {
+        let mut frame = vec![0.0; self.channels];
+        while self.input.len() < frames * self.channels {
+            if !read(&mut frame) {
+                return false;
+            }
+            self.input.extend_from_slice(&frame);
+        }
+        true
+    }
+
+    /// The start between `first` and `last` whose waveform is most like the one at `natural`
+    fn best_match(&self, natural: usize, first: usize, last: usize) -> usize {
+        let best_of = |candidates: &mut dyn Iterator<Item = usize>| {
+            candidates
+                .map(|candidate| (candidate, self.similarity(natural, candidate)))
+                .fold((natural, f32::MIN), |best, next| {
+                    if next.1 > best.1 { next } else { best }
+                })
+                .0
+        };
+        let coarse = best_of(&mut (first..=last).step_by(CORRELATION_STEP));
+        let near = coarse.saturating_sub(CORRELATION_STEP - 1).max(first)
+            ..=(coarse + CORRELATION_STEP - 1).min(last);
+        best_of(&mut near.into_iter())
+    }
+
+    /// Normalized correlation of the first half segments at `a` and `b`, mixed to mono
+    fn similarity(&self, a: usize, b: usize) -> f32 {
+        let mono = |frame: usize| -> f32 {
+            self.input[frame * self.channels..(frame + 1) * self.channels]
+                .iter()
+                .sum()
+        };
+        let (mut product, mut energy) = (0.0, 0.0);
+        for i in (0..self.hop).step_by(CORRELATION_STEP) {
+            let candidate = mono(b + i);
+            product += mono(a + i) * candidate;
+            energy += candidate * candidate;
+        }
+        product / energy.sqrt().max(f32::EPSILON)
+    }
+}
+
+/// Changes the pitch together with the tempo by reading the input `ratio` times faster, with
+/// cubic interpolation between the frames
+pub(crate) struct Resampler {
+    channels: usize,
+    ratio: f64,
+    /// Position between `history[1]` and `history[2]`
+    phase: f64,
+    /// Four frames around the position, interleaved
+    history: Vec<f32>,
+    primed: bool,
+}
+
+impl Resampler {
+    pub(crate) fn new(channels: usize, ratio: f64) -> Self {
+        Self {
+            channels,
+            ratio,
+            phase: 0.0,
+            history: vec![0.0; 4 * channels],
+            primed: false,
+        }
+    }
+
+    pub(crate) fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio;
+    }
+
+    /// Forget the buffered input, e.g. after seeking
+    pub(crate) fn clear(&mut self) {
+        self.phase = 0.0;
+        self.primed = false;
+    }
+
+    /// The next output frame, pulling input frames from `read`. False at the end of the input.
+    pub(crate) fn next_frame(
+        &mut self,
+        frame: &mut [f32],
+        read: &mut impl FnMut(&mut [f32]) -> bool,
+    ) -> bool {
+        let channels = self.channels;
+        if !self.primed {
+            // the first output frame is the next input frame itself
+            if !read(&mut self.history[channels..2 * channels]) {
+                return false;
+            }
+            self.history.copy_within(channels..2 * channels, 0);
+            for i in 2..4 {
+                if !read(&mut self.history[i * channels..(i + 1) * channels]) {
+                    return false;
+                }
+            }
+            self.primed = true;
+        }
+        while self.phase >= 1.0 {
+            self.history.copy_within(channels.., 0);
+            if !read(&mut self.history[3 * channels..]) {
+                return false;
+            }
+            self.phase -= 1.0;
+        }
+
+        let t = self.phase as f32;
+        for (c, sample) in frame.iter_mut().enumerate() {
+            let [x0, x1, x2, x3] = [0, 1, 2, 3].map(|i| self.history[i * channels + c]);
+            // Catmull-Rom spline through the four frames
+            *sample = x1
+                + 0.5
+                    * t
+                    * (x2 - x0
+                        + t * (2.0 * x0 - 5.0 * x1 + 4.0 * x2 - x3
+                            + t * (3.0 * (x1 - x2) + x3 - x0)));
+        }
+        self.phase += self.ratio;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Two seconds of a mono sine
+    fn sine(frequency: f32) -> Vec<f32> {
+        (0..2 * SAMPLE_RATE)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                (2.0 * std::f32::consts::PI * frequency * t).sin() * 0.5
+            })
+            .collect()
+    }
+
+    /// Reads the mono frames of `input` one after the other
+    fn reader(input: &[f32]) -> impl FnMut(&mut [f32]) -> bool {
+        let mut samples = input.iter();
+        move |frame| match samples.next() {
+            Some(&sample) => {
+                frame[0] = sample;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run `input` through `next_frame` until it ends
+    fn process(
+        input: &[f32],
+        mut next_frame: impl FnMut(&mut [f32], &mut dyn FnMut(&mut [f32]) -> bool) -> bool,
+    ) -> Vec<f32> {
+        let mut read = reader(input);
+        let mut output = Vec::new();
+        let mut frame = [0.0];
+        while next_frame(&mut frame, &mut read) {
+            output.push(frame[0]);
+        }
+        output
+    }
+
+    /// Frequency of a sine, from the rising zero crossings of its middle half
+    fn frequency(samples: &[f32]) -> f32 {
+        let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+        let crossings = middle
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * SAMPLE_RATE as f32 / middle.len() as f32
+    }
+
+    fn stretch(input: &[f32], rate: f64) -> Vec<f32> {
+        let mut stretch = TimeStretch::new(1, SAMPLE_RATE, rate);
+        process(input, |frame, mut read| {
+            stretch.next_frame(frame, &mut read)
+        })
+    }
+
+    fn resample(input: &[f32], ratio: f64) -> Vec<f32> {
+        let mut resampler = Resampler::new(1, ratio);
+        process(input, |frame, mut read| {
+            resampler.next_frame(frame, &mut read)
+        })
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= expected * tolerance,
+            "{} instead of {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn stretch_changes_the_length_but_not_the_pitch() {
+        let input = sine(440.0);
+        for rate in [0.5, 0.8, 1.25, 2.0] {
+            let output = stretch(&input, rate);
+            // what is buffered when the input ends, less than a window and the seek range, plays
+            // unstretched
+            let expected = input.len() as f32 / rate as f32;
+            let tail = (WINDOW_SECS + SEEK_SECS) * SAMPLE_RATE as f32;
+            assert!(
+                (output.len() as f32 - expected).abs() < tail,
+                "{} frames instead of {} at rate {}",
+                output.len(),
+                expected,
+                rate
+            );
+            assert_close(frequency(&output), 440.0, 0.02);
+        }
+    }
+
+    #[test]
+    fn stretch_at_rate_1_keeps_the_samples() {
+        let input = sine(440.0);
+        let output = stretch(&input, 1.0);
+        assert_eq!(output.len(), input.len());
+        let error = input
+            .iter()
+            .zip(&output)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-4, "{}", error);
+    }
+
+    #[test]
+    fn finished_stretch_passes_the_rest_through() {
+        let input = sine(440.0);
+        let mut stretch = TimeStretch::new(1, SAMPLE_RATE, 2.0);
+        let mut read = reader(&input);
+        let mut frame = [0.0];
+        for _ in 0..SAMPLE_RATE / 2 {
+            assert!(stretch.next_frame(&mut frame, &mut read));
+        }
+        stretch.finish();
+        let mut rest = 0;
+        while stretch.next_frame(&mut frame, &mut read) {
+            rest += 1;
+        }
+        assert!(stretch.is_drained());
+        // half a second of output took about a second of input, the other second follows as is
+        assert_close(rest as f32, SAMPLE_RATE as f32, 0.05);
+    }
+
+    #[test]
+    fn resampler_changes_length_and_pitch_together() {
+        let input = sine(440.0);
+        for ratio in [0.5, 2f64.powf(7.0 / 12.0), 2.0] {
+            let output = resample(&input, ratio);
+            assert_close(output.len() as f32, input.len() as f32 / ratio as f32, 0.01);
+            assert_close(frequency(&output), 440.0 * ratio as f32, 0.02);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+const STATE_DIR_NAME: &str = "music-player";
+const TRACKS_FILE_NAME: &str = "tracks.json";
+
+/// What is remembered about one track
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackState {
+    /// Playback speed, see `Player::set_speed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Pitch shift in semitones, see `Player::set_pitch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch_semitones: Option<f32>,
+    /// Named A–B loops, see `Player::save_loop`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub loops: Vec<LoopRegion>,
+}
+
+/// A section of a track saved under a name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    /// Unique within the track
+    pub name: String,
+    /// Where the loop starts, in milliseconds
+    pub start_ms: u64,
+    /// Where the loop jumps back to the start, in milliseconds
+    pub end_ms: u64,
+}
+
+/// Settings made for single tracks, kept between runs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrackStates {
+    tracks: HashMap<PathBuf, TrackState>,
+}
+
+impl TrackStates {
+    /// `$XDG_STATE_HOME/music-player/tracks.json` on Linux, the local data dir elsewhere
+    pub fn path() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join(STATE_DIR_NAME).join(TRACKS_FILE_NAME))
+    }
+
+    /// The states saved by an earlier run, empty when there are none or they can't be read
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&contents)
+            .inspect_err(|e| error!("Ignoring invalid track states {}: {}", path.display(), e))
+            .unwrap_or_default()
+    }
+
+    /// Write the states to `path`, creating its directory
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no state directory available")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// What is remembered about `track`, the default when nothing is
+    pub fn get(&self, track: &Path) -> TrackState {
+        self.tracks.get(track).cloned().unwrap_or_default()
+    }
+
+    /// Remember `state` for `track`, forgetting the track when there is nothing left to remember
+    pub fn set(&mut self, track: &Path, state: TrackState) {
+        if state == TrackState::default() {
+            self.tracks.remove(track);
+        } else {
+            self.tracks.insert(track.to_path_buf(), state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str, start_ms: u64, end_ms: u64) -> LoopRegion {
+        LoopRegion {
+            name: name.to_string(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    #[test]
+    fn loops_survive_saving_and_loading() {
+        let track = Path::new("/music/song.mp3");
+        let mut states = TrackStates::default();
+        let state = TrackState {
+            loops: vec![region("Intro", 0, 15_000), region("Solo", 95_500, 120_250)],
+            ..TrackState::default()
+        };
+        states.set(track, state.clone());
+
+        let json = serde_json::to_string(&states).unwrap();
+        let loaded: TrackStates = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get(track), state);
+        assert_eq!(
+            loaded.get(Path::new("/music/other.mp3")),
+            TrackState::default()
+        );
+    }
+
+    #[test]
+    fn tracks_without_state_are_forgotten() {
+        let track = Path::new("/music/song.mp3");
+        let mut states = TrackStates::default();
+        states.set(
+            track,
+            TrackState {
+                loops: vec![region("Loop 1", 1000, 2000)],
+                ..TrackState::default()
+            },
+        );
+        states.set(track, TrackState::default());
+        assert_eq!(serde_json::to_string(&states).unwrap(), r#"{"tracks":{}}"#);
+    }
+}
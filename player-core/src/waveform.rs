@@ -0,0 +1,152 @@
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Columns of an overview, whatever the length of the track
+const COLUMNS: usize = 256;
+/// Frames whose minimum and maximum are collected while decoding, before they are merged into
+/// columns
+const BLOCK_FRAMES: usize = 256;
+/// Value of a full scale sample in a column
+const FULL_SCALE: f32 = 127.0;
+
+/// Lowest and highest sample of a whole track in a few hundred columns, for drawing it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+    /// Minimum and maximum of every column, -127 to 127 for full scale
+    columns: Vec<[i8; 2]>,
+}
+
+/// Why the overview of a track could not be computed
+#[derive(Debug, Clone)]
+pub enum WaveformError {
+    /// The file could not be opened
+    FileError(String),
+    /// The file is not a supported audio format
+    DecodeError(String),
+}
+
+impl std::fmt::Display for WaveformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaveformError::FileError(e) => write!(f, "File error: {}", e),
+            WaveformError::DecodeError(e) => write!(f, "Decode error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WaveformError {}
+
+impl Waveform {
+    /// Decode the file at `path` and collect its overview
+    pub fn of(path: &Path) -> Result<Self, WaveformError> {
+        let file = File::open(path).map_err(|e| WaveformError::FileError(e.to_string()))?;
+        let source = Decoder::new(BufReader::new(file))
+            .map_err(|e| WaveformError::DecodeError(e.to_string()))?;
+        let channels = source.channels();
+        Ok(Self::from_samples(source, channels))
+    }
+
+    /// The overview of interleaved `samples` with `channels` channels
+    fn from_samples(samples: impl Iterator<Item = f32>, channels: u16) -> Self {
+        let block_samples = BLOCK_FRAMES * channels.max(1) as usize;
+
+        // every channel goes into the same block, the overview shows the loudest one
+        let mut blocks = Vec::new();
+        let mut block = [0.0f32; 2];
+        let mut in_block = 0;
+        for sample in samples {
+            block = [block[0].min(sample), block[1].max(sample)];
+            in_block += 1;
+            if in_block == block_samples {
+                blocks.push(block);
+                block = [0.0; 2];
+                in_block = 0;
+            }
+        }
+        if in_block > 0 {
+            blocks.push(block);
+        }
+
+        let columns = COLUMNS.min(blocks.len());
+        let columns = (0..columns)
+            .map(|column| {
+                let range = column * blocks.len() / columns..(column + 1) * blocks.len() / columns;
+                let [min, max] = blocks[range].iter().fold([0.0f32; 2], |[min, max], block| {
+                    [min.min(block[0]), max.max(block[1])]
+                });
+                [quantize(min), quantize(max)]
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// Minimum and maximum of every column, from -1.0 to 1.0, in the order they play
+    pub fn columns(&self) -> impl ExactSizeIterator<Item = (f32, f32)> + '_ {
+        self.columns
+            .iter()
+            .map(|&[min, max]| (min as f32 / FULL_SCALE, max as f32 / FULL_SCALE))
+    }
+
+    /// Whether the track had no samples at all
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+}
+
+fn quantize(sample: f32) -> i8 {
+    (sample.clamp(-1.0, 1.0) * FULL_SCALE).round() as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `blocks` blocks of stereo frames, the left channel of block `n` swinging between -n and
+    /// n hundredths
+    fn blocks(blocks: usize) -> impl Iterator<Item = f32> {
+        (0..blocks).flat_map(|block| {
+            let level = block as f32 / 100.0;
+            (0..BLOCK_FRAMES).flat_map(move |frame| {
+                let left = if frame % 2 == 0 { level } else { -level };
+                [left, 0.0]
+            })
+        })
+    }
+
+    #[test]
+    fn short_tracks_get_a_column_per_block() {
+        let waveform = Waveform::from_samples(blocks(10), 2);
+        assert_eq!(waveform.columns().len(), 10);
+        for (block, (min, max)) in waveform.columns().enumerate() {
+            let level = (block as f32 / 100.0 * FULL_SCALE).round() / FULL_SCALE;
+            assert_eq!((min, max), (-level, level));
+        }
+    }
+
+    #[test]
+    fn long_tracks_merge_blocks_into_columns() {
+        // two blocks per column, each column keeps the louder one
+        let waveform = Waveform::from_samples(blocks(2 * COLUMNS).map(|s| s / 10.0), 2);
+        assert_eq!(waveform.columns().len(), COLUMNS);
+        for (column, (min, max)) in waveform.columns().enumerate() {
+            let level = ((2 * column + 1) as f32 / 1000.0 * FULL_SCALE).round() / FULL_SCALE;
+            assert_eq!((min, max), (-level, level), "{}", column);
+        }
+
+        // blocks that don't divide evenly still all end up in a column
+        let waveform = Waveform::from_samples(blocks(COLUMNS + 1), 2);
+        assert_eq!(waveform.columns().len(), COLUMNS);
+        let (min, max) = waveform.columns().last().unwrap();
+        assert_eq!((min, max), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn samples_are_clamped_to_full_scale() {
+        let waveform = Waveform::from_samples([1.5, -3.0, 0.25].into_iter(), 1);
+        assert_eq!(waveform.columns().collect::<Vec<_>>(), [(-1.0, 1.0)]);
+        assert!(Waveform::from_samples(std::iter::empty(), 2).is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! The audio thread driven through `AudioManager::with_output`, on the null and WAV outputs so no
+//! sound card is needed.
+
+mod common;
+
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use hound::WavReader;
+
+use player_core::audio_manager::{AudioEvent, AudioManager};
+use player_core::output::{AudioOutput, NullOutput, WavOutput};
+use player_core::replaygain::ReplayGain;
+use player_core::settings::Settings;
+
+use common::{SAMPLE_RATE, TempDir};
+
+/// The outputs consume audio this many times faster than real time
+const SPEED: f32 = 20.0;
+/// Length of the test track
+const TRACK_SECS: u32 = 2;
+/// Peak level of the test track
+const AMPLITUDE: f32 = 0.5;
+
+/// Load and play `track`, returning once it finished
+fn play_to_end(manager: &AudioManager, events: &Receiver<AudioEvent>, track: &Path) {
+    manager.load(
+        track.to_string_lossy().into_owned(),
+        false,
+        ReplayGain::default(),
+    );
+    manager.play();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match events.recv_timeout(left) {
+            Ok(AudioEvent::TrackFinished) => return,
+            Ok(_) => {}
+            Err(e) => panic!("no TrackFinished: {}", e),
+        }
+    }
+}
+
+#[test]
+fn plays_to_the_end_on_the_null_output() {
+    let dir = TempDir::new("null");
+    let track = dir.0.join("track.wav");
+    common::write_track(&track, TRACK_SECS, AMPLITUDE);
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let manager = AudioManager::with_output(&Settings::default(), event_tx, |_| {
+        Ok(Box::new(NullOutput::new(SPEED)) as Box<dyn AudioOutput>)
+    });
+    let started = Instant::now();
+    play_to_end(&manager, &event_rx, &track);
+
+    // at 20x the two seconds take a tenth of a second, not real time
+    assert!(started.elapsed() < Duration::from_secs(TRACK_SECS as u64));
+    let duration = manager.status().duration.unwrap();
+    assert!(
+        duration.abs_diff(Duration::from_secs(TRACK_SECS as u64)) < Duration::from_millis(50),
+        "{:?}",
+        duration
+    );
+}
+
+#[test]
+fn records_the_track_on_the_wav_output() {
+    let dir = TempDir::new("wav");
+    let track = dir.0.join("track.wav");
+    let recording = dir.0.join("out.wav");
+    common::write_track(&track, TRACK_SECS, AMPLITUDE);
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let output_path = recording.clone();
+    let manager = AudioManager::with_output(&Settings::default(), event_tx, move |_| {
+        Ok(Box::new(WavOutput::create(&output_path, SPEED)?) as Box<dyn AudioOutput>)
+    });
+    play_to_end(&manager, &event_rx, &track);
+    drop(manager);
+
+    // the output keeps recording silence around the track
+    let samples = WavReader::open(&recording)
+        .unwrap()
+        .into_samples::<f32>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let audible = samples.iter().filter(|sample| sample.abs() > 0.01).count();
+    let expected = (SAMPLE_RATE * TRACK_SECS * 2) as usize;
+    assert!(
+        audible > expected * 9 / 10 && audible <= expected,
+        "{} of {} samples audible",
+        audible,
+        expected
+    );
+    let peak = samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    assert!((peak - AMPLITUDE).abs() < 0.02, "peak {}", peak);
+}
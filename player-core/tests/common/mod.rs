@@ -0,0 +1,47 @@
+//! Helpers shared by the integration tests
+
+// each test binary uses only some of them
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::{fs, process};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// A scratch directory removed when the test ends
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("player-core-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A stereo 440 Hz sine of `secs` seconds peaking at `amplitude`
+pub fn write_track(path: &Path, secs: u32, amplitude: f32) {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec).unwrap();
+    for frame in 0..SAMPLE_RATE * secs {
+        let phase = 2.0 * std::f32::consts::PI * 440.0 * frame as f32 / SAMPLE_RATE as f32;
+        let sample = (phase.sin() * amplitude * i16::MAX as f32) as i16;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+}
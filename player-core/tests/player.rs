@@ -0,0 +1,247 @@
+//! The player on the null and WAV outputs, with its state kept in a scratch directory instead of
+//! the user's one.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+use hound::WavReader;
+
+use player_core::player::{PlaybackState, Player, SleepMode};
+use player_core::settings::{OutputKind, Settings};
+use player_core::track_state::{LoopRegion, TrackStates};
+
+use common::{SAMPLE_RATE, TempDir};
+
+/// Length of the test track with loops
+const TRACK_SECS: u32 = 10;
+/// Length of the tracks the sleep timer ends, and of its fade
+const SHORT_TRACK_SECS: u32 = 2;
+const SLEEP_FADE: Duration = Duration::from_secs(1);
+/// Peak level of the test tracks
+const AMPLITUDE: f32 = 0.5;
+
+/// Point the state and cache directories at a scratch directory. Called first by every test, so
+/// no other test thread reads the environment while it changes.
+fn isolate_state() {
+    static ISOLATE: Once = Once::new();
+    ISOLATE.call_once(|| {
+        let dir = env::temp_dir().join("player-core-tests-home");
+        let _ = fs::remove_dir_all(&dir);
+        // SAFETY: the other test threads wait for `ISOLATE` before touching the environment
+        unsafe {
+            env::set_var("XDG_STATE_HOME", dir.join("state"));
+            env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+            env::set_var("XDG_DATA_HOME", dir.join("data"));
+        }
+    });
+}
+
+/// A player on the null output, or recording to `wav_file`, playing in real time
+fn player(wav_file: Option<&Path>) -> Arc<Player> {
+    let mut settings = Settings::default();
+    settings.audio.output = match wav_file {
+        Some(_) => OutputKind::Wav,
+        None => OutputKind::Null,
+    };
+    settings.audio.wav_file = wav_file.map(Path::to_path_buf);
+    settings.audio.output_speed = 1.0;
+    Player::new(&settings)
+}
+
+/// Wait up to several seconds for `done`
+fn wait_for(what: &str, done: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn track(dir: &TempDir, name: &str, secs: u32) -> PathBuf {
+    let path = dir.0.join(name);
+    common::write_track(&path, secs, AMPLITUDE);
+    path
+}
+
+/// Peak level of every tenth of a second recorded in `wav_file`, from the first one played on.
+/// The output keeps the file valid while it records.
+fn recorded_peaks(wav_file: &Path) -> Vec<f32> {
+    let samples = WavReader::open(wav_file)
+        .unwrap()
+        .into_samples::<f32>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let start = samples
+        .iter()
+        .position(|sample| sample.abs() > 0.01)
+        .unwrap_or(samples.len());
+    samples[start..]
+        .chunks(SAMPLE_RATE as usize / 10 * 2)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        })
+        .collect()
+}
+
+/// Set up the sleep timer with `start` on a queue of two short tracks and wait for it to stop
+/// playback. Checks that it stopped with the track at `next` current, so `play` continues with
+/// it, and returns how long that took and the recorded peaks.
+fn sleep_until_stopped(
+    name: &str,
+    next: Option<usize>,
+    start: impl Fn(&Player),
+) -> (Duration, Vec<f32>) {
+    isolate_state();
+    let dir = TempDir::new(name);
+    let recording = dir.0.join("out.wav");
+    let player = player(Some(&recording));
+    player.set_sleep_fade(SLEEP_FADE);
+    let queue = (0..2)
+        .map(|index| track(&dir, &format!("{}.wav", index), SHORT_TRACK_SECS))
+        .collect::<Vec<_>>();
+
+    let started = Instant::now();
+    player.play_tracks(queue.clone(), 0);
+    start(&player);
+    wait_for("playback", || player.state() == PlaybackState::Playing);
+    wait_for("the sleep timer", || {
+        player.state() == PlaybackState::Stopped
+    });
+    let elapsed = started.elapsed();
+
+    let status = player.status();
+    assert_eq!(status.sleep_timer, None);
+    assert_eq!(status.volume, 1.0);
+    assert_eq!(status.track, next.map(|index| queue[index].clone()));
+    // nothing is played after it stopped
+    std::thread::sleep(Duration::from_millis(300));
+    let peaks = recorded_peaks(&recording);
+    assert!(peaks.ends_with(&[0.0, 0.0]), "{:?}", peaks);
+    (elapsed, peaks)
+}
+
+/// Check that `peaks` stay at full volume for `secs` seconds but the fade, then go down to
+/// silence over the fade. Tracks may be apart by a moment of silence.
+fn assert_faded_out(peaks: &[f32], secs: u32) {
+    let audible = peaks.iter().rposition(|&peak| peak > 0.0).unwrap() + 1;
+    let tenths = secs as usize * 10;
+    assert!(audible.abs_diff(tenths) <= 2, "{:?}", peaks);
+    let fade = SLEEP_FADE.as_millis() as usize / 100;
+    let (full, fading) = peaks[..audible].split_at(audible - fade);
+    assert!(
+        full[..full.len() - 1]
+            .iter()
+            .filter(|&&peak| peak > 0.0)
+            .all(|&peak| peak > AMPLITUDE * 0.9),
+        "{:?}",
+        peaks
+    );
+    assert!(
+        fading.windows(2).all(|pair| pair[1] <= pair[0]),
+        "{:?}",
+        peaks
+    );
+    assert!(fading[fade - 1] < AMPLITUDE * 0.3, "{:?}", peaks);
+}
+
+#[test]
+fn saved_loops_are_kept_per_track() {
+    isolate_state();
+    let dir = TempDir::new("loops");
+    let track = track(&dir, "loops.wav", TRACK_SECS);
+    let player = player(None);
+    player.play_tracks(vec![track.clone()], 0);
+    wait_for("playback", || player.state() == PlaybackState::Playing);
+    player.pause();
+
+    let secs = Duration::from_secs;
+    assert!(player.set_ab_loop(secs(4), secs(6)));
+    assert_eq!(player.save_loop(None).as_deref(), Some("Loop 1"));
+    assert!(player.set_ab_loop(secs(1), secs(2)));
+    assert_eq!(player.save_loop(None).as_deref(), Some("Loop 2"));
+    // a name already taken is replaced
+    assert!(player.set_ab_loop(secs(7), secs(9)));
+    assert_eq!(player.save_loop(Some("Loop 1")).as_deref(), Some("Loop 1"));
+
+    let region = |name: &str, start_ms, end_ms| LoopRegion {
+        name: name.to_string(),
+        start_ms,
+        end_ms,
+    };
+    let expected = vec![region("Loop 2", 1000, 2000), region("Loop 1", 7000, 9000)];
+    assert_eq!(player.loops(), expected);
+    // written for the next run
+    assert_eq!(TrackStates::load().get(&track).loops, expected);
+
+    assert!(player.play_loop("Loop 2"));
+    let ab_loop = player.ab_loop().unwrap();
+    assert_eq!((ab_loop.start_ms, ab_loop.end_ms), (1000, Some(2000)));
+
+    assert!(player.delete_loop("Loop 2"));
+    assert!(!player.delete_loop("Loop 2"));
+    assert!(!player.play_loop("Loop 2"));
+    assert_eq!(
+        TrackStates::load().get(&track).loops,
+        [region("Loop 1", 7000, 9000)]
+    );
+}
+
+#[test]
+fn sleep_at_end_of_track_fades_out_and_stops() {
+    let (elapsed, peaks) =
+        sleep_until_stopped("sleep-track", Some(1), Player::sleep_at_end_of_track);
+
+    let track = Duration::from_secs(SHORT_TRACK_SECS as u64);
+    assert!(
+        elapsed >= track - Duration::from_millis(100),
+        "{:?}",
+        elapsed
+    );
+    assert!(elapsed < track * 2, "{:?}", elapsed);
+    assert_faded_out(&peaks, SHORT_TRACK_SECS);
+}
+
+#[test]
+fn sleep_at_end_of_queue_plays_the_queue_then_fades_out() {
+    let (elapsed, peaks) = sleep_until_stopped("sleep-queue", None, Player::sleep_at_end_of_queue);
+
+    let queue = Duration::from_secs(2 * SHORT_TRACK_SECS as u64);
+    assert!(
+        elapsed >= queue - Duration::from_millis(100),
+        "{:?}",
+        elapsed
+    );
+    // only the last track fades
+    assert_faded_out(&peaks, 2 * SHORT_TRACK_SECS);
+}
+
+#[test]
+fn cancelling_clears_the_sleep_timer() {
+    isolate_state();
+    let dir = TempDir::new("sleep-cancel");
+    let player = player(None);
+    player.play_tracks(vec![track(&dir, "cancel.wav", TRACK_SECS)], 0);
+    wait_for("playback", || player.state() == PlaybackState::Playing);
+
+    player.sleep_after(Duration::from_secs(60));
+    let timer = player.status().sleep_timer.unwrap();
+    assert_eq!(timer.mode, SleepMode::Timer);
+    assert!(timer.remaining_ms.is_some_and(|ms| ms > 59_000));
+    player.cancel_sleep_timer();
+    assert_eq!(player.status().sleep_timer, None);
+
+    player.sleep_at_end_of_queue();
+    assert_eq!(
+        player.status().sleep_timer.unwrap().mode,
+        SleepMode::EndOfQueue
+    );
+    player.cancel_sleep_timer();
+    assert_eq!(player.status().sleep_timer, None);
+    assert_eq!(player.state(), PlaybackState::Playing);
+}
@@ -0,0 +1,29 @@
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+
+use gpui::{AssetSource, Result, SharedString};
+
+/// Icon paths, relative to the `assets` directory
+pub(crate) const PLAY_ICON: &str = "icons/play.svg";
+pub(crate) const PAUSE_ICON: &str = "icons/pause.svg";
+pub(crate) const SEEK_FORWARD_ICON: &str = "icons/seek-forward.svg";
+pub(crate) const SEEK_BACKWARD_ICON: &str = "icons/seek-backward.svg";
+
+/// UI assets bundled into the executable, so they are found no matter which directory the player
+/// is started from.
+#[derive(RustEmbed)]
+#[folder = "assets"]
+pub(crate) struct Assets;
+
+impl AssetSource for Assets {
+    fn load(&self, path: &str) -> Result<Option<Cow<'static, [u8]>>> {
+        Ok(Self::get(path).map(|file| file.data))
+    }
+
+    fn list(&self, path: &str) -> Result<Vec<SharedString>> {
+        Ok(Self::iter()
+            .filter(|asset| asset.starts_with(path))
+            .map(|asset| SharedString::from(asset.into_owned()))
+            .collect())
+    }
+}
@@ -0,0 +1,367 @@
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, Parser, Subcommand};
+use std::io;
+use std::path::{self, PathBuf};
+use std::process::ExitCode;
+
+use player_core::output;
+use player_core::player::{LoopMode, PlaybackState, PlayerStatus};
+use player_core::settings::ChannelSettings;
+
+use crate::format::{balance_label, format_time, sleep_label};
+use crate::ipc::{self, Request};
+
+#[derive(Debug, Parser)]
+#[command(name = "music-player", version, about = "A simple music player")]
+#[command(args_conflicts_with_subcommands = true)]
+pub(crate) struct Cli {
+    /// Files, folders or playlists (.m3u/.m3u8) to play
+    pub(crate) paths: Vec<PathBuf>,
+
+    /// Library folder to use instead of the configured roots, can be repeated
+    #[arg(long, value_name = "DIR")]
+    pub(crate) library: Vec<PathBuf>,
+
+    /// Play the tracks in random order
+    #[arg(long)]
+    pub(crate) shuffle: bool,
+
+    /// Initial volume in percent
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub(crate) volume: Option<u8>,
+
+    /// Play in the terminal without opening a window, exits at the end of the queue
+    #[arg(long)]
+    pub(crate) no_gui: bool,
+
+    /// Run as a daemon without a window, controlled through the socket, MPRIS, MPD or HTTP.
+    /// Picks up the queue of the last run and saves it again on SIGTERM.
+    #[arg(long, conflicts_with = "no_gui")]
+    pub(crate) headless: bool,
+
+    /// Show a terminal UI instead of the window, e.g. over SSH
+    #[arg(long, conflicts_with_all = ["no_gui", "headless"])]
+    pub(crate) tui: bool,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+impl Cli {
+    /// Whether this launch opens the player window
+    pub(crate) fn has_window(&self) -> bool {
+        !self.no_gui && !self.headless && !self.tui
+    }
+}
+
+/// Commands sent to an already running instance
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Resume playback
+    Play,
+    /// Pause playback
+    Pause,
+    /// Skip to the next track
+    Next,
+    /// Print what is playing
+    Status,
+    /// Play faster or slower without changing the pitch, 0.5 to 3.0
+    Speed { speed: f32 },
+    /// Shift the pitch by semitones without changing the speed, -12 to 12
+    Pitch {
+        #[arg(allow_negative_numbers = true)]
+        semitones: f32,
+    },
+    /// Turn the balance towards the left (-1.0) or the right (1.0) channel, 0 is the center
+    Balance {
+        #[arg(allow_negative_numbers = true)]
+        balance: f32,
+    },
+    /// Mix both channels down to mono
+    Mono {
+        #[arg(value_name = "on|off", action = ArgAction::Set, value_parser = BoolishValueParser::new())]
+        mono: bool,
+    },
+    /// Swap the left and right channel
+    Swap {
+        #[arg(value_name = "on|off", action = ArgAction::Set, value_parser = BoolishValueParser::new())]
+        swap: bool,
+    },
+    /// Set, clear and save the A–B loop of the current track
+    #[command(subcommand)]
+    Loop(LoopCommand),
+    /// Fade out and pause after MINUTES, or stop at the end of the `track` or the `queue`;
+    /// `cancel` stops the timer
+    Sleep {
+        #[arg(value_name = "MINUTES|track|queue|cancel", value_parser = parse_sleep)]
+        when: Sleep,
+    },
+    /// List the audio output devices, for `audio.output_device`
+    Outputs,
+    /// Measure the loudness of files, folders or playlists in the background, of the whole
+    /// library when none are given
+    Analyze {
+        paths: Vec<PathBuf>,
+        /// Store the results as ReplayGain tags in the files too
+        #[arg(long)]
+        write_tags: bool,
+    },
+}
+
+/// What to do with the A–B loop
+#[derive(Debug, Subcommand)]
+pub(crate) enum LoopCommand {
+    /// Set point A to the current position
+    A,
+    /// Set point B to the current position and start looping
+    B,
+    /// Stop looping
+    Clear,
+    /// Save the loop for the current track, as `Loop <n>` without a name
+    Save { name: Option<String> },
+    /// List the loops saved for the current track
+    List,
+    /// Loop a saved loop
+    Play { name: String },
+    /// Delete a saved loop
+    Delete { name: String },
+}
+
+/// Argument of the `sleep` command
+#[derive(Clone, Debug)]
+pub(crate) enum Sleep {
+    Minutes(f32),
+    EndOfTrack,
+    EndOfQueue,
+    Cancel,
+}
+
+fn parse_sleep(arg: &str) -> Result<Sleep, String> {
+    match arg {
+        "track" => Ok(Sleep::EndOfTrack),
+        "queue" => Ok(Sleep::EndOfQueue),
+        "cancel" | "off" => Ok(Sleep::Cancel),
+        _ => arg
+            .parse()
+            .map(Sleep::Minutes)
+            .map_err(|_| String::from("expected minutes, `track`, `queue` or `cancel`")),
+    }
+}
+
+/// Send `command` to the running instance and print the result
+pub(crate) fn run_command(command: &Command) -> ExitCode {
+    let request = match command {
+        Command::Play => Request::Play,
+        Command::Pause => Request::Pause,
+        Command::Next => Request::Next,
+        Command::Status => Request::Status,
+        Command::Speed { speed } => Request::SetSpeed { speed: *speed },
+        Command::Pitch { semitones } => Request::SetPitch {
+            semitones: *semitones,
+        },
+        Command::Balance { balance } => Request::SetBalance { balance: *balance },
+        Command::Mono { mono } => Request::SetMono { mono: *mono },
+        Command::Swap { swap } => Request::SetSwapChannels { swap: *swap },
+        Command::Loop(command) => match command {
+            LoopCommand::A => Request::SetLoopStart,
+            LoopCommand::B => Request::SetLoopEnd,
+            LoopCommand::Clear => Request::ClearAbLoop,
+            LoopCommand::Save { name } => Request::SaveLoop { name: name.clone() },
+            LoopCommand::List => Request::Loops,
+            LoopCommand::Play { name } => Request::PlayLoop { name: name.clone() },
+            LoopCommand::Delete { name } => Request::DeleteLoop { name: name.clone() },
+        },
+        Command::Sleep { when } => match when {
+            Sleep::Minutes(minutes) => Request::SleepAfter { minutes: *minutes },
+            Sleep::EndOfTrack => Request::SleepAtEndOfTrack,
+            Sleep::EndOfQueue => Request::SleepAtEndOfQueue,
+            Sleep::Cancel => Request::CancelSleepTimer,
+        },
+        Command::Analyze { paths, write_tags } => Request::Analyze {
+            // the running instance has its own working directory
+            paths: paths
+                .iter()
+                .map(|p| path::absolute(p).unwrap_or_else(|_| p.clone()))
+                .collect(),
+            write_tags: *write_tags,
+        },
+        // answered without the running instance, the devices are the same
+        Command::Outputs => {
+            output::output_device_names()
+                .iter()
+                .for_each(|name| println!("{}", name));
+            return ExitCode::SUCCESS;
+        }
+    };
+
+    match ipc::request(&request) {
+        Ok(response) if response.ok => {
+            if let Some(status) = response.status {
+                print_status(&status);
+            }
+            for region in response.loops.unwrap_or_default() {
+                println!(
+                    "{}: {} - {}",
+                    region.name,
+                    format_time(region.start_ms),
+                    format_time(region.end_ms)
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(response) => {
+            eprintln!(
+                "music-player: {}",
+                response
+                    .error
+                    .unwrap_or_else(|| String::from("request failed"))
+            );
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!(
+                "music-player: no running instance at {} ({})",
+                ipc::socket_path().display(),
+                e
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Hand the launch over to an already running instance: its queue is replaced by `paths`, the
+/// `--volume` and `--shuffle` options are applied and its window is focused. Returns `None`
+/// when no instance is running and this one should start.
+pub(crate) fn forward_to_running_instance(cli: &Cli) -> Option<ExitCode> {
+    let mut requests = Vec::new();
+    // shuffled before opening so the paths start from a random track
+    if cli.shuffle {
+        requests.push(Request::SetShuffle { shuffle: true });
+    }
+    if !cli.paths.is_empty() {
+        // the running instance has its own working directory
+        let paths = cli
+            .paths
+            .iter()
+            .map(|p| path::absolute(p).unwrap_or_else(|_| p.clone()))
+            .collect();
+        requests.push(Request::Open { paths });
+    }
+    if let Some(volume) = cli.volume {
+        requests.push(Request::SetVolume {
+            volume: f32::from(volume) / 100.0,
+        });
+    }
+    if cli.has_window() {
+        requests.push(Request::Focus);
+    }
+    if requests.is_empty() {
+        // a bare `--no-gui` or `--headless` launch still has to find out whether an instance is running
+        requests.push(Request::Status);
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for request in &requests {
+        match ipc::request(request) {
+            Ok(response) if response.ok => {}
+            Ok(response) => {
+                eprintln!(
+                    "music-player: {}",
+                    response
+                        .error
+                        .unwrap_or_else(|| String::from("request failed"))
+                );
+                exit_code = ExitCode::FAILURE;
+            }
+            // nobody is listening, or only a stale socket is left behind
+            Err(e) if is_not_running(&e) => return None,
+            Err(e) => {
+                eprintln!("music-player: the running instance did not answer ({})", e);
+                return Some(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    eprintln!("music-player: forwarded to the running instance");
+    Some(exit_code)
+}
+
+fn is_not_running(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// The channel settings that differ from the default, e.g. `balance L 30%, mono`
+fn channels_label(channels: ChannelSettings) -> String {
+    let mut parts = Vec::new();
+    if channels.balance != 0.0 {
+        parts.push(format!("balance {}", balance_label(channels.balance)));
+    }
+    if channels.mono {
+        parts.push(String::from("mono"));
+    }
+    if channels.swap {
+        parts.push(String::from("swapped"));
+    }
+    parts.join(", ")
+}
+
+fn print_status(status: &PlayerStatus) {
+    let state = match status.state {
+        PlaybackState::Playing => "playing",
+        PlaybackState::Paused => "paused",
+        PlaybackState::Stopped => "stopped",
+    };
+    match &status.track {
+        Some(track) => println!("{}: {}", state, track.display()),
+        None => println!("{}", state),
+    }
+    if status.state != PlaybackState::Stopped {
+        match status.duration_ms {
+            Some(duration) => println!(
+                "position: {} / {}",
+                format_time(status.position_ms),
+                format_time(duration)
+            ),
+            None => println!("position: {}", format_time(status.position_ms)),
+        }
+    }
+    println!("volume: {}%", (status.volume * 100.0).round());
+    if status.speed != 1.0 || status.pitch_semitones != 0.0 {
+        println!(
+            "speed: {}×, pitch {:+} semitones",
+            status.speed, status.pitch_semitones
+        );
+    }
+    if let Some(ab_loop) = status.ab_loop {
+        match ab_loop.end_ms {
+            Some(end_ms) => println!(
+                "loop: {} - {}",
+                format_time(ab_loop.start_ms),
+                format_time(end_ms)
+            ),
+            None => println!("loop: from {}", format_time(ab_loop.start_ms)),
+        }
+    }
+    if status.channels != ChannelSettings::default() {
+        println!("channels: {}", channels_label(status.channels));
+    }
+    if let Some(timer) = status.sleep_timer {
+        println!("sleep: {}", sleep_label(timer));
+    }
+    if let Some(position) = status.queue_position {
+        println!(
+            "queue: {}/{}{}{}",
+            position + 1,
+            status.queue_length,
+            if status.shuffle { " (shuffle)" } else { "" },
+            match status.loop_mode {
+                LoopMode::None => "",
+                LoopMode::Track => " (repeat track)",
+                LoopMode::Playlist => " (repeat queue)",
+            }
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use player_core::player::{SleepMode, SleepTimer};
+
+/// `m:ss`
+pub(crate) fn format_time(ms: u64) -> String {
+    let secs = Duration::from_millis(ms).as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// e.g. `Center` or `L 30%` for a balance of -0.3
+pub(crate) fn balance_label(balance: f32) -> String {
+    let percent = (balance.abs() * 100.0).round();
+    match balance {
+        _ if percent == 0.0 => String::from("Center"),
+        _ if balance < 0.0 => format!("L {}%", percent),
+        _ => format!("R {}%", percent),
+    }
+}
+
+/// e.g. `in 12:34` or `at the end of the track (3:12)`
+pub(crate) fn sleep_label(timer: SleepTimer) -> String {
+    match (timer.mode, timer.remaining_ms) {
+        (SleepMode::Timer, remaining) => format!("in {}", format_time(remaining.unwrap_or(0))),
+        (SleepMode::EndOfTrack, Some(remaining)) => {
+            format!("at the end of the track ({})", format_time(remaining))
+        }
+        (SleepMode::EndOfTrack, None) => String::from("at the end of the track"),
+        (SleepMode::EndOfQueue, Some(remaining)) => {
+            format!("at the end of the queue ({})", format_time(remaining))
+        }
+        (SleepMode::EndOfQueue, None) => String::from("at the end of the queue"),
+    }
+}
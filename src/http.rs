@@ -0,0 +1,782 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use player_core::library::{self, SongInfo};
+use player_core::player::Player;
+use player_core::settings::HttpSettings;
+
+use crate::assets::Assets;
+use crate::ipc::{self, Request, Response};
+
+/// The remote control page, relative to the `assets` directory
+const REMOTE_PAGE: &str = "remote/index.html";
+
+/// Largest accepted request head and body
+const MAX_HEAD_LEN: usize = 16 * 1024;
+const MAX_BODY_LEN: usize = 1024 * 1024;
+/// Largest accepted WebSocket message from a client, they only send control frames
+const MAX_FRAME_LEN: u64 = 64 * 1024;
+/// Most clients served at once, each has its own thread and event streams keep theirs open
+const MAX_CLIENTS: usize = 64;
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Event streams send a keep-alive this often, so closed connections are noticed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Appended to the client key to accept a WebSocket handshake (RFC 6455)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Start serving the HTTP API and the remote control page on a background thread
+pub(crate) fn serve(player: Arc<Player>, settings: &HttpSettings) -> io::Result<()> {
+    let listener = TcpListener::bind((settings.bind_address.as_str(), settings.port))?;
+    info!(
+        "HTTP server listening on http://{}:{}",
+        settings.bind_address, settings.port
+    );
+    let loopback = settings
+        .bind_address
+        .parse::<IpAddr>()
+        .is_ok_and(|address| address.is_loopback());
+    if settings.token.is_none() && !loopback {
+        warn!("The HTTP API accepts requests from the network without a token");
+    }
+
+    let token: Option<Arc<str>> = settings.token.as_deref().map(Arc::from);
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    let Some(slot) = ClientSlot::claim(&clients) else {
+                        let busy = HttpResponse::error(503, "too many clients");
+                        let _ = write_response(&mut stream, &busy);
+                        continue;
+                    };
+                    let player = Arc::clone(&player);
+                    let token = token.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_client(stream, &player, token.as_deref()) {
+                            error!("HTTP client error: {}", e);
+                        }
+                        drop(slot);
+                    });
+                }
+                Err(e) => error!("HTTP server error: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// One of the `MAX_CLIENTS` connections being served, given back when dropped
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    fn claim(clients: &Arc<AtomicUsize>) -> Option<Self> {
+        clients
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < MAX_CLIENTS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(Arc::clone(clients)))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[derive(Debug)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    /// Header names are lowercase
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// The token from an `Authorization: Bearer` header, or from the query for clients that can't
+    /// set headers (`EventSource`, `<img>`)
+    fn token(&self) -> Option<&str> {
+        self.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| self.query.get("token").map(String::as_str))
+    }
+
+    /// Whether a browser sent the request from a page of another site. Browsers add `Origin` to
+    /// cross-site requests, scripts and same-site pages either leave it out or name this host.
+    fn foreign_origin(&self) -> bool {
+        let Some(origin) = self.header("origin") else {
+            return false;
+        };
+        let origin_host = origin.split_once("://").map(|(_, host)| host);
+        origin_host.is_none_or(|origin_host| {
+            self.header("host")
+                .is_none_or(|host| !origin_host.eq_ignore_ascii_case(host))
+        })
+    }
+
+    /// Commands must be sent as JSON, which browsers can't do cross-site without asking first
+    fn is_json(&self) -> bool {
+        self.header("content-type").is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+        })
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Cow<'static, [u8]>,
+}
+
+impl HttpResponse {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: Cow::Owned(serde_json::to_vec(value).unwrap_or_default()),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(
+            status,
+            &Response {
+                ok: false,
+                error: Some(message.into()),
+                ..Response::default()
+            },
+        )
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn handle_client(stream: TcpStream, player: &Player, token: Option<&str>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let request = match read_request(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            return write_response(&mut writer, &HttpResponse::error(400, e.to_string()));
+        }
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+            return write_response(&mut writer, &HttpResponse::error(413, e.to_string()));
+        }
+        Err(e) => return Err(e),
+    };
+
+    if request.path.starts_with("/api/") && request.foreign_origin() {
+        return write_response(
+            &mut writer,
+            &HttpResponse::error(403, "requests from other sites are not accepted"),
+        );
+    }
+    if request.path.starts_with("/api/")
+        && let Some(token) = token
+        && !request
+            .token()
+            .is_some_and(|given| tokens_match(given, token))
+    {
+        return write_response(
+            &mut writer,
+            &HttpResponse::error(401, "missing or wrong token"),
+        );
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/events") => ignore_hangup(stream_events(writer, player)),
+        ("GET", "/api/ws") => {
+            let Some(key) = request.header("sec-websocket-key") else {
+                return write_response(
+                    &mut writer,
+                    &HttpResponse::error(400, "expected a WebSocket handshake"),
+                );
+            };
+            let accept = BASE64.encode(
+                sha1_smol::Sha1::from(format!("{}{}", key, WEBSOCKET_GUID))
+                    .digest()
+                    .bytes(),
+            );
+            writer.write_all(
+                format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                    accept
+                )
+                .as_bytes(),
+            )?;
+            ignore_hangup(stream_websocket(reader.into_inner(), writer, player))
+        }
+        _ => write_response(&mut writer, &route(&request, player, token.is_some())),
+    }
+}
+
+/// Event streams end when the client goes away, which is no error
+fn ignore_hangup(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+            ) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Compare without returning early, so the token can't be guessed from the response time
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// `with_token` tells whether clients had to present a token, which unlocks commands that
+/// change files
+fn route(request: &HttpRequest, player: &Player, with_token: bool) -> HttpResponse {
+    let method = request.method.as_str();
+    match request.path.as_str() {
+        "/" | "/index.html" if method == "GET" => match Assets::get(REMOTE_PAGE) {
+            Some(page) => HttpResponse {
+                status: 200,
+                content_type: "text/html; charset=utf-8",
+                body: page.data,
+            },
+            None => HttpResponse::error(404, "the remote page is missing"),
+        },
+        "/api/status" if method == "GET" => HttpResponse::json(200, &player.status()),
+        "/api/queue" if method == "GET" => {
+            let library = player
+                .library()
+                .into_iter()
+                .map(|song| (song.path.clone(), song))
+                .collect::<HashMap<_, _>>();
+            let queue = player
+                .queue()
+                .into_iter()
+                .map(|track| {
+                    library
+                        .get(&track)
+                        .cloned()
+                        .unwrap_or_else(|| SongInfo::read(&track))
+                })
+                .collect::<Vec<_>>();
+            HttpResponse::json(200, &entries(queue.iter().enumerate()))
+        }
+        "/api/library" if method == "GET" => {
+            let library = player.library();
+            let songs = library
+                .iter()
+                .enumerate()
+                .filter(|(_, song)| matches_query(song, &request.query));
+            HttpResponse::json(200, &entries(songs))
+        }
+        "/api/art" if method == "GET" => album_art(request, player),
+        path if path.starts_with("/api/") && method == "POST" => {
+            if !request.is_json() {
+                return HttpResponse::error(415, "the body must be sent as application/json");
+            }
+            command(&path["/api/".len()..], &request.body, player, with_token)
+        }
+        path if path == "/" || path.starts_with("/api/") => {
+            HttpResponse::error(405, format!("{} is not supported here", method))
+        }
+        path => HttpResponse::error(404, format!("no such page: {}", path)),
+    }
+}
+
+/// A song of the library or the queue, with its index to play or jump to it
+#[derive(Serialize)]
+struct Entry<'a> {
+    index: usize,
+    #[serde(flatten)]
+    song: &'a SongInfo,
+}
+
+fn entries<'a>(songs: impl Iterator<Item = (usize, &'a SongInfo)>) -> Vec<Entry<'a>> {
+    songs.map(|(index, song)| Entry { index, song }).collect()
+}
+
+/// `q` matches any tag or the path case-insensitively, `artist` and `album` browse by exact value
+fn matches_query(song: &SongInfo, query: &HashMap<String, String>) -> bool {
+    if let Some(artist) = query.get("artist")
+        && song.artist.as_ref() != Some(artist)
+    {
+        return false;
+    }
+    if let Some(album) = query.get("album")
+        && song.album.as_ref() != Some(album)
+    {
+        return false;
+    }
+    let Some(q) = query.get("q").map(|q| q.to_lowercase()) else {
+        return true;
+    };
+    [&song.title, &song.artist, &song.album]
+        .into_iter()
+        .flatten()
+        .any(|tag| tag.to_lowercase().contains(&q))
+        || song.path.to_string_lossy().to_lowercase().contains(&q)
+}
+
+/// The cover of the track at `path`, or of the current track. Only songs of the library or the
+/// queue are looked at, and `command` only queues files inside the library roots, so the API
+/// can't be used to probe other files.
+fn album_art(request: &HttpRequest, player: &Player) -> HttpResponse {
+    let track = match request.query.get("path") {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let known = player.queue().contains(&path)
+                || player.library().iter().any(|song| song.path == path);
+            known.then_some(path)
+        }
+        None => player.status().track,
+    };
+    let Some(art) = track.as_deref().and_then(library::cover_art) else {
+        return HttpResponse::error(404, "no album art");
+    };
+
+    match fs::read(&art) {
+        Ok(data) => HttpResponse {
+            status: 200,
+            content_type: image_type(&art),
+            body: Cow::Owned(data),
+        },
+        Err(e) => HttpResponse::error(500, format!("unable to read album art: {}", e)),
+    }
+}
+
+fn image_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `POST /api/<command>` runs a control socket command, the body holds its arguments as a JSON
+/// object, e.g. `POST /api/seek` with `{"position_ms":30000}`. Unlike the control socket, paths
+/// must lie inside the library roots and writing tags needs a token.
+fn command(name: &str, body: &[u8], player: &Player, with_token: bool) -> HttpResponse {
+    let mut args = if body.iter().all(u8::is_ascii_whitespace) {
+        Map::new()
+    } else {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Object(args)) => args,
+            Ok(_) => return HttpResponse::error(400, "the body must be a JSON object"),
+            Err(e) => return HttpResponse::error(400, format!("invalid body: {}", e)),
+        }
+    };
+    args.insert(String::from("command"), Value::from(name));
+
+    match serde_json::from_value::<Request>(Value::Object(args)) {
+        // the event stream is served by `/api/events` and `/api/ws`
+        Ok(Request::Subscribe) => HttpResponse::error(404, "use /api/events or /api/ws"),
+        Ok(Request::Analyze {
+            write_tags: true, ..
+        }) if !with_token => {
+            HttpResponse::error(403, "writing tags over HTTP needs a token in the settings")
+        }
+        Ok(
+            Request::Open { paths } | Request::Enqueue { paths } | Request::Analyze { paths, .. },
+        ) if !paths.iter().all(|path| inside_library(path, player)) => {
+            HttpResponse::error(403, "only files inside the library can be opened")
+        }
+        Ok(request) => {
+            let response = ipc::handle_request(request, player, None);
+            HttpResponse::json(if response.ok { 200 } else { 400 }, &response)
+        }
+        Err(e) => HttpResponse::error(400, format!("invalid command: {}", e)),
+    }
+}
+
+/// Whether `path` lies inside a library root once symlinks and `..` are resolved
+fn inside_library(path: &Path, player: &Player) -> bool {
+    let Ok(path) = fs::canonicalize(path) else {
+        return false;
+    };
+    player
+        .library_roots()
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .any(|root| path.starts_with(root))
+}
+
+/// Read one request, `None` when the client hung up without sending one
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<HttpRequest>> {
+    let mut head = Vec::new();
+    // a line without an end must not grow `head` past the limit either
+    let mut head_reader = reader.by_ref().take(MAX_HEAD_LEN as u64 + 1);
+    while !head.ends_with(b"\r\n\r\n") {
+        let read = head_reader.read_until(b'\n', &mut head)?;
+        if head.len() > MAX_HEAD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "request head too large",
+            ));
+        }
+        if read == 0 {
+            return if head.is_empty() {
+                Ok(None)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "incomplete request",
+                ))
+            };
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Request::new(&mut headers);
+    let invalid =
+        |e: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    parsed.parse(&head).map_err(|e| invalid(&e))?;
+
+    let (path, query) = match parsed.path.unwrap_or("/").split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (parsed.path.unwrap_or("/"), ""),
+    };
+    let headers = parsed
+        .headers
+        .iter()
+        .map(|header| {
+            (
+                header.name.to_lowercase(),
+                String::from_utf8_lossy(header.value).into_owned(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let length = match headers.get("content-length") {
+        Some(length) => length
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| invalid(&"invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            "request body too large",
+        ));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(HttpRequest {
+        method: parsed.method.unwrap_or("GET").to_string(),
+        path: path.to_string(),
+        query: form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        headers,
+        body,
+    }))
+}
+
+fn write_response(writer: &mut TcpStream, response: &HttpResponse) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\
+         Connection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(&response.body)
+}
+
+/// Send every `PlayerEvent` as a server-sent event until the client hangs up
+fn stream_events(mut writer: TcpStream, player: &Player) -> io::Result<()> {
+    // subscribe before answering so no event is missed
+    let events = player.subscribe();
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+
+    loop {
+        match events.recv_timeout(KEEP_ALIVE_INTERVAL) {
+            Ok(event) => {
+                let data = serde_json::to_string(&event)?;
+                writer.write_all(format!("data: {}\n\n", data).as_bytes())?;
+            }
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keep-alive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// Send every `PlayerEvent` as a WebSocket text message until the client closes the connection.
+/// Messages from the client are ignored apart from pings and the close handshake.
+fn stream_websocket(stream: TcpStream, writer: TcpStream, player: &Player) -> io::Result<()> {
+    let events = player.subscribe();
+    let writer = Arc::new(Mutex::new(writer));
+    stream.set_read_timeout(None)?;
+
+    thread::spawn({
+        let writer = Arc::clone(&writer);
+        move || {
+            let mut stream = stream;
+            loop {
+                let reply = match read_frame(&mut stream) {
+                    Ok((OPCODE_PING, payload)) => write_frame(&writer, OPCODE_PONG, &payload),
+                    Ok((OPCODE_CLOSE, _)) | Err(_) => break,
+                    Ok(_) => Ok(()),
+                };
+                if reply.is_err() {
+                    break;
+                }
+            }
+            // the event loop notices on its next write
+            let _ = write_frame(&writer, OPCODE_CLOSE, &[]);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    });
+
+    loop {
+        match events.recv_timeout(KEEP_ALIVE_INTERVAL) {
+            Ok(event) => write_frame(
+                &writer,
+                OPCODE_TEXT,
+                serde_json::to_string(&event)?.as_bytes(),
+            )?,
+            Err(RecvTimeoutError::Timeout) => write_frame(&writer, OPCODE_PING, &[])?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// Read one client frame, returns its opcode and unmasked payload
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut length = [0; 2];
+            stream.read_exact(&mut length)?;
+            u64::from(u16::from_be_bytes(length))
+        }
+        127 => {
+            let mut length = [0; 8];
+            stream.read_exact(&mut length)?;
+            u64::from_be_bytes(length)
+        }
+        length => u64::from(length),
+    };
+    if length > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            "WebSocket frame too large",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// Write one unfragmented, unmasked frame
+fn write_frame(writer: &Mutex<TcpStream>, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.lock().unwrap().write_all(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> io::Result<Option<HttpRequest>> {
+        read_request(&mut io::Cursor::new(raw.as_bytes()))
+    }
+
+    fn request(headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: String::from("POST"),
+            path: String::from("/api/play"),
+            query: HashMap::new(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_path_query_headers_and_body() {
+        let request = parse(
+            "POST /api/seek?token=a%20b&x=1 HTTP/1.1\r\nHost: localhost:8080\r\n\
+             Content-Type: application/json\r\nContent-Length: 21\r\n\r\n{\"position_ms\":30000}",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/seek");
+        assert_eq!(request.token(), Some("a b"));
+        assert_eq!(request.query["x"], "1");
+        assert_eq!(request.header("host"), Some("localhost:8080"));
+        assert_eq!(request.body, br#"{"position_ms":30000}"#);
+    }
+
+    #[test]
+    fn rejects_broken_requests() {
+        assert!(parse("").unwrap().is_none());
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nHost: x\r\n").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            parse("GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            parse(&format!(
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY_LEN + 1
+            ))
+            .unwrap_err()
+            .kind(),
+            io::ErrorKind::FileTooLarge
+        );
+    }
+
+    #[test]
+    fn stops_reading_an_endless_head_line() {
+        let endless = io::repeat(b'a');
+        let mut reader = BufReader::new(endless);
+        assert_eq!(
+            read_request(&mut reader).unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+    }
+
+    #[test]
+    fn client_slots_are_limited_and_given_back() {
+        let clients = Arc::new(AtomicUsize::new(0));
+        let slots = (0..MAX_CLIENTS)
+            .map(|_| ClientSlot::claim(&clients).unwrap())
+            .collect::<Vec<_>>();
+        assert!(ClientSlot::claim(&clients).is_none());
+        drop(slots);
+        assert!(ClientSlot::claim(&clients).is_some());
+    }
+
+    #[test]
+    fn bearer_header_wins_over_the_query() {
+        let mut request = request(&[("authorization", "Bearer secret")]);
+        request
+            .query
+            .insert(String::from("token"), String::from("other"));
+        assert_eq!(request.token(), Some("secret"));
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+    }
+
+    #[test]
+    fn only_same_site_origins_are_accepted() {
+        assert!(!request(&[("host", "pi:8080")]).foreign_origin());
+        assert!(!request(&[("host", "pi:8080"), ("origin", "http://pi:8080")]).foreign_origin());
+        assert!(
+            request(&[("host", "pi:8080"), ("origin", "https://evil.example")]).foreign_origin()
+        );
+        assert!(request(&[("host", "pi:8080"), ("origin", "null")]).foreign_origin());
+        assert!(request(&[("origin", "http://pi:8080")]).foreign_origin());
+    }
+
+    #[test]
+    fn commands_must_be_json() {
+        assert!(request(&[("content-type", "application/json")]).is_json());
+        assert!(request(&[("content-type", "Application/JSON; charset=utf-8")]).is_json());
+        assert!(!request(&[("content-type", "text/plain")]).is_json());
+        assert!(!request(&[]).is_json());
+    }
+}
@@ -0,0 +1,485 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+
+use player_core::library::{self, SongInfo};
+use player_core::player::{
+    LoopMode, MIN_LOOP_LENGTH, PITCH_RANGE_SEMITONES, Player, PlayerStatus, SPEED_RANGE,
+};
+use player_core::settings::{BALANCE_RANGE, ChannelSettings};
+use player_core::track_state::LoopRegion;
+
+/// File name of the control socket inside the runtime dir
+const SOCKET_FILE_NAME: &str = "music-player.sock";
+/// How long a client waits for the running instance to answer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest accepted relative seek step, same as in the settings file
+const MAX_SEEK_STEP_SECS: u64 = 600;
+/// Longest sleep timer, a day
+const MAX_SLEEP_MINUTES: f32 = 24.0 * 60.0;
+
+/// A request sent by a client, one JSON object per line, e.g. `{"command":"pause"}` or
+/// `{"command":"seek","position_ms":30000}`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(crate) enum Request {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    Seek {
+        position_ms: u64,
+    },
+    SeekForward,
+    SeekBackward,
+    SetSeekStep {
+        secs: u64,
+    },
+    /// 0.0 - 1.0
+    SetVolume {
+        volume: f32,
+    },
+    SetShuffle {
+        shuffle: bool,
+    },
+    /// 0.5 - 3.0, the pitch is kept
+    SetSpeed {
+        speed: f32,
+    },
+    /// -12.0 - 12.0
+    SetPitch {
+        semitones: f32,
+    },
+    /// -1.0 (left only) - 1.0 (right only)
+    SetBalance {
+        balance: f32,
+    },
+    /// Mix both channels down to mono
+    SetMono {
+        mono: bool,
+    },
+    /// Swap the left and right channel
+    SetSwapChannels {
+        swap: bool,
+    },
+    /// `none`, `track` or `playlist`
+    SetLoop {
+        mode: LoopMode,
+    },
+    /// Set point A of the A–B loop to the current position
+    SetLoopStart,
+    /// Set point B to the current position and start looping
+    SetLoopEnd,
+    SetAbLoop {
+        start_ms: u64,
+        end_ms: u64,
+    },
+    ClearAbLoop,
+    /// Save the A–B loop for the current track, named `Loop <n>` without a name
+    SaveLoop {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// The loops saved for the current track
+    Loops,
+    PlayLoop {
+        name: String,
+    },
+    DeleteLoop {
+        name: String,
+    },
+    /// Fade out and pause after `minutes`
+    SleepAfter {
+        minutes: f32,
+    },
+    /// Fade out and stop at the end of the current track
+    SleepAtEndOfTrack,
+    /// Fade out and stop at the end of the last track of the queue
+    SleepAtEndOfQueue,
+    CancelSleepTimer,
+    Status,
+    /// Replace the queue with these files, folders or playlists and start playing
+    Open {
+        paths: Vec<PathBuf>,
+    },
+    /// Add files, folders or playlists to the end of the queue
+    Enqueue {
+        paths: Vec<PathBuf>,
+    },
+    Queue,
+    ClearQueue,
+    /// Play the track at `index` of the queue
+    Jump {
+        index: usize,
+    },
+    Library,
+    RescanLibrary,
+    /// Replace the queue with the whole library, starting at the song at `index`
+    PlayLibrary {
+        #[serde(default)]
+        index: usize,
+    },
+    /// Measure the loudness of these files, folders or playlists in the background, of the whole
+    /// library when there are none
+    Analyze {
+        #[serde(default)]
+        paths: Vec<PathBuf>,
+        /// Store the results as ReplayGain tags in the files too
+        #[serde(default)]
+        write_tags: bool,
+    },
+    CancelAnalysis,
+    /// Bring the player window to the front
+    Focus,
+    /// Answer, then keep the connection open and send every `PlayerEvent` as a line
+    Subscribe,
+}
+
+/// The answer to a request, one JSON object per line
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Response {
+    pub(crate) ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) status: Option<PlayerStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) queue: Option<Vec<PathBuf>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) library: Option<Vec<SongInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) loops: Option<Vec<LoopRegion>>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            ..Self::default()
+        }
+    }
+
+    fn error(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Path of the control socket: `$XDG_RUNTIME_DIR/music-player.sock`, or a per-user file in the
+/// temp dir on platforms without a runtime dir
+pub(crate) fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join(SOCKET_FILE_NAME),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("music-player-{}.sock", user))
+        }
+    }
+}
+
+/// Send one request to the running instance and wait for its response
+pub(crate) fn request(request: &Request) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Claim the control socket, which makes this the running instance. A socket left behind by a
+/// crashed instance is replaced, a live one is an `AddrInUse` error.
+pub(crate) fn bind() -> io::Result<UnixListener> {
+    let path = socket_path();
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another instance is listening on {}", path.display()),
+                ));
+            }
+            fs::remove_file(&path)?;
+            UnixListener::bind(&path)?
+        }
+        Err(e) => return Err(e),
+    };
+    info!("Control socket listening on {}", path.display());
+    Ok(listener)
+}
+
+/// Start accepting requests on the socket claimed by `bind` on a background thread. `Focus`
+/// requests are passed on to `focus_tx`. Clients that connected before are answered now.
+pub(crate) fn serve(listener: UnixListener, player: Arc<Player>, focus_tx: Sender<()>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let player = Arc::clone(&player);
+                    let focus_tx = focus_tx.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle_client(stream, &player, &focus_tx) {
+                            error!("Control client error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Control socket error: {}", e),
+            }
+        }
+    });
+}
+
+fn handle_client(stream: UnixStream, player: &Player, focus_tx: &Sender<()>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Subscribe) => {
+                // subscribe before answering so no event is missed
+                let events = player.subscribe();
+                write_line(&mut writer, &Response::ok())?;
+                // ends with an error once the client hangs up
+                for event in events {
+                    write_line(&mut writer, &event)?;
+                }
+                return Ok(());
+            }
+            Ok(request) => handle_request(request, player, Some(focus_tx)),
+            Err(e) => Response::error(format!("invalid request: {}", e)),
+        };
+        write_line(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+fn write_line(writer: &mut UnixStream, value: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())
+}
+
+/// Carry out everything but `Subscribe`, which needs the connection. `Focus` fails without a
+/// `focus_tx`.
+pub(crate) fn handle_request(
+    request: Request,
+    player: &Player,
+    focus_tx: Option<&Sender<()>>,
+) -> Response {
+    match request {
+        Request::Play => player.play(),
+        Request::Pause => player.pause(),
+        Request::Toggle => player.toggle(),
+        Request::Stop => player.stop(),
+        Request::Next => player.next(),
+        Request::Previous => player.previous(),
+        Request::Seek { position_ms } => player.seek_to(Duration::from_millis(position_ms)),
+        Request::SeekForward => player.seek_forward(),
+        Request::SeekBackward => player.seek_backward(),
+        Request::SetSeekStep { secs } => {
+            if !(1..=MAX_SEEK_STEP_SECS).contains(&secs) {
+                return Response::error(format!(
+                    "seek step must be between 1 and {} seconds",
+                    MAX_SEEK_STEP_SECS
+                ));
+            }
+            player.set_seek_step(Duration::from_secs(secs));
+        }
+        Request::SetVolume { volume } => {
+            if !(0.0..=1.0).contains(&volume) {
+                return Response::error("volume must be between 0.0 and 1.0");
+            }
+            player.set_volume(volume);
+        }
+        Request::SetShuffle { shuffle } => player.set_shuffle(shuffle),
+        Request::SetSpeed { speed } => {
+            let (min, max) = SPEED_RANGE;
+            if !(min..=max).contains(&speed) {
+                return Response::error(format!("speed must be between {} and {}", min, max));
+            }
+            player.set_speed(speed);
+        }
+        Request::SetPitch { semitones } => {
+            let (min, max) = PITCH_RANGE_SEMITONES;
+            if !(min..=max).contains(&semitones) {
+                return Response::error(format!(
+                    "pitch must be between {} and {} semitones",
+                    min, max
+                ));
+            }
+            player.set_pitch(semitones);
+        }
+        Request::SetBalance { balance } => {
+            let (min, max) = BALANCE_RANGE;
+            if !(min..=max).contains(&balance) {
+                return Response::error(format!("balance must be between {} and {}", min, max));
+            }
+            player.set_channels(ChannelSettings {
+                balance,
+                ..player.channels()
+            });
+        }
+        Request::SetMono { mono } => player.set_channels(ChannelSettings {
+            mono,
+            ..player.channels()
+        }),
+        Request::SetSwapChannels { swap } => player.set_channels(ChannelSettings {
+            swap,
+            ..player.channels()
+        }),
+        Request::SetLoop { mode } => player.set_loop_mode(mode),
+        Request::SetLoopStart => {
+            if player.status().track.is_none() {
+                return Response::error("nothing is playing");
+            }
+            player.set_loop_start();
+        }
+        Request::SetLoopEnd => {
+            if !player.set_loop_end() {
+                return Response::error(format!(
+                    "the loop must be at least {} ms long",
+                    MIN_LOOP_LENGTH.as_millis()
+                ));
+            }
+        }
+        Request::SetAbLoop { start_ms, end_ms } => {
+            let (start, end) = (
+                Duration::from_millis(start_ms),
+                Duration::from_millis(end_ms),
+            );
+            if !player.set_ab_loop(start, end) {
+                return Response::error(format!(
+                    "the loop must be at least {} ms long and something must be playing",
+                    MIN_LOOP_LENGTH.as_millis()
+                ));
+            }
+        }
+        Request::ClearAbLoop => player.clear_ab_loop(),
+        Request::SaveLoop { name } => {
+            if name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+                return Response::error("the loop name must not be empty");
+            }
+            if player.save_loop(name.as_deref()).is_none() {
+                return Response::error("no A-B loop is set");
+            }
+        }
+        Request::Loops => {
+            return Response {
+                loops: Some(player.loops()),
+                ..Response::ok()
+            };
+        }
+        Request::PlayLoop { name } => {
+            if !player.play_loop(&name) {
+                return Response::error(format!("no loop named {}", name));
+            }
+        }
+        Request::DeleteLoop { name } => {
+            if !player.delete_loop(&name) {
+                return Response::error(format!("no loop named {}", name));
+            }
+        }
+        Request::SleepAfter { minutes } => {
+            if !(minutes > 0.0 && minutes <= MAX_SLEEP_MINUTES) {
+                return Response::error(format!(
+                    "the sleep timer must be between 0 and {} minutes",
+                    MAX_SLEEP_MINUTES
+                ));
+            }
+            player.sleep_after(Duration::from_secs_f32(minutes * 60.0));
+        }
+        Request::SleepAtEndOfTrack => player.sleep_at_end_of_track(),
+        Request::SleepAtEndOfQueue => player.sleep_at_end_of_queue(),
+        Request::CancelSleepTimer => player.cancel_sleep_timer(),
+        Request::Status => {
+            return Response {
+                status: Some(player.status()),
+                ..Response::ok()
+            };
+        }
+        Request::Open { paths } => {
+            let tracks = library::expand(&paths);
+            if tracks.is_empty() {
+                return Response::error("nothing to play");
+            }
+            player.open(tracks);
+        }
+        Request::Enqueue { paths } => {
+            let tracks = library::expand(&paths);
+            if tracks.is_empty() {
+                return Response::error("nothing to enqueue");
+            }
+            player.enqueue(tracks);
+        }
+        Request::Queue => {
+            return Response {
+                queue: Some(player.queue()),
+                ..Response::ok()
+            };
+        }
+        Request::ClearQueue => player.clear_queue(),
+        Request::Jump { index } => {
+            if !player.jump(index) {
+                return Response::error(format!("no track at index {}", index));
+            }
+        }
+        Request::Library => {
+            return Response {
+                library: Some(player.library()),
+                ..Response::ok()
+            };
+        }
+        Request::RescanLibrary => player.rescan_library(),
+        Request::PlayLibrary { index } => {
+            if index >= player.library().len() {
+                return Response::error(format!("no song at index {}", index));
+            }
+            player.play_library(index);
+        }
+        Request::Analyze { paths, write_tags } => {
+            let started = if paths.is_empty() {
+                player.analyze_library(write_tags)
+            } else {
+                let tracks = library::expand(&paths);
+                if tracks.is_empty() {
+                    return Response::error("nothing to analyze");
+                }
+                player.analyze_loudness(tracks, write_tags)
+            };
+            if !started {
+                return Response::error("an analysis is already running");
+            }
+        }
+        Request::CancelAnalysis => player.cancel_analysis(),
+        Request::Focus => {
+            if focus_tx.is_none_or(|focus_tx| focus_tx.send(()).is_err()) {
+                return Response::error("the player has no window");
+            }
+        }
+        // handled by the connection, which turns into an event stream
+        Request::Subscribe => {}
+    }
+    Response::ok()
+}
@@ -0,0 +1,153 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use gpui::{App, KeyBinding, Keystroke};
+use log::error;
+
+use crate::settings::{Settings, SettingsError};
+use crate::{
+    AnalyzeLoudness, CancelSleepTimer, ClearLoop, PitchDown, PitchUp, PlayPause, Preferences, Quit,
+    ResetRate, SaveLoop, SeekBackward, SeekForward, SetLoopEnd, SetLoopStart, SleepTimer,
+    SpeedDown, SpeedUp,
+};
+
+/// Names accepted on the right hand side of a keymap entry
+const ACTION_NAMES: &[&str] = &[
+    "Quit",
+    "PlayPause",
+    "SeekForward",
+    "SeekBackward",
+    "Preferences",
+    "AnalyzeLoudness",
+    "SpeedUp",
+    "SpeedDown",
+    "PitchUp",
+    "PitchDown",
+    "ResetRate",
+    "SetLoopStart",
+    "SetLoopEnd",
+    "ClearLoop",
+    "SaveLoop",
+    "SleepTimer",
+    "CancelSleepTimer",
+];
+
+/// Bindings that are always present, user keymap entries are added on top
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("cmd-q", "Quit"),
+    ("ctrl-c", "Quit"),
+    ("space", "PlayPause"),
+    ("right", "SeekForward"),
+    ("left", "SeekBackward"),
+    ("cmd-,", "Preferences"),
+    ("ctrl-,", "Preferences"),
+    ("]", "SpeedUp"),
+    ("[", "SpeedDown"),
+    ("alt-]", "PitchUp"),
+    ("alt-[", "PitchDown"),
+    ("\\", "ResetRate"),
+    ("a", "SetLoopStart"),
+    ("b", "SetLoopEnd"),
+    ("shift-a", "ClearLoop"),
+    ("s", "SaveLoop"),
+    ("z", "SleepTimer"),
+    ("shift-z", "CancelSleepTimer"),
+];
+
+/// A keymap file maps keystrokes to action names, e.g.
+///
+/// ```toml
+/// [bindings]
+/// "space" = "PlayPause"
+/// "ctrl-right" = "SeekForward"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    #[serde(default)]
+    bindings: BTreeMap<String, String>,
+}
+
+/// Load and validate the keymap file at `path`, returning `(keystroke, action)` pairs
+pub(crate) fn load(path: &Path) -> Result<Vec<(String, String)>, SettingsError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| SettingsError::Io(path.to_path_buf(), e.to_string()))?;
+    let keymap: KeymapFile = toml::from_str(&contents)
+        .map_err(|e| SettingsError::Parse(path.to_path_buf(), e.to_string()))?;
+
+    for (keystrokes, action) in &keymap.bindings {
+        for keystroke in keystrokes.split_whitespace() {
+            if Keystroke::parse(keystroke).is_err() {
+                return Err(SettingsError::Parse(
+                    path.to_path_buf(),
+                    format!("invalid keystroke `{}`", keystroke),
+                ));
+            }
+        }
+        if !ACTION_NAMES.contains(&action.as_str()) {
+            return Err(SettingsError::Parse(
+                path.to_path_buf(),
+                format!(
+                    "unknown action `{}` for `{}`, expected one of {}",
+                    action,
+                    keystrokes,
+                    ACTION_NAMES.join(", ")
+                ),
+            ));
+        }
+    }
+
+    Ok(keymap.bindings.into_iter().collect())
+}
+
+fn binding(keystrokes: &str, action: &str) -> Option<KeyBinding> {
+    match action {
+        "Quit" => Some(KeyBinding::new(keystrokes, Quit, None)),
+        "PlayPause" => Some(KeyBinding::new(keystrokes, PlayPause, None)),
+        "SeekForward" => Some(KeyBinding::new(keystrokes, SeekForward, None)),
+        "SeekBackward" => Some(KeyBinding::new(keystrokes, SeekBackward, None)),
+        "Preferences" => Some(KeyBinding::new(keystrokes, Preferences, None)),
+        "AnalyzeLoudness" => Some(KeyBinding::new(keystrokes, AnalyzeLoudness, None)),
+        "SpeedUp" => Some(KeyBinding::new(keystrokes, SpeedUp, None)),
+        "SpeedDown" => Some(KeyBinding::new(keystrokes, SpeedDown, None)),
+        "PitchUp" => Some(KeyBinding::new(keystrokes, PitchUp, None)),
+        "PitchDown" => Some(KeyBinding::new(keystrokes, PitchDown, None)),
+        "ResetRate" => Some(KeyBinding::new(keystrokes, ResetRate, None)),
+        "SetLoopStart" => Some(KeyBinding::new(keystrokes, SetLoopStart, None)),
+        "SetLoopEnd" => Some(KeyBinding::new(keystrokes, SetLoopEnd, None)),
+        "ClearLoop" => Some(KeyBinding::new(keystrokes, ClearLoop, None)),
+        "SaveLoop" => Some(KeyBinding::new(keystrokes, SaveLoop, None)),
+        "SleepTimer" => Some(KeyBinding::new(keystrokes, SleepTimer, None)),
+        "CancelSleepTimer" => Some(KeyBinding::new(keystrokes, CancelSleepTimer, None)),
+        _ => None,
+    }
+}
+
+/// All `(keystroke, action)` pairs in effect for `settings`, defaults first
+pub(crate) fn bindings(settings: &Settings) -> Vec<(String, String)> {
+    let mut bindings = DEFAULT_BINDINGS
+        .iter()
+        .map(|(keystrokes, action)| (keystrokes.to_string(), action.to_string()))
+        .collect::<Vec<_>>();
+
+    if let Some(path) = &settings.shortcuts.keymap {
+        match load(path) {
+            Ok(user_bindings) => bindings.extend(user_bindings),
+            Err(e) => error!("{}; using default key bindings", e),
+        }
+    }
+
+    bindings
+}
+
+/// (Re)bind all keys: the defaults first, then the user keymap so its entries take precedence
+pub(crate) fn bind_keys(settings: &Settings, cx: &mut App) {
+    cx.clear_key_bindings();
+    cx.bind_keys(
+        bindings(settings)
+            .iter()
+            .filter_map(|(keystrokes, action)| binding(keystrokes, action)),
+    );
+}
@@ -1,28 +1,168 @@
-use std::{path::Path, sync::mpsc};
-mod audio_manager;
+use std::{process::ExitCode, time::Duration};
+mod assets;
+mod cli;
+mod format;
+mod http;
+mod ipc;
+mod keymap;
+mod mpd;
+#[cfg(target_os = "linux")]
+mod mpris;
 mod music_list_view;
 mod play_element;
+mod preferences_view;
+mod seek_bar;
+mod settings;
+mod theme;
+mod tui;
+mod visualizer;
+use clap::Parser;
 use gpui::{
-    AppContext, Application, Bounds, ClickEvent, Element, Entity, ImageSource, InteractiveElement,
-    KeyBinding, ParentElement, Render, Resource, StatefulInteractiveElement, Styled, Window,
-    WindowBounds, WindowOptions, actions, div, img, px, rgb, size,
+    AppContext, Application, Bounds, ClickEvent, Context, Entity, InteractiveElement, Menu,
+    MenuItem, ParentElement, Render, SharedString, StatefulInteractiveElement, Styled,
+    Subscription, Window, WindowBounds, WindowOptions, actions, div, prelude::FluentBuilder, px,
+    size, svg,
 };
-use log::info;
-use std::sync::Arc;
+use log::{error, info};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, mpsc};
+use std::thread;
 
-use crate::{audio_manager::AudioManager, music_list_view::ListView};
+use player_core::library;
+use player_core::player::{PlaybackRate, PlaybackState, Player, PlayerEvent};
+use player_core::state::SavedState;
+
+use crate::{
+    assets::{Assets, SEEK_BACKWARD_ICON, SEEK_FORWARD_ICON},
+    cli::Cli,
+    music_list_view::ListView,
+    seek_bar::SeekBar,
+    settings::{GlobalSettings, Settings, VisualizerMode},
+    theme::Theme,
+    visualizer::Visualizer,
+};
 use play_element::PlayElement;
 
-actions!(music_player, [Quit]);
+actions!(
+    music_player,
+    [
+        Quit,
+        PlayPause,
+        SeekForward,
+        SeekBackward,
+        Preferences,
+        AnalyzeLoudness,
+        SpeedUp,
+        SpeedDown,
+        PitchUp,
+        PitchDown,
+        ResetRate,
+        SetLoopStart,
+        SetLoopEnd,
+        ClearLoop,
+        SaveLoop,
+        SleepTimer,
+        CancelSleepTimer
+    ]
+);
 
-enum PlayerCommand {
-    Play,
-    Paused,
-}
+/// How often the UI picks up settings reloaded by the file watcher
+const SETTINGS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the UI picks up events from the player
+const PLAYER_EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the headless daemon checks for a shutdown signal and reloaded settings
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long playback fades out when the headless daemon is stopped
+const SHUTDOWN_FADE: Duration = Duration::from_secs(1);
+/// How much the speed actions change the playback speed
+const SPEED_STEP: f32 = 0.1;
+/// How much the pitch actions change the pitch, in semitones
+const PITCH_STEP: f32 = 1.0;
+
+/// Set by the SIGTERM/SIGINT handler of the headless daemon
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
 
 struct MusicPlayer {
     play_btn: Entity<PlayElement>,
     songs_list: Entity<ListView>,
+    seek_bar: Entity<SeekBar>,
+    visualizer: Entity<Visualizer>,
+    player: Arc<Player>,
+    /// Settings currently applied to the views and the player
+    settings: Settings,
+    /// Why audio moved to another output, shown until clicked away
+    notice: Option<SharedString>,
+    /// Progress of the running loudness analysis
+    analysis: Option<SharedString>,
+    /// Speed and pitch, when they differ from normal playback
+    rate: Option<SharedString>,
+    _settings_subscription: Subscription,
+    _appearance_subscription: Subscription,
+}
+
+impl MusicPlayer {
+    fn new(
+        play_btn: Entity<PlayElement>,
+        songs_list: Entity<ListView>,
+        player: Arc<Player>,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let rate = rate_label(player.rate());
+        let settings = cx.global::<GlobalSettings>().0.clone();
+        let visualizer_mode = settings.appearance.visualizer;
+        Self {
+            play_btn,
+            songs_list,
+            seek_bar: cx.new(|_| SeekBar::new(Arc::clone(&player))),
+            visualizer: cx.new(|cx| Visualizer::new(Arc::clone(&player), visualizer_mode, cx)),
+            player,
+            settings,
+            notice: None,
+            analysis: None,
+            rate,
+            _settings_subscription: cx
+                .observe_global_in::<GlobalSettings>(window, Self::apply_settings),
+            // the "system" theme follows the desktop's light/dark preference
+            _appearance_subscription: cx
+                .observe_window_appearance(window, |_, _, cx| theme::apply(cx)),
+        }
+    }
+
+    /// Apply changed settings live to the window, the player and the song list
+    fn apply_settings(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let settings = cx.global::<GlobalSettings>().0.clone();
+        if settings == self.settings {
+            return;
+        }
+
+        self.player.apply_settings(&self.settings, &settings);
+        if settings.appearance.window_width != self.settings.appearance.window_width
+            || settings.appearance.window_height != self.settings.appearance.window_height
+        {
+            window.resize(size(
+                px(settings.appearance.window_width),
+                px(settings.appearance.window_height),
+            ));
+        }
+        if settings.appearance.visualizer != self.settings.appearance.visualizer {
+            self.visualizer.update(cx, |visualizer, cx| {
+                visualizer.set_mode(settings.appearance.visualizer, cx)
+            });
+        }
+        if settings.library.roots != self.settings.library.roots {
+            self.songs_list.update(cx, |list, cx| {
+                list.reload();
+                cx.notify();
+            });
+        }
+
+        self.settings = settings;
+        cx.notify();
+    }
 }
 
 impl Render for MusicPlayer {
@@ -35,16 +175,13 @@ impl Render for MusicPlayer {
         let play_btn_for_forward = self.play_btn.clone();
         let play_btn_for_backward = self.play_btn.clone();
         let songs_list_view = self.songs_list.clone();
-
-        let next_button_img = ImageSource::Resource(Resource::Path(Arc::from(Path::new(
-            "assets/right-button.png",
-        ))));
-        let prev_button_img = ImageSource::Resource(Resource::Path(Arc::from(Path::new(
-            "assets/left-button.png",
-        ))));
+        let seek_bar = self.seek_bar.clone();
+        let visualizer = self.visualizer.clone();
+        let theme = cx.global::<Theme>();
 
         div()
-            .bg(rgb(0xFAF9F6))
+            .bg(theme.background)
+            .text_color(theme.text)
             .flex()
             .justify_center()
             .flex_col()
@@ -54,8 +191,47 @@ impl Render for MusicPlayer {
             .size_full()
             // App title
             .child(div().text_center().child("Music Player").text_3xl())
+            .when_some(self.notice.clone(), |this, notice| {
+                this.child(
+                    div()
+                        .id("notice")
+                        .px_4()
+                        .text_color(theme.error)
+                        .child(notice)
+                        .on_click(cx.listener(|this, _: &ClickEvent, _window, cx| {
+                            this.notice = None;
+                            cx.notify();
+                        })),
+                )
+            })
+            .when_some(self.analysis.clone(), |this, analysis| {
+                this.child(div().px_4().child(analysis))
+            })
+            .when_some(self.rate.clone(), |this, rate| {
+                this.child(div().px_4().child(rate))
+            })
+            .when_some(self.player.sleep_timer(), |this, timer| {
+                this.child(
+                    div()
+                        .id("sleep-timer")
+                        .px_4()
+                        .cursor_pointer()
+                        .child(format!(
+                            "Sleep {} · click to cancel",
+                            format::sleep_label(timer)
+                        ))
+                        .on_click(cx.listener(|this, _: &ClickEvent, _window, _cx| {
+                            this.player.cancel_sleep_timer();
+                        })),
+                )
+            })
             // music list
             .child(songs_list_view)
+            .when(
+                self.settings.appearance.visualizer != VisualizerMode::Off,
+                |this| this.child(visualizer),
+            )
+            .child(seek_bar)
             // control plane
             .child(
                 div()
@@ -64,8 +240,8 @@ impl Render for MusicPlayer {
                     .flex_row()
                     .justify_between()
                     .items_center()
-                    .bg(rgb(0x1C4A5A))
-                    .w(px(800.))
+                    .bg(theme.accent)
+                    .w_full()
                     .h(px(100.))
                     // Seek backward button
                     .child(
@@ -79,7 +255,12 @@ impl Render for MusicPlayer {
                                     });
                                 },
                             ))
-                            .child(img(prev_button_img).size_full()),
+                            .child(
+                                svg()
+                                    .path(SEEK_BACKWARD_ICON)
+                                    .text_color(theme.accent_text)
+                                    .size_full(),
+                            ),
                     )
                     // Play/pause button
                     .child(play_btn)
@@ -95,42 +276,445 @@ impl Render for MusicPlayer {
                                     });
                                 },
                             ))
-                            .child(img(next_button_img).size_full()),
+                            .child(
+                                svg()
+                                    .path(SEEK_FORWARD_ICON)
+                                    .text_color(theme.accent_text)
+                                    .size_full(),
+                            ),
                     ),
             )
     }
 }
 
-fn main() {
+/// Label of the playback rate, e.g. `Speed 1.5× · pitch +2 semitones`, `None` at normal rate
+fn rate_label(rate: PlaybackRate) -> Option<SharedString> {
+    let mut parts = Vec::new();
+    if rate.speed != 1.0 {
+        parts.push(format!("Speed {}×", rate.speed));
+    }
+    if rate.pitch_semitones != 0.0 {
+        parts.push(format!("pitch {:+} semitones", rate.pitch_semitones));
+    }
+    (!parts.is_empty()).then(|| parts.join(" · ").into())
+}
+
+/// Replace the configured library roots with the ones given on the command line
+fn override_library(settings: &mut Settings, library: &[PathBuf]) {
+    if !library.is_empty() {
+        settings.library.roots = library.to_vec();
+    }
+}
+
+/// Play without a window until the queue runs out
+fn run_in_terminal(player: &Player, has_paths: bool) -> ExitCode {
+    let events = player.subscribe();
+    if !has_paths {
+        player.open(player.library().into_iter().map(|song| song.path).collect());
+    }
+    if player.state() == PlaybackState::Stopped {
+        eprintln!("music-player: nothing to play");
+        return ExitCode::FAILURE;
+    }
+
+    for event in events {
+        match event {
+            PlayerEvent::TrackChanged(Some(track)) => println!("Playing {}", track.display()),
+            PlayerEvent::StateChanged(PlaybackState::Stopped) => break,
+            _ => {}
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Serve the control interfaces without a window until SIGTERM or SIGINT, then fade out and save
+/// the queue for the next start
+fn run_daemon(
+    player: &Player,
+    mut settings: Settings,
+    settings_rx: &Receiver<Settings>,
+    library_override: &[PathBuf],
+) -> ExitCode {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        let registered = unsafe {
+            signal_hook_registry::register(signal, || SHUTDOWN.store(true, Ordering::SeqCst))
+        };
+        if let Err(e) = registered {
+            error!("Unable to handle signal {}: {}", signal, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    info!("Running headless, stop with SIGTERM");
+
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        let Some(mut changed) = settings_rx.try_iter().last() else {
+            continue;
+        };
+        override_library(&mut changed, library_override);
+        player.apply_settings(&settings, &changed);
+        settings = changed;
+    }
+
+    info!("Shutting down");
+    let saved = player.saved_state();
+    player.fade_out(SHUTDOWN_FADE);
+    if let Err(e) = saved.save() {
+        error!("Unable to save the player state: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
     env_logger::init();
+    let cli = Cli::parse();
+    if let Some(command) = &cli.command {
+        return cli::run_command(command);
+    }
+    // only one instance may own the audio device, later launches hand over to it
+    if let Some(exit_code) = cli::forward_to_running_instance(&cli) {
+        return exit_code;
+    }
+
     info!("Music player starting...");
-    let application = Application::new();
+    let mut settings = settings::load_or_default();
+    override_library(&mut settings, &cli.library);
+    let settings_rx = settings::watch();
 
-    // this is a global communication so we can control the state of the icon
-    let (tx_global, rx_global) = mpsc::channel::<PlayerCommand>();
-    let audio_manager = Arc::new(AudioManager::new());
-    let element = PlayElement::new(Arc::clone(&audio_manager), rx_global);
-    let mut list_view = ListView::new(Arc::clone(&audio_manager), tx_global);
-    list_view.load_songs();
+    // claimed before the audio device is opened and the library scanned, so a launch racing
+    // this one past `forward_to_running_instance` finds it
+    let listener = match ipc::bind() {
+        Ok(listener) => Some(listener),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            if let Some(exit_code) = cli::forward_to_running_instance(&cli) {
+                return exit_code;
+            }
+            error!("Unable to start the control socket: {}", e);
+            None
+        }
+        Err(e) => {
+            error!("Unable to start the control socket: {}", e);
+            None
+        }
+    };
 
+    let player = Player::new(&settings);
+    if let Some(volume) = cli.volume {
+        player.set_volume(f32::from(volume) / 100.0);
+    }
+    player.set_shuffle(cli.shuffle);
+    if !cli.paths.is_empty() {
+        player.open(library::expand(&cli.paths));
+    } else if cli.headless
+        && let Some(saved) = SavedState::load()
+    {
+        player.restore(saved);
+    }
+    let (focus_tx, focus_rx) = mpsc::channel();
+    // forwarded requests are answered while the library is scanned, they don't time out
+    if let Some(listener) = listener {
+        ipc::serve(listener, Arc::clone(&player), focus_tx.clone());
+    }
+    player.scan_library(&settings.library.roots);
+    #[cfg(target_os = "linux")]
+    if let Err(e) = mpris::serve(
+        Arc::clone(&player),
+        cli.has_window().then(|| focus_tx.clone()),
+    ) {
+        error!("Unable to register on the session bus: {}", e);
+    }
+    if settings.mpd.enabled
+        && let Err(e) = mpd::serve(Arc::clone(&player), &settings.mpd)
+    {
+        error!("Unable to start the MPD server: {}", e);
+    }
+    if settings.http.enabled
+        && let Err(e) = http::serve(Arc::clone(&player), &settings.http)
+    {
+        error!("Unable to start the HTTP server: {}", e);
+    }
+
+    if cli.no_gui {
+        drop(focus_rx);
+        return run_in_terminal(&player, !cli.paths.is_empty());
+    }
+    if cli.tui {
+        drop(focus_rx);
+        if let Err(e) = tui::run(&player, settings, &settings_rx, &cli.library) {
+            eprintln!("music-player: unable to run the terminal UI: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    if cli.headless {
+        drop(focus_rx);
+        return run_daemon(&player, settings, &settings_rx, &cli.library);
+    }
+
+    let application = Application::new().with_assets(Assets);
+    let player_events = player.subscribe();
+    let element = PlayElement::new(Arc::clone(&player));
+    let list_view = ListView::new(Arc::clone(&player));
+    let library_override = cli.library;
     application.run(move |app| {
-        let bounds = Bounds::centered(None, size(px(800.0), px(800.0)), app);
+        let window_size = size(
+            px(settings.appearance.window_width),
+            px(settings.appearance.window_height),
+        );
+        keymap::bind_keys(&settings, app);
+        app.set_global(GlobalSettings(settings));
+        theme::apply(app);
+        app.observe_global::<GlobalSettings>(|app| {
+            let settings = app.global::<GlobalSettings>().0.clone();
+            keymap::bind_keys(&settings, app);
+            theme::apply(app);
+        })
+        .detach();
+
+        let analysis_player = Arc::clone(&player);
+        let rate_player = Arc::clone(&player);
+        let loop_player = Arc::clone(&player);
+        let sleep_player = Arc::clone(&player);
+        let bounds = Bounds::centered(None, window_size, app);
         let window_options = WindowOptions {
             window_bounds: Some(WindowBounds::Windowed(bounds)),
             ..Default::default()
         };
         let window = app
-            .open_window(window_options, |_, cx| {
-                cx.new(|cx| MusicPlayer {
-                    play_btn: cx.new(|_| element),
-                    songs_list: cx.new(|_| list_view),
+            .open_window(window_options, |window, cx| {
+                cx.new(|cx| {
+                    let play_btn = cx.new(|_| element);
+                    let songs_list = cx.new(|_| list_view);
+                    MusicPlayer::new(play_btn, songs_list, player, window, cx)
                 })
             })
             .unwrap();
-        let _view = window.update(app, |_, _, cx| cx.entity()).unwrap();
+        let view = window.update(app, |_, _, cx| cx.entity()).unwrap();
+
+        // another launch forwarded its arguments, bring the window to the front for it
+        app.spawn(async move |cx| {
+            loop {
+                cx.background_executor()
+                    .timer(PLAYER_EVENT_POLL_INTERVAL)
+                    .await;
+                if focus_rx.try_iter().count() == 0 {
+                    continue;
+                }
+                let focused = window.update(cx, |_, window, cx| {
+                    cx.activate(true);
+                    window.activate_window();
+                });
+                if focused.is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+        let play_btn = view.read(app).play_btn.clone();
         app.activate(true);
         app.on_action(|_: &Quit, app| app.quit());
-        app.bind_keys([KeyBinding::new("cmd-q", Quit, None)]);
-        app.bind_keys([KeyBinding::new("ctrl-c", Quit, None)]);
+        app.on_action(|_: &Preferences, app| preferences_view::open(app));
+        app.on_action(move |_: &AnalyzeLoudness, app| {
+            let write_tags = app.global::<GlobalSettings>().0.library.write_gain_tags;
+            if !analysis_player.analyze_library(write_tags) {
+                info!("A loudness analysis is already running");
+            }
+        });
+        app.on_action({
+            let player = Arc::clone(&rate_player);
+            // rounded, so repeated steps don't drift
+            move |_: &SpeedUp, _| {
+                let speed = player.rate().speed + SPEED_STEP;
+                player.set_speed((speed * 10.0).round() / 10.0);
+            }
+        });
+        app.on_action({
+            let player = Arc::clone(&rate_player);
+            move |_: &SpeedDown, _| {
+                let speed = player.rate().speed - SPEED_STEP;
+                player.set_speed((speed * 10.0).round() / 10.0);
+            }
+        });
+        app.on_action({
+            let player = Arc::clone(&rate_player);
+            move |_: &PitchUp, _| player.set_pitch(player.rate().pitch_semitones + PITCH_STEP)
+        });
+        app.on_action({
+            let player = Arc::clone(&rate_player);
+            move |_: &PitchDown, _| player.set_pitch(player.rate().pitch_semitones - PITCH_STEP)
+        });
+        app.on_action(move |_: &ResetRate, _| {
+            rate_player.set_speed(1.0);
+            rate_player.set_pitch(0.0);
+        });
+        app.on_action({
+            let player = Arc::clone(&loop_player);
+            move |_: &SetLoopStart, _| player.set_loop_start()
+        });
+        app.on_action({
+            let player = Arc::clone(&loop_player);
+            move |_: &SetLoopEnd, _| {
+                player.set_loop_end();
+            }
+        });
+        app.on_action({
+            let player = Arc::clone(&loop_player);
+            move |_: &ClearLoop, _| player.clear_ab_loop()
+        });
+        app.on_action(move |_: &SaveLoop, _| {
+            loop_player.save_loop(None);
+        });
+        app.on_action({
+            let player = Arc::clone(&sleep_player);
+            move |_: &SleepTimer, _| player.cycle_sleep_timer()
+        });
+        app.on_action(move |_: &CancelSleepTimer, _| sleep_player.cancel_sleep_timer());
+        app.set_menus(vec![Menu {
+            name: "Music Player".into(),
+            items: vec![
+                MenuItem::action("Preferences…", Preferences),
+                MenuItem::action("Analyze Loudness", AnalyzeLoudness),
+                MenuItem::separator(),
+                MenuItem::action("Faster", SpeedUp),
+                MenuItem::action("Slower", SpeedDown),
+                MenuItem::action("Normal Speed and Pitch", ResetRate),
+                MenuItem::separator(),
+                MenuItem::action("Set Loop Start", SetLoopStart),
+                MenuItem::action("Set Loop End", SetLoopEnd),
+                MenuItem::action("Save Loop", SaveLoop),
+                MenuItem::action("Clear Loop", ClearLoop),
+                MenuItem::separator(),
+                MenuItem::action("Sleep Timer", SleepTimer),
+                MenuItem::action("Cancel Sleep Timer", CancelSleepTimer),
+                MenuItem::separator(),
+                MenuItem::action("Quit", Quit),
+            ],
+        }]);
+        app.on_action({
+            let play_btn = play_btn.clone();
+            move |_: &PlayPause, app| play_btn.update(app, |element, cx| element.toggle(cx))
+        });
+        app.on_action({
+            let play_btn = play_btn.clone();
+            move |_: &SeekForward, app| play_btn.read(app).seek_forward()
+        });
+        app.on_action({
+            let play_btn = play_btn.clone();
+            move |_: &SeekBackward, app| play_btn.read(app).seek_backward()
+        });
+
+        // keep the play button, the song list and the seek bar in sync with changes made from
+        // the list, the queue or the control socket
+        let songs_list = view.read(app).songs_list.clone();
+        let seek_bar = view.read(app).seek_bar.clone();
+        app.spawn(async move |cx| {
+            loop {
+                cx.background_executor()
+                    .timer(PLAYER_EVENT_POLL_INTERVAL)
+                    .await;
+                let mut state = None;
+                let mut library_changed = false;
+                let mut notice = None;
+                // `Some(None)` once the analysis is over
+                let mut analysis = None;
+                let mut rate = None;
+                let mut sleep_changed = false;
+                for event in player_events.try_iter() {
+                    match event {
+                        PlayerEvent::StateChanged(new_state) => state = Some(new_state),
+                        PlayerEvent::LibraryChanged => library_changed = true,
+                        PlayerEvent::OutputFallback(message) => notice = Some(message),
+                        PlayerEvent::AnalysisProgress { done, total } => {
+                            analysis = Some(Some(format!("Analyzing loudness {}/{}", done, total)))
+                        }
+                        PlayerEvent::AnalysisFinished(_) => analysis = Some(None),
+                        PlayerEvent::RateChanged(new_rate) => rate = Some(new_rate),
+                        PlayerEvent::SleepTimerChanged(_) => sleep_changed = true,
+                        _ => {}
+                    }
+                }
+
+                // the position moves on without events
+                if seek_bar.update(cx, |_, cx| cx.notify()).is_err() {
+                    break;
+                }
+                // so does the time left of the sleep timer
+                let updated = view.update(cx, |view, cx| {
+                    if sleep_changed || view.player.sleep_timer().is_some() {
+                        cx.notify();
+                    }
+                });
+                if updated.is_err() {
+                    break;
+                }
+                if let Some(state) = state {
+                    let updated = play_btn.update(cx, |element, cx| {
+                        element.set_playing(state == PlaybackState::Playing, cx)
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+                if library_changed {
+                    let updated = songs_list.update(cx, |list, cx| {
+                        list.reload();
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+                if let Some(analysis) = analysis {
+                    let updated = view.update(cx, |view, cx| {
+                        view.analysis = analysis.map(SharedString::from);
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+                if let Some(rate) = rate {
+                    let updated = view.update(cx, |view, cx| {
+                        view.rate = rate_label(rate);
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+                if let Some(notice) = notice {
+                    let updated = view.update(cx, |view, cx| {
+                        view.notice = Some(notice.into());
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+            }
+        })
+        .detach();
+
+        // pick up edits of the settings file made while the player is running
+        app.spawn(async move |cx| {
+            loop {
+                cx.background_executor().timer(SETTINGS_POLL_INTERVAL).await;
+                let Some(mut settings) = settings_rx.try_iter().last() else {
+                    continue;
+                };
+                override_library(&mut settings, &library_override);
+                if cx
+                    .update(|cx| cx.set_global(GlobalSettings(settings)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        })
+        .detach();
     });
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,1232 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::iter::Peekable;
+use std::net::{TcpListener, TcpStream};
+use std::path::{self, Path, PathBuf};
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use player_core::library::{self, SongInfo};
+use player_core::player::{LoopMode, PlaybackState, Player, PlayerEvent, PlayerStatus};
+use player_core::settings::MpdSettings;
+
+/// Sent to every client on connect, the protocol version decides which commands clients use
+const GREETING: &str = "OK MPD 0.23.5\n";
+
+/// Commands understood by the server, reported by `commands`. The ids of `addid`, `playid`,
+/// `seekid` and `playlistid` are queue positions: the queue keeps no ids of its own, so an id
+/// stays valid only until the queue is replaced.
+const COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "commands",
+    "currentsong",
+    "decoders",
+    "find",
+    "findadd",
+    "idle",
+    "list",
+    "listplaylists",
+    "lsinfo",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "plchangesposid",
+    "previous",
+    "random",
+    "repeat",
+    "search",
+    "searchadd",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "single",
+    "stats",
+    "status",
+    "stop",
+    "tagtypes",
+    "urlhandlers",
+];
+
+/// Longest accepted command line, longer ones close the connection
+const MAX_LINE_LEN: usize = 64 * 1024;
+/// Most bytes of commands collected in one command list, like MPD's `max_command_list_size`
+const MAX_COMMAND_LIST_SIZE: usize = 2 * 1024 * 1024;
+
+/// Tags that can be listed and searched
+const TAG_TYPES: &[&str] = &["Artist", "Album", "Title"];
+
+/// MPD error codes sent in `ACK` responses
+const ACK_ERROR_NOT_LIST: u32 = 1;
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_NO_EXIST: u32 = 50;
+
+/// An error answered with `ACK [code@index] {command} message`
+#[derive(Debug)]
+struct Ack {
+    code: u32,
+    message: String,
+}
+
+impl Ack {
+    fn arg(message: impl Into<String>) -> Self {
+        Self {
+            code: ACK_ERROR_ARG,
+            message: message.into(),
+        }
+    }
+
+    fn no_exist(message: impl Into<String>) -> Self {
+        Self {
+            code: ACK_ERROR_NO_EXIST,
+            message: message.into(),
+        }
+    }
+}
+
+/// State shared by all connections
+struct Server {
+    player: Arc<Player>,
+    /// Bumped on every queue change, reported as `playlist` in `status`
+    playlist_version: AtomicU32,
+    started: Instant,
+}
+
+/// Start serving the MPD protocol on a background thread
+pub(crate) fn serve(player: Arc<Player>, settings: &MpdSettings) -> io::Result<()> {
+    let listener = TcpListener::bind((settings.bind_address.as_str(), settings.port))?;
+    info!(
+        "MPD server listening on {}:{}",
+        settings.bind_address, settings.port
+    );
+
+    let events = player.subscribe();
+    let server = Arc::new(Server {
+        player,
+        playlist_version: AtomicU32::new(1),
+        started: Instant::now(),
+    });
+
+    thread::spawn({
+        let server = Arc::clone(&server);
+        move || {
+            for event in events {
+                if let PlayerEvent::QueueChanged = event {
+                    server.playlist_version.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = Arc::clone(&server);
+                    thread::spawn(move || {
+                        if let Err(e) = handle_client(stream, &server) {
+                            error!("MPD client error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("MPD server error: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// What a client connection waits for
+enum Input {
+    Line(String),
+    /// A line longer than `MAX_LINE_LEN`
+    LineTooLong,
+    Event(PlayerEvent),
+    Closed,
+}
+
+/// The `idle` subsystem a player event belongs to
+fn subsystems(event: &PlayerEvent) -> &'static [&'static str] {
+    match event {
+        PlayerEvent::StateChanged(_) | PlayerEvent::TrackChanged(_) | PlayerEvent::Seeked(_) => {
+            &["player"]
+        }
+        PlayerEvent::VolumeChanged(_) => &["mixer"],
+        // shuffle is a queue change too
+        PlayerEvent::QueueChanged => &["playlist", "options"],
+        PlayerEvent::LoopChanged(_) => &["options"],
+        PlayerEvent::LibraryChanged => &["database"],
+        PlayerEvent::OutputFallback(_) => &["output"],
+        PlayerEvent::RateChanged(_)
+        | PlayerEvent::AbLoopChanged(_)
+        | PlayerEvent::LoopsChanged
+        | PlayerEvent::SleepTimerChanged(_)
+        | PlayerEvent::ChannelsChanged(_)
+        | PlayerEvent::AnalysisProgress { .. }
+        | PlayerEvent::AnalysisFinished(_) => &[],
+    }
+}
+
+fn handle_client(stream: TcpStream, server: &Server) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let (input_tx, input_rx) = mpsc::channel();
+
+    // lines and player events arrive on the same channel so `idle` can wait for either
+    thread::spawn({
+        let input_tx = input_tx.clone();
+        move || {
+            let mut reader = BufReader::new(stream);
+            loop {
+                let input = match read_line(&mut reader) {
+                    Ok(Some(line)) => Input::Line(line),
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => Input::LineTooLong,
+                    Ok(None) | Err(_) => Input::Closed,
+                };
+                let last = !matches!(input, Input::Line(_));
+                if input_tx.send(input).is_err() || last {
+                    return;
+                }
+            }
+        }
+    });
+    forward_events(&server.player, input_tx);
+
+    writer.write_all(GREETING.as_bytes())?;
+
+    let mut session = Session {
+        server,
+        changed: BTreeSet::new(),
+        idle: None,
+        command_list: None,
+    };
+    for input in input_rx {
+        let reply = match input {
+            Input::Line(line) => match session.line(&line) {
+                Some(reply) => reply,
+                None => return Ok(()),
+            },
+            Input::Event(event) => {
+                session.changed.extend(subsystems(&event));
+                session.idle_reply().unwrap_or_default()
+            }
+            Input::LineTooLong => {
+                let ack = Ack::arg("line too long");
+                return writer.write_all(ack_line(&ack, 0, "").as_bytes());
+            }
+            Input::Closed => return Ok(()),
+        };
+        if !reply.is_empty() {
+            writer.write_all(reply.as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one line without its line break, `None` at the end of the stream. Lines longer than
+/// `MAX_LINE_LEN` are an `InvalidData` error.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    if line.len() > MAX_LINE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Pass the player events on to a connection until it is closed
+fn forward_events(player: &Player, input_tx: Sender<Input>) {
+    let events = player.subscribe();
+    thread::spawn(move || {
+        for event in events {
+            if input_tx.send(Input::Event(event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// The protocol state of one connection
+struct Session<'a> {
+    server: &'a Server,
+    /// Subsystems changed since the last `idle`
+    changed: BTreeSet<&'static str>,
+    /// Subsystems an `idle` command waits for, empty for all of them
+    idle: Option<Vec<String>>,
+    /// Commands collected between `command_list_begin` and `command_list_end`
+    command_list: Option<CommandList>,
+}
+
+/// The commands of a command list, collected until `command_list_end`
+#[derive(Default)]
+struct CommandList {
+    commands: Vec<Vec<String>>,
+    /// Whether `list_OK` is sent after each command
+    list_ok: bool,
+    /// Bytes of all commands added, the commands are dropped once it exceeds
+    /// `MAX_COMMAND_LIST_SIZE`
+    size: usize,
+}
+
+impl CommandList {
+    fn new(list_ok: bool) -> Self {
+        Self {
+            list_ok,
+            ..Self::default()
+        }
+    }
+
+    fn push(&mut self, line: &str, args: Vec<String>) {
+        self.size += line.len();
+        if self.is_too_large() {
+            self.commands = Vec::new();
+        } else {
+            self.commands.push(args);
+        }
+    }
+
+    fn is_too_large(&self) -> bool {
+        self.size > MAX_COMMAND_LIST_SIZE
+    }
+}
+
+impl Session<'_> {
+    /// Handle one line from the client, returns the reply or `None` to close the connection
+    fn line(&mut self, line: &str) -> Option<String> {
+        let args = match tokenize(line) {
+            Ok(args) => args,
+            Err(ack) => return Some(ack_line(&ack, 0, "")),
+        };
+        let Some(command) = args.first().map(String::as_str) else {
+            return Some(ack_line(&Ack::arg("no command given"), 0, ""));
+        };
+
+        if self.idle.is_some() {
+            // only `noidle` is allowed while idling
+            self.idle = None;
+            return Some(if command == "noidle" {
+                self.take_changed(None)
+            } else {
+                ack_line(
+                    &Ack::arg("only \"noidle\" is allowed during idle"),
+                    0,
+                    command,
+                )
+            });
+        }
+
+        if let Some(list) = &mut self.command_list {
+            if command != "command_list_end" {
+                list.push(line, args);
+                return Some(String::new());
+            }
+            let list = self.command_list.take().unwrap();
+            if list.is_too_large() {
+                return Some(ack_line(&Ack::arg("command list is too large"), 0, ""));
+            }
+            return Some(self.run_list(&list.commands, list.list_ok));
+        }
+
+        match command {
+            "close" => None,
+            "command_list_begin" => {
+                self.command_list = Some(CommandList::new(false));
+                Some(String::new())
+            }
+            "command_list_ok_begin" => {
+                self.command_list = Some(CommandList::new(true));
+                Some(String::new())
+            }
+            "idle" => {
+                self.idle = Some(args[1..].iter().map(|s| s.to_lowercase()).collect());
+                Some(self.idle_reply().unwrap_or_default())
+            }
+            "noidle" => Some(String::from("OK\n")),
+            _ => Some(match self.execute(&args) {
+                Ok(mut reply) => {
+                    reply.push_str("OK\n");
+                    reply
+                }
+                Err(ack) => ack_line(&ack, 0, command),
+            }),
+        }
+    }
+
+    fn run_list(&mut self, commands: &[Vec<String>], list_ok: bool) -> String {
+        let mut reply = String::new();
+        for (index, args) in commands.iter().enumerate() {
+            let command = args[0].as_str();
+            let result = match command {
+                "idle" | "noidle" | "close" | "command_list_begin" | "command_list_ok_begin" => {
+                    Err(Ack {
+                        code: ACK_ERROR_NOT_LIST,
+                        message: format!("\"{}\" is not allowed in a command list", command),
+                    })
+                }
+                _ => self.execute(args),
+            };
+            match result {
+                Ok(output) => {
+                    reply.push_str(&output);
+                    if list_ok {
+                        reply.push_str("list_OK\n");
+                    }
+                }
+                Err(ack) => {
+                    reply.push_str(&ack_line(&ack, index, command));
+                    return reply;
+                }
+            }
+        }
+        reply.push_str("OK\n");
+        reply
+    }
+
+    /// Answer a pending `idle` if one of the subsystems it waits for changed
+    fn idle_reply(&mut self) -> Option<String> {
+        let wanted = self.idle.as_ref()?;
+        let matching = self
+            .changed
+            .iter()
+            .any(|subsystem| wanted.is_empty() || wanted.iter().any(|w| w == subsystem));
+        if !matching {
+            return None;
+        }
+        let wanted = self.idle.take();
+        Some(self.take_changed(wanted.as_deref()))
+    }
+
+    /// List and forget the changed subsystems, only the `wanted` ones when given
+    fn take_changed(&mut self, wanted: Option<&[String]>) -> String {
+        let mut reply = String::new();
+        self.changed.retain(|subsystem| {
+            let report = wanted
+                .is_none_or(|wanted| wanted.is_empty() || wanted.iter().any(|w| w == subsystem));
+            if report {
+                let _ = writeln!(reply, "changed: {}", subsystem);
+            }
+            !report
+        });
+        reply.push_str("OK\n");
+        reply
+    }
+
+    fn execute(&mut self, args: &[String]) -> Result<String, Ack> {
+        let player = &self.server.player;
+        let mut reply = String::new();
+
+        match args[0].as_str() {
+            "ping" => {}
+            "commands" => {
+                for command in COMMANDS {
+                    let _ = writeln!(reply, "command: {}", command);
+                }
+            }
+            "notcommands" => {}
+            "tagtypes" => {
+                for tag in TAG_TYPES {
+                    let _ = writeln!(reply, "tagtype: {}", tag);
+                }
+            }
+            "urlhandlers" => reply.push_str("handler: file://\n"),
+            "decoders" => reply.push_str(
+                "plugin: symphonia\nsuffix: mp3\nsuffix: flac\nsuffix: ogg\nsuffix: oga\n\
+                 suffix: wav\nmime_type: audio/mpeg\nmime_type: audio/flac\nmime_type: audio/ogg\n\
+                 mime_type: audio/wav\n",
+            ),
+            "outputs" => reply
+                .push_str("outputid: 0\noutputname: default\nplugin: rodio\noutputenabled: 1\n"),
+            "listplaylists" => {}
+            "status" => reply = self.status(),
+            "stats" => {
+                let library = player.library();
+                let artists = library.iter().filter_map(|s| s.artist.as_ref());
+                let albums = library.iter().filter_map(|s| s.album.as_ref());
+                let _ = write!(
+                    reply,
+                    "artists: {}\nalbums: {}\nsongs: {}\nuptime: {}\nplaytime: 0\n\
+                     db_playtime: 0\ndb_update: 0\n",
+                    artists.collect::<BTreeSet<_>>().len(),
+                    albums.collect::<BTreeSet<_>>().len(),
+                    library.len(),
+                    self.server.started.elapsed().as_secs(),
+                );
+            }
+            "currentsong" => {
+                let status = player.status();
+                if let (Some(track), Some(index)) = (&status.track, status.queue_position) {
+                    self.write_song(&mut reply, &self.song(track), Some(index));
+                    if let Some(duration) = status.duration_ms {
+                        let secs = duration as f64 / 1000.0;
+                        let _ = write!(reply, "Time: {}\nduration: {:.3}\n", secs.round(), secs);
+                    }
+                }
+            }
+            "play" | "playid" => match args.get(1) {
+                Some(index) => {
+                    let index = parse_index(index)?;
+                    if !player.jump(index) {
+                        return Err(Ack::no_exist("No such song"));
+                    }
+                }
+                None => player.play(),
+            },
+            "pause" => match args.get(1).map(String::as_str) {
+                Some("1") => player.pause(),
+                Some("0") => player.play(),
+                Some(other) => return Err(Ack::arg(format!("Boolean (0/1) expected: {}", other))),
+                None => player.toggle(),
+            },
+            "stop" => player.stop(),
+            "next" => player.next(),
+            "previous" => player.previous(),
+            "seek" | "seekid" => {
+                let index = parse_index(required(args, 1)?)?;
+                let position = parse_time(required(args, 2)?)?;
+                if player.status().queue_position != Some(index) && !player.jump(index) {
+                    return Err(Ack::no_exist("No such song"));
+                }
+                player.seek_to(position);
+            }
+            "seekcur" => {
+                let time = required(args, 1)?;
+                let position = match time.chars().next() {
+                    Some(sign @ ('+' | '-')) => {
+                        let offset = parse_time(&time[1..])?;
+                        let current = Duration::from_millis(player.status().position_ms);
+                        if sign == '+' {
+                            current + offset
+                        } else {
+                            current.saturating_sub(offset)
+                        }
+                    }
+                    _ => parse_time(time)?,
+                };
+                player.seek_to(position);
+            }
+            "setvol" => {
+                let volume = required(args, 1)?
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|volume| *volume <= 100)
+                    .ok_or_else(|| Ack::arg("Invalid volume value"))?;
+                player.set_volume(f32::from(volume) / 100.0);
+            }
+            "random" => player.set_shuffle(parse_bool(required(args, 1)?)?),
+            "repeat" => {
+                let mode = match (parse_bool(required(args, 1)?)?, player.loop_mode()) {
+                    (false, _) => LoopMode::None,
+                    (true, LoopMode::Track) => LoopMode::Track,
+                    (true, _) => LoopMode::Playlist,
+                };
+                player.set_loop_mode(mode);
+            }
+            "single" => {
+                // only "repeat the current track" is supported, as single + repeat
+                let mode = match required(args, 1)?.as_str() {
+                    "1" => LoopMode::Track,
+                    "0" if player.loop_mode() == LoopMode::Track => LoopMode::Playlist,
+                    "0" => player.loop_mode(),
+                    other => return Err(Ack::arg(format!("Unsupported single mode: {}", other))),
+                };
+                player.set_loop_mode(mode);
+            }
+            "playlistinfo" | "playlistid" | "plchanges" | "plchangesposid" => {
+                // every change is reported as a change of the whole queue
+                let only = match args[0].as_str() {
+                    "playlistinfo" | "playlistid" => {
+                        args.get(1).map(|i| parse_index(i)).transpose()?
+                    }
+                    _ => None,
+                };
+                let queue = player.queue();
+                if only.is_some_and(|index| index >= queue.len()) {
+                    return Err(Ack::no_exist("Bad song index"));
+                }
+                let library = self.library_by_path();
+                for (index, track) in queue.iter().enumerate() {
+                    if only.is_some_and(|only| only != index) {
+                        continue;
+                    }
+                    if args[0] == "plchangesposid" {
+                        let _ = write!(reply, "cpos: {}\nId: {}\n", index, index);
+                        continue;
+                    }
+                    let song = library
+                        .get(track)
+                        .cloned()
+                        .unwrap_or_else(|| SongInfo::read(track));
+                    self.write_song(&mut reply, &song, Some(index));
+                }
+            }
+            "add" | "addid" => {
+                let tracks = library::expand(&[self.resolve(required(args, 1)?)?]);
+                if tracks.is_empty() {
+                    return Err(Ack::no_exist("No such directory"));
+                }
+                let first_id = player.queue().len();
+                player.enqueue(tracks);
+                if args[0] == "addid" {
+                    let _ = writeln!(reply, "Id: {}", first_id);
+                }
+            }
+            "clear" => player.clear_queue(),
+            "list" => {
+                let tag = Tag::parse(required(args, 1)?)?;
+                let filters = parse_filters(list_filter_args(&args[2..]))?;
+                let values = player
+                    .library()
+                    .iter()
+                    .filter(|song| filters.iter().all(|f| f.matches(song, self, false)))
+                    .filter_map(|song| tag.value(song, self))
+                    .collect::<BTreeSet<_>>();
+                for value in values {
+                    let _ = writeln!(reply, "{}: {}", tag.name(), value);
+                }
+            }
+            command @ ("find" | "search" | "findadd" | "searchadd") => {
+                let ignore_case = command.starts_with("search");
+                let filters = parse_filters(&args[1..])?;
+                let songs = player
+                    .library()
+                    .into_iter()
+                    .filter(|song| filters.iter().all(|f| f.matches(song, self, ignore_case)))
+                    .collect::<Vec<_>>();
+                if command.ends_with("add") {
+                    player.enqueue(songs.into_iter().map(|song| song.path).collect());
+                } else {
+                    for song in &songs {
+                        self.write_song(&mut reply, song, None);
+                    }
+                }
+            }
+            "lsinfo" => reply = self.lsinfo(args.get(1).map(String::as_str).unwrap_or(""))?,
+            command => {
+                return Err(Ack {
+                    code: ACK_ERROR_UNKNOWN,
+                    message: format!("unknown command \"{}\"", command),
+                });
+            }
+        }
+
+        Ok(reply)
+    }
+
+    fn status(&self) -> String {
+        let status: PlayerStatus = self.server.player.status();
+        let mut reply = String::new();
+        let _ = write!(
+            reply,
+            "volume: {}\nrepeat: {}\nrandom: {}\nsingle: {}\nconsume: 0\nplaylist: {}\n\
+             playlistlength: {}\nstate: {}\n",
+            (status.volume * 100.0).round(),
+            u8::from(status.loop_mode != LoopMode::None),
+            u8::from(status.shuffle),
+            u8::from(status.loop_mode == LoopMode::Track),
+            self.server.playlist_version.load(Ordering::Relaxed),
+            status.queue_length,
+            match status.state {
+                PlaybackState::Playing => "play",
+                PlaybackState::Paused => "pause",
+                PlaybackState::Stopped => "stop",
+            },
+        );
+        if let Some(index) = status.queue_position {
+            let _ = write!(reply, "song: {}\nsongid: {}\n", index, index);
+        }
+        if status.state != PlaybackState::Stopped {
+            let elapsed = status.position_ms as f64 / 1000.0;
+            let _ = writeln!(reply, "elapsed: {:.3}", elapsed);
+            if let Some(duration) = status.duration_ms {
+                let duration = duration as f64 / 1000.0;
+                let _ = write!(
+                    reply,
+                    "time: {}:{}\nduration: {:.3}\n",
+                    elapsed as u64,
+                    duration.round() as u64,
+                    duration
+                );
+            }
+        }
+        reply
+    }
+
+    /// List the folders and songs below the library folder `uri`
+    fn lsinfo(&self, uri: &str) -> Result<String, Ack> {
+        let dirs = if uri.is_empty() || uri == "/" {
+            self.server.player.library_roots()
+        } else {
+            vec![self.resolve(uri)?]
+        };
+        if !dirs.iter().any(|dir| dir.is_dir()) {
+            return Err(Ack::no_exist("No such directory"));
+        }
+
+        let mut reply = String::new();
+        for dir in dirs {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            let mut paths = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect::<Vec<_>>();
+            paths.sort();
+            for path in paths {
+                if path.is_dir() {
+                    let _ = writeln!(reply, "directory: {}", self.uri(&path));
+                } else if library::is_audio_file(&path) {
+                    self.write_song(&mut reply, &SongInfo::read(&path), None);
+                }
+            }
+        }
+        Ok(reply)
+    }
+
+    fn write_song(&self, reply: &mut String, song: &SongInfo, position: Option<usize>) {
+        let _ = writeln!(reply, "file: {}", self.uri(&song.path));
+        if let Some(title) = &song.title {
+            let _ = writeln!(reply, "Title: {}", title);
+        }
+        if let Some(artist) = &song.artist {
+            let _ = writeln!(reply, "Artist: {}", artist);
+        }
+        if let Some(album) = &song.album {
+            let _ = writeln!(reply, "Album: {}", album);
+        }
+        if let Some(position) = position {
+            let _ = write!(reply, "Pos: {}\nId: {}\n", position, position);
+        }
+    }
+
+    /// Tags of `track`, from the library when it is part of it
+    fn song(&self, track: &Path) -> SongInfo {
+        self.server
+            .player
+            .library()
+            .into_iter()
+            .find(|song| song.path == track)
+            .unwrap_or_else(|| SongInfo::read(track))
+    }
+
+    fn library_by_path(&self) -> HashMap<PathBuf, SongInfo> {
+        self.server
+            .player
+            .library()
+            .into_iter()
+            .map(|song| (song.path.clone(), song))
+            .collect()
+    }
+
+    /// MPD names songs by their path relative to the music folder, files outside the library
+    /// roots keep their absolute path
+    fn uri(&self, path: &Path) -> String {
+        for root in self.server.player.library_roots() {
+            let root = path::absolute(&root).unwrap_or(root);
+            if let Ok(relative) = path.strip_prefix(&root) {
+                return relative.to_string_lossy().into_owned();
+            }
+        }
+        path.to_string_lossy().into_owned()
+    }
+
+    /// The file or folder named by a client supplied `uri`
+    fn resolve(&self, uri: &str) -> Result<PathBuf, Ack> {
+        resolve(&self.server.player.library_roots(), uri)
+    }
+}
+
+/// Find `uri` below one of `roots`. Symlinks and `..` are resolved first so clients cannot reach
+/// files outside the library.
+fn resolve(roots: &[PathBuf], uri: &str) -> Result<PathBuf, Ack> {
+    let uri = uri.strip_prefix("file://").unwrap_or(uri);
+    for root in roots {
+        let Ok(canonical_root) = fs::canonicalize(root) else {
+            continue;
+        };
+        // joining an absolute `uri` replaces the root
+        let Ok(path) = fs::canonicalize(root.join(uri)) else {
+            continue;
+        };
+        if let Ok(relative) = path.strip_prefix(&canonical_root) {
+            // keep the root as configured so `Session::uri` can strip it again
+            return Ok(root.join(relative));
+        }
+    }
+    Err(Ack::no_exist("No such file or directory"))
+}
+
+fn ack_line(ack: &Ack, index: usize, command: &str) -> String {
+    format!(
+        "ACK [{}@{}] {{{}}} {}\n",
+        ack.code, index, command, ack.message
+    )
+}
+
+fn required(args: &[String], index: usize) -> Result<&String, Ack> {
+    args.get(index)
+        .ok_or_else(|| Ack::arg(format!("missing argument for \"{}\"", args[0])))
+}
+
+fn parse_index(value: &str) -> Result<usize, Ack> {
+    value
+        .parse()
+        .map_err(|_| Ack::arg(format!("Integer expected: {}", value)))
+}
+
+fn parse_bool(value: &str) -> Result<bool, Ack> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(Ack::arg(format!("Boolean (0/1) expected: {}", value))),
+    }
+}
+
+/// Times are seconds with an optional fraction
+fn parse_time(value: &str) -> Result<Duration, Ack> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| Ack::arg(format!("Number expected: {}", value)))
+}
+
+/// Split a command line into its arguments, which may be double quoted with `\` escapes
+fn tokenize(line: &str) -> Result<Vec<String>, Ack> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => arg.push(escaped),
+                        None => return Err(Ack::arg("Unterminated quoted string")),
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err(Ack::arg("Unterminated quoted string")),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+/// A tag that songs can be listed and searched by
+#[derive(Clone, Copy, PartialEq)]
+enum Tag {
+    Artist,
+    Album,
+    Title,
+    File,
+    /// Any of the above, for searching only
+    Any,
+}
+
+impl Tag {
+    fn parse(name: &str) -> Result<Self, Ack> {
+        match name.to_lowercase().as_str() {
+            "artist" | "albumartist" => Ok(Self::Artist),
+            "album" => Ok(Self::Album),
+            "title" => Ok(Self::Title),
+            "file" => Ok(Self::File),
+            "any" => Ok(Self::Any),
+            _ => Err(Ack::arg(format!("Unknown tag type: {}", name))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Artist => "Artist",
+            Self::Album => "Album",
+            Self::Title => "Title",
+            Self::File | Self::Any => "file",
+        }
+    }
+
+    fn value(self, song: &SongInfo, session: &Session) -> Option<String> {
+        match self {
+            Self::Artist => song.artist.clone(),
+            Self::Album => song.album.clone(),
+            Self::Title => song.title.clone(),
+            Self::File | Self::Any => Some(session.uri(&song.path)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    Equals,
+    NotEquals,
+    Contains,
+}
+
+/// One condition of `find`, `search` and `list`
+struct Filter {
+    tag: Tag,
+    operator: Operator,
+    value: String,
+}
+
+impl Filter {
+    fn matches(&self, song: &SongInfo, session: &Session, ignore_case: bool) -> bool {
+        let tags = match self.tag {
+            Tag::Any => vec![Tag::Artist, Tag::Album, Tag::Title, Tag::File],
+            tag => vec![tag],
+        };
+        let normalize = |s: &str| {
+            if ignore_case {
+                s.to_lowercase()
+            } else {
+                s.to_string()
+            }
+        };
+        let wanted = normalize(&self.value);
+        let found = tags.iter().any(|tag| {
+            tag.value(song, session).is_some_and(|value| {
+                let value = normalize(&value);
+                match self.operator {
+                    Operator::Contains => value.contains(&wanted),
+                    // `search` matches substrings in the old syntax too
+                    _ if ignore_case => value.contains(&wanted),
+                    _ => value == wanted,
+                }
+            })
+        });
+        found != (self.operator == Operator::NotEquals)
+    }
+}
+
+/// `list` takes a single value as a filter on the artist in the old syntax, and `group` clauses
+/// which are ignored
+fn list_filter_args(args: &[String]) -> &[String] {
+    let end = args
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case("group"))
+        .unwrap_or(args.len());
+    &args[..end]
+}
+
+/// Parse `TAG VALUE` pairs, or a filter expression like `((artist == 'X') AND (album == 'Y'))`
+fn parse_filters(args: &[String]) -> Result<Vec<Filter>, Ack> {
+    match args {
+        [] => Ok(Vec::new()),
+        [expression] if expression.starts_with('(') => parse_expression(expression),
+        [value] => Ok(vec![Filter {
+            tag: Tag::Artist,
+            operator: Operator::Equals,
+            value: value.clone(),
+        }]),
+        _ if args.len().is_multiple_of(2) => args
+            .chunks(2)
+            .map(|pair| {
+                Ok(Filter {
+                    tag: Tag::parse(&pair[0])?,
+                    operator: Operator::Equals,
+                    value: pair[1].clone(),
+                })
+            })
+            .collect(),
+        _ => Err(Ack::arg("Incorrect number of filter arguments")),
+    }
+}
+
+/// A piece of a filter expression
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    /// A tag name, an operator or `AND`
+    Word(String),
+    /// A value in single or double quotes, with `\` escapes removed
+    Quoted(String),
+}
+
+fn lex_expression(expression: &str) -> Result<Vec<Token>, Ack> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '\'' | '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => value.push(escaped),
+                            None => return Err(Ack::arg("Unterminated quoted string")),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err(Ack::arg("Unterminated quoted string")),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '\'' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse `(TAG OP 'VALUE')` and `(EXPR AND EXPR ...)`. Negation, `OR` and the other operators
+/// are rejected rather than read as something else.
+fn parse_expression(expression: &str) -> Result<Vec<Filter>, Ack> {
+    let tokens = lex_expression(expression)?;
+    let mut tokens = tokens.iter().peekable();
+    let mut filters = Vec::new();
+    parse_group(&mut tokens, &mut filters)?;
+    if let Some(token) = tokens.next() {
+        return Err(Ack::arg(format!(
+            "Unexpected {:?} after the expression",
+            token
+        )));
+    }
+    Ok(filters)
+}
+
+fn parse_group(
+    tokens: &mut Peekable<slice::Iter<'_, Token>>,
+    filters: &mut Vec<Filter>,
+) -> Result<(), Ack> {
+    if tokens.next() != Some(&Token::Open) {
+        return Err(Ack::arg("'(' expected"));
+    }
+
+    if tokens.peek() == Some(&&Token::Open) {
+        parse_group(tokens, filters)?;
+        loop {
+            match tokens.next() {
+                Some(Token::Close) => return Ok(()),
+                Some(Token::Word(word)) if word == "AND" => parse_group(tokens, filters)?,
+                Some(Token::Word(word)) => {
+                    return Err(Ack::arg(format!("Unsupported operator: {}", word)));
+                }
+                Some(token) => return Err(Ack::arg(format!("Unexpected {:?}", token))),
+                None => return Err(Ack::arg("')' expected")),
+            }
+        }
+    }
+
+    let tag = match tokens.next() {
+        Some(Token::Word(tag)) => Tag::parse(tag)?,
+        Some(token) => return Err(Ack::arg(format!("Unsupported filter: {:?}", token))),
+        None => return Err(Ack::arg("Tag expected")),
+    };
+    let operator = match tokens.next() {
+        Some(Token::Word(operator)) => match operator.as_str() {
+            "==" => Operator::Equals,
+            "!=" => Operator::NotEquals,
+            "contains" => Operator::Contains,
+            _ => return Err(Ack::arg(format!("Unsupported operator: {}", operator))),
+        },
+        _ => return Err(Ack::arg("Operator expected")),
+    };
+    let Some(Token::Quoted(value)) = tokens.next() else {
+        return Err(Ack::arg("Quoted value expected"));
+    };
+    if tokens.next() != Some(&Token::Close) {
+        return Err(Ack::arg("')' expected"));
+    }
+
+    filters.push(Filter {
+        tag,
+        operator,
+        value: value.clone(),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(expression: &str) -> Result<Vec<(&'static str, String)>, u32> {
+        parse_expression(expression)
+            .map(|filters| {
+                filters
+                    .into_iter()
+                    .map(|filter| (filter.tag.name(), filter.value))
+                    .collect()
+            })
+            .map_err(|ack| ack.code)
+    }
+
+    #[test]
+    fn tokenize_splits_quoted_arguments() {
+        let args = tokenize(r#"find "artist" "The \"Best\" Band" album x"#).unwrap();
+        assert_eq!(args, ["find", "artist", "The \"Best\" Band", "album", "x"]);
+        assert_eq!(tokenize("  status  ").unwrap(), ["status"]);
+        assert_eq!(tokenize(r#"add "a b"#).unwrap_err().code, ACK_ERROR_ARG);
+    }
+
+    #[test]
+    fn read_line_limits_the_line_length() {
+        let mut reader = io::Cursor::new(b"status\r\nplay 1\nping".to_vec());
+        assert_eq!(read_line(&mut reader).unwrap().unwrap(), "status");
+        assert_eq!(read_line(&mut reader).unwrap().unwrap(), "play 1");
+        assert_eq!(read_line(&mut reader).unwrap().unwrap(), "ping");
+        assert!(read_line(&mut reader).unwrap().is_none());
+
+        let mut endless = BufReader::new(io::repeat(b'a'));
+        assert_eq!(
+            read_line(&mut endless).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn command_list_drops_its_commands_when_too_large() {
+        let line = format!("add \"{}\"", "a".repeat(1000));
+        let mut list = CommandList::new(true);
+        list.push(&line, tokenize(&line).unwrap());
+        assert_eq!(list.commands.len(), 1);
+        assert!(!list.is_too_large());
+
+        while !list.is_too_large() {
+            list.push(&line, tokenize(&line).unwrap());
+        }
+        assert!(list.commands.is_empty());
+        list.push("status", tokenize("status").unwrap());
+        assert!(list.commands.is_empty());
+    }
+
+    #[test]
+    fn expression_values_keep_apostrophes() {
+        assert_eq!(
+            filter(r#"(artist == "Guns N' Roses")"#),
+            Ok(vec![("Artist", "Guns N' Roses".to_string())])
+        );
+        assert_eq!(
+            filter(r"(title contains 'Don\'t Stop')"),
+            Ok(vec![("Title", "Don't Stop".to_string())])
+        );
+    }
+
+    #[test]
+    fn expression_joins_groups_with_and() {
+        assert_eq!(
+            filter("((artist == 'A') AND (album == 'B (Live)'))"),
+            Ok(vec![
+                ("Artist", "A".to_string()),
+                ("Album", "B (Live)".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn expression_rejects_unsupported_syntax() {
+        for expression in [
+            "((artist == 'A') OR (album == 'B'))",
+            "(!(artist == 'A'))",
+            "(artist =~ 'A.*')",
+            "((artist == 'A') AND (album == 'B')",
+            "(artist == 'A'))",
+            "(artist == 'A') (album == 'B')",
+            "(artist == A)",
+            "(artist == 'A)",
+        ] {
+            assert_eq!(filter(expression), Err(ACK_ERROR_ARG), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn resolve_stays_inside_the_roots() {
+        let dir = std::env::temp_dir().join(format!("music-player-mpd-{}", std::process::id()));
+        let root = dir.join("music");
+        fs::create_dir_all(root.join("album")).unwrap();
+        fs::write(root.join("album/song.mp3"), b"").unwrap();
+        fs::write(dir.join("secret.mp3"), b"").unwrap();
+        let roots = [root.clone()];
+
+        assert_eq!(
+            resolve(&roots, "album/song.mp3").unwrap(),
+            root.join("album/song.mp3")
+        );
+        assert_eq!(
+            resolve(&roots, "album/../album/song.mp3").unwrap(),
+            root.join("album/song.mp3")
+        );
+        for uri in [
+            "../secret.mp3",
+            "album/../../secret.mp3",
+            &dir.join("secret.mp3").display().to_string(),
+            &format!("file://{}", dir.join("secret.mp3").display()),
+            "missing.mp3",
+        ] {
+            assert_eq!(
+                resolve(&roots, uri).map_err(|ack| ack.code),
+                Err(ACK_ERROR_NO_EXIST),
+                "{}",
+                uri
+            );
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
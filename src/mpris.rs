@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+use url::Url;
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::object_server::InterfaceRef;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{fdo, interface};
+
+use player_core::library::{self, SongInfo};
+use player_core::player::{LoopMode, PlaybackState, Player, PlayerEvent, SPEED_RANGE};
+
+/// Well-known name on the session bus, `playerctl -p music_player` finds the player by it
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.music_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// Track id reported when nothing is loaded
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Expose the player on the session bus as an MPRIS2 media player. The bus is taken from
+/// `DBUS_SESSION_BUS_ADDRESS`, so a private `dbus-daemon` can be used for testing. `Raise`
+/// requests are passed on to `focus_tx`, the player cannot be raised without it.
+pub(crate) fn serve(player: Arc<Player>, focus_tx: Option<Sender<()>>) -> zbus::Result<()> {
+    let events = player.subscribe();
+    let connection = Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, MediaPlayer { focus_tx })?
+        .serve_at(
+            OBJECT_PATH,
+            MprisPlayer {
+                player: Arc::clone(&player),
+            },
+        )?
+        .build()?;
+    info!("MPRIS interface registered as {}", BUS_NAME);
+
+    let iface = connection
+        .object_server()
+        .interface::<_, MprisPlayer>(OBJECT_PATH)?;
+    thread::spawn(move || {
+        // keeps the bus name while the player is running
+        let _connection: Connection = connection;
+        for event in events {
+            if let Err(e) = zbus::block_on(notify(&iface, event)) {
+                error!("Unable to send MPRIS signal: {}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Tell MPRIS clients about a player change
+async fn notify(iface: &InterfaceRef<MprisPlayer>, event: PlayerEvent) -> zbus::Result<()> {
+    let emitter = iface.signal_emitter();
+    let mpris = iface.get();
+    match event {
+        PlayerEvent::StateChanged(_) => mpris.playback_status_changed(emitter).await,
+        PlayerEvent::TrackChanged(_) => {
+            mpris.metadata_changed(emitter).await?;
+            mpris.can_go_previous_changed(emitter).await?;
+            mpris.can_go_next_changed(emitter).await
+        }
+        PlayerEvent::VolumeChanged(_) => mpris.volume_changed(emitter).await,
+        PlayerEvent::Seeked(position_ms) => {
+            MprisPlayer::seeked(emitter, micros(Duration::from_millis(position_ms))).await
+        }
+        PlayerEvent::QueueChanged => {
+            mpris.shuffle_changed(emitter).await?;
+            mpris.can_play_changed(emitter).await?;
+            mpris.can_go_next_changed(emitter).await
+        }
+        PlayerEvent::LoopChanged(_) => mpris.loop_status_changed(emitter).await,
+        PlayerEvent::RateChanged(_) => mpris.rate_changed(emitter).await,
+        PlayerEvent::LibraryChanged
+        | PlayerEvent::OutputFallback(_)
+        | PlayerEvent::AbLoopChanged(_)
+        | PlayerEvent::LoopsChanged
+        | PlayerEvent::SleepTimerChanged(_)
+        | PlayerEvent::ChannelsChanged(_)
+        | PlayerEvent::AnalysisProgress { .. }
+        | PlayerEvent::AnalysisFinished(_) => Ok(()),
+    }
+}
+
+/// MPRIS positions and lengths are in microseconds
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros() as i64
+}
+
+fn track_id(index: usize) -> ObjectPath<'static> {
+    ObjectPath::try_from(format!("/org/mpris/MediaPlayer2/Track/{}", index))
+        .expect("track ids are valid object paths")
+}
+
+fn owned(value: Value<'_>) -> OwnedValue {
+    value
+        .try_to_owned()
+        .expect("metadata values hold no file descriptors")
+}
+
+/// The `org.mpris.MediaPlayer2` root interface
+struct MediaPlayer {
+    focus_tx: Option<Sender<()>>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer {
+    fn raise(&self) {
+        if let Some(focus_tx) = &self.focus_tx {
+            let _ = focus_tx.send(());
+        }
+    }
+
+    /// Not supported, see `CanQuit`
+    fn quit(&self) {}
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_raise(&self) -> bool {
+        self.focus_tx.is_some()
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn identity(&self) -> &str {
+        "Music Player"
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn supported_uri_schemes(&self) -> Vec<&str> {
+        vec!["file"]
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn supported_mime_types(&self) -> Vec<&str> {
+        vec!["audio/mpeg", "audio/x-mpegurl"]
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface
+struct MprisPlayer {
+    player: Arc<Player>,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MprisPlayer {
+    fn next(&self) {
+        self.player.next();
+    }
+
+    fn previous(&self) {
+        self.player.previous();
+    }
+
+    fn pause(&self) {
+        self.player.pause();
+    }
+
+    fn play_pause(&self) {
+        self.player.toggle();
+    }
+
+    fn stop(&self) {
+        self.player.stop();
+    }
+
+    fn play(&self) {
+        self.player.play();
+    }
+
+    /// Seek by `offset` microseconds, seeking past the end skips to the next track
+    fn seek(&self, offset: i64) {
+        let status = self.player.status();
+        if status.state == PlaybackState::Stopped {
+            return;
+        }
+        let position = Duration::from_millis(status.position_ms);
+        let target = if offset < 0 {
+            position.saturating_sub(Duration::from_micros(offset.unsigned_abs()))
+        } else {
+            position + Duration::from_micros(offset as u64)
+        };
+
+        match status.duration_ms.map(Duration::from_millis) {
+            Some(duration) if target >= duration => self.player.next(),
+            _ => self.player.seek_to(target),
+        }
+    }
+
+    /// Seek to `position` microseconds, ignored when `track_id` is no longer the current track
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let status = self.player.status();
+        if status.queue_position.map(self::track_id).as_ref() != Some(&track_id) || position < 0 {
+            return;
+        }
+        let target = Duration::from_micros(position as u64);
+        if status
+            .duration_ms
+            .is_some_and(|duration| target > Duration::from_millis(duration))
+        {
+            return;
+        }
+        self.player.seek_to(target);
+    }
+
+    fn open_uri(&self, uri: &str) -> fdo::Result<()> {
+        let path = Url::parse(uri)
+            .ok()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("not a file URI: {}", uri)))?;
+
+        let tracks = library::expand(&[path]);
+        if tracks.is_empty() {
+            return Err(fdo::Error::InvalidArgs(format!("nothing to play: {}", uri)));
+        }
+        self.player.open(tracks);
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        match self.player.state() {
+            PlaybackState::Playing => "Playing",
+            PlaybackState::Paused => "Paused",
+            PlaybackState::Stopped => "Stopped",
+        }
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &str {
+        match self.player.loop_mode() {
+            LoopMode::None => "None",
+            LoopMode::Track => "Track",
+            LoopMode::Playlist => "Playlist",
+        }
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, status: &str) -> zbus::Result<()> {
+        let mode = match status {
+            "None" => LoopMode::None,
+            "Track" => LoopMode::Track,
+            "Playlist" => LoopMode::Playlist,
+            _ => {
+                return Err(
+                    fdo::Error::InvalidArgs(format!("unknown loop status: {}", status)).into(),
+                );
+            }
+        };
+        self.player.set_loop_mode(mode);
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        self.player.rate().speed as f64
+    }
+
+    /// The speed, with the pitch kept. The spec asks to pause for 0.0 instead.
+    #[zbus(property)]
+    fn set_rate(&self, rate: f64) {
+        if rate == 0.0 {
+            self.player.pause();
+        } else {
+            self.player.set_speed(rate as f32);
+        }
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn minimum_rate(&self) -> f64 {
+        SPEED_RANGE.0 as f64
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn maximum_rate(&self) -> f64 {
+        SPEED_RANGE.1 as f64
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.player.status().shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) {
+        self.player.set_shuffle(shuffle);
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let status = self.player.status();
+        let mut metadata = HashMap::new();
+        let (Some(track), Some(index)) = (status.track, status.queue_position) else {
+            metadata.insert(
+                String::from("mpris:trackid"),
+                owned(Value::from(ObjectPath::from_static_str_unchecked(NO_TRACK))),
+            );
+            return metadata;
+        };
+
+        metadata.insert(
+            String::from("mpris:trackid"),
+            owned(Value::from(track_id(index))),
+        );
+        if let Some(duration) = status.duration_ms {
+            metadata.insert(
+                String::from("mpris:length"),
+                owned(Value::from(micros(Duration::from_millis(duration)))),
+            );
+        }
+        if let Ok(url) = Url::from_file_path(&track) {
+            metadata.insert(String::from("xesam:url"), owned(Value::from(url.as_str())));
+        }
+        if let Some(url) = library::cover_art(&track).and_then(|art| Url::from_file_path(art).ok())
+        {
+            metadata.insert(
+                String::from("mpris:artUrl"),
+                owned(Value::from(url.as_str())),
+            );
+        }
+        insert_tags(&mut metadata, &track);
+
+        metadata
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        f64::from(self.player.status().volume)
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) {
+        self.player.set_volume(volume as f32);
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(Duration::from_millis(self.player.status().position_ms))
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.player.status().queue_length > 0
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.player.status().queue_position.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.player.status().queue_length > 0
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Add the title, artist and album tags of `track`, falling back to the file name as its title
+fn insert_tags(metadata: &mut HashMap<String, OwnedValue>, track: &Path) {
+    let song = SongInfo::read(track);
+    let title = song.title.unwrap_or_else(|| {
+        track
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+
+    metadata.insert(String::from("xesam:title"), owned(Value::from(title)));
+    if let Some(artist) = song.artist {
+        metadata.insert(
+            String::from("xesam:artist"),
+            owned(Value::from(vec![artist])),
+        );
+    }
+    if let Some(album) = song.album {
+        metadata.insert(String::from("xesam:album"), owned(Value::from(album)));
+    }
+}
@@ -1,70 +1,33 @@
-use std::{fs::read_dir, str::FromStr, sync::Arc, sync::mpsc};
+use std::sync::Arc;
 
-use crate::PlayerCommand;
-use crate::audio_manager::AudioManager;
+use player_core::library::SongInfo;
+use player_core::player::Player;
+
+use crate::theme::Theme;
 use gpui::{
     InteractiveElement, ParentElement, Render, StatefulInteractiveElement, Styled, div,
-    prelude::FluentBuilder, rgb, uniform_list,
+    prelude::FluentBuilder, uniform_list,
 };
-use id3::{Error, ErrorKind, Tag, TagLike};
 use log::info;
 
-type SharedAudioManager = Arc<AudioManager>;
-
-#[derive(Clone, Debug)]
-struct SongInfo {
-    name: String,
-    artist: String,
-    album: String,
-}
+type SharedPlayer = Arc<Player>;
 
 pub(crate) struct ListView {
     songs_list: Vec<SongInfo>,
-    pub(crate) audio_manager: SharedAudioManager,
-    msg_sender: mpsc::Sender<PlayerCommand>,
+    pub(crate) player: SharedPlayer,
 }
 
 impl ListView {
-    pub(crate) fn new(
-        audio_manager: SharedAudioManager,
-        msg_sender: mpsc::Sender<PlayerCommand>,
-    ) -> Self {
+    pub(crate) fn new(player: SharedPlayer) -> Self {
         Self {
-            songs_list: Vec::new(),
-            audio_manager,
-            msg_sender,
+            songs_list: player.library(),
+            player,
         }
     }
 
-    pub(crate) fn load_songs(&mut self) {
-        let songs = read_dir(".")
-            .expect("Unable to list files at the given path")
-            .map(|res| res.unwrap().path())
-            .filter(|p| p.is_file() && p.extension().is_some() && p.extension().unwrap() == "mp3")
-            .map(|p| p.to_string_lossy().into_owned())
-            .collect::<Vec<String>>();
-
-        for song_path in songs.iter() {
-            let tag = match Tag::read_from_path(&song_path) {
-                Ok(tag) => tag,
-                Err(Error {
-                    kind: ErrorKind::NoTag,
-                    ..
-                }) => Tag::new(),
-                Err(err) => Tag::new(),
-            };
-            let title = tag.title().unwrap_or(song_path);
-            let artist = tag.artist().unwrap_or("No artist info!");
-            let album = tag.album().unwrap_or("No album info!");
-
-            let song_info = SongInfo {
-                name: String::from(title),
-                artist: String::from(artist),
-                album: String::from(album),
-            };
-            self.songs_list.push(song_info);
-        }
-
+    /// Show the player's library again after it was rescanned
+    pub(crate) fn reload(&mut self) {
+        self.songs_list = self.player.library();
         info!("entries {:?}", self.songs_list);
     }
 }
@@ -76,11 +39,11 @@ impl Render for ListView {
         cx: &mut gpui::Context<Self>,
     ) -> impl gpui::IntoElement {
         let songs_list = self.songs_list.clone();
-        let audio_manager = self.audio_manager.clone();
-        let msg_sender = self.msg_sender.clone();
+        let player = self.player.clone();
+        let theme = cx.global::<Theme>().clone();
 
         div()
-            .bg(rgb(0xC8E5EE))
+            .bg(theme.list_background)
             .gap_3()
             .size_full()
             .flex()
@@ -88,7 +51,7 @@ impl Render for ListView {
             .when_else(
                 !songs_list.is_empty(),
                 move |_div| {
-                    let audio_manager = audio_manager.clone(); // clone BEFORE move
+                    let player = player.clone(); // clone BEFORE move
 
                     _div.items_start()
                         .flex()
@@ -101,8 +64,8 @@ impl Render for ListView {
                                 .w_full()
                                 .px_4()
                                 .border_1()
-                                .bg(rgb(0x1C4A5A))
-                                .text_color(rgb(0xf1f1f1))
+                                .bg(theme.accent)
+                                .text_color(theme.accent_text)
                                 .child(
                                     div()
                                         .flex_1()
@@ -134,18 +97,23 @@ impl Render for ListView {
                             uniform_list("songs-list", songs_list.len(), {
                                 let songs_list = songs_list.clone();
                                 cx.processor(move |_this, range, _window, _cx| {
-                                    let audio_manager = audio_manager.clone(); // clone AGAIN
+                                    let player = player.clone(); // clone AGAIN
                                     let mut items = Vec::new();
 
                                     for idx in range {
                                         let song: &SongInfo = &songs_list[idx];
                                         let text_val: &'static str =
-                                            Box::leak(song.name.clone().into_boxed_str());
-                                        let artist = song.artist.clone();
-                                        let album = song.album.clone();
+                                            Box::leak(song.name().into_boxed_str());
+                                        let artist = song
+                                            .artist
+                                            .clone()
+                                            .unwrap_or_else(|| String::from("No artist info!"));
+                                        let album = song
+                                            .album
+                                            .clone()
+                                            .unwrap_or_else(|| String::from("No album info!"));
 
-                                        let audio_manager = audio_manager.clone();
-                                        let msg_sender = msg_sender.clone();
+                                        let player = player.clone();
                                         items.push(
                                             div()
                                                 .id(text_val)
@@ -153,20 +121,14 @@ impl Render for ListView {
                                                 .flex()
                                                 .w_full()
                                                 .border_b_1()
-                                                .border_color(gpui::black())
+                                                .border_color(theme.list_border)
                                                 .cursor_pointer()
                                                 .on_click(move |_, _, _| {
                                                     println!("song clicked {:?}", text_val);
-                                                    audio_manager.load(
-                                                        String::from_str(text_val)
-                                                            .expect("Unable to load the song"),
-                                                    );
-                                                    audio_manager.play();
-                                                    let res = msg_sender.send(PlayerCommand::Play);
-                                                    info!(
-                                                        "Playing a new song {:?}\tcommand {:?}!!!",
-                                                        text_val, res
-                                                    );
+                                                    // queue the whole list so next/previous
+                                                    // continue from the clicked song
+                                                    player.play_library(idx);
+                                                    info!("Playing a new song {:?}!!!", text_val);
                                                 })
                                                 .child(
                                                     div()
@@ -1,63 +1,63 @@
 use gpui::{
-    ClickEvent, Context, ImageSource, InteractiveElement, ParentElement, Render, Resource,
-    StatefulInteractiveElement, Styled, Window, div, img,
+    ClickEvent, Context, InteractiveElement, ParentElement, Render, StatefulInteractiveElement,
+    Styled, Window, div, svg,
 };
-use log::info;
-use std::path::Path;
-use std::sync::{Arc, mpsc};
+use std::sync::Arc;
 
-use crate::PlayerCommand;
-use crate::audio_manager::AudioManager;
+use player_core::player::{PlaybackState, Player};
 
-type SharedAudioManager = Arc<AudioManager>;
+use crate::assets::{PAUSE_ICON, PLAY_ICON};
+use crate::theme::Theme;
+
+type SharedPlayer = Arc<Player>;
 
 pub struct PlayElement {
     pub(crate) is_playing: bool,
-    btn: ImageSource,
-    pub(crate) audio_manager: SharedAudioManager,
-    state_reciever: mpsc::Receiver<PlayerCommand>,
+    btn: &'static str,
+    pub(crate) player: SharedPlayer,
 }
 impl PlayElement {
-    pub fn new(audio_manager: SharedAudioManager, rx: mpsc::Receiver<PlayerCommand>) -> Self {
+    pub fn new(player: SharedPlayer) -> Self {
         Self {
             is_playing: false,
-            btn: ImageSource::Resource(Resource::Path(Arc::from(Path::new(
-                "assets/play-button.png",
-            )))),
-            audio_manager,
-            state_reciever: rx,
+            btn: PLAY_ICON,
+            player,
         }
     }
 
-    fn on_click(&mut self, _: &ClickEvent, _: &mut Window, _cx: &mut Context<Self>) {
-        self.is_playing = !self.is_playing;
+    fn on_click(&mut self, _: &ClickEvent, _: &mut Window, cx: &mut Context<Self>) {
+        self.toggle(cx);
+    }
+
+    /// Toggle between playing and paused
+    pub(crate) fn toggle(&mut self, cx: &mut Context<Self>) {
+        self.player.toggle();
+        self.set_playing(self.player.state() == PlaybackState::Playing, cx);
+    }
+
+    /// Show the icon for the player's state, called when the player reports a change
+    pub(crate) fn set_playing(&mut self, is_playing: bool, cx: &mut Context<Self>) {
+        self.is_playing = is_playing;
         self.update_icon();
+        cx.notify();
     }
 
     fn update_icon(&mut self) {
         if self.is_playing {
-            self.btn = ImageSource::Resource(Resource::Path(Arc::from(Path::new(
-                "assets/pause-button.png",
-            ))));
-
-            self.audio_manager.play();
+            self.btn = PAUSE_ICON;
         } else {
-            self.btn = ImageSource::Resource(Resource::Path(Arc::from(Path::new(
-                "assets/play-button.png",
-            ))));
-
-            self.audio_manager.pause();
+            self.btn = PLAY_ICON;
         }
     }
 
-    /// Seek forward by 10 seconds
+    /// Seek forward by the configured seek step
     pub fn seek_forward(&self) {
-        self.audio_manager.seek_forward();
+        self.player.seek_forward();
     }
 
-    /// Seek backward by 10 seconds
+    /// Seek backward by the configured seek step
     pub fn seek_backward(&self) {
-        self.audio_manager.seek_backward();
+        self.player.seek_backward();
     }
 }
 
@@ -67,23 +67,15 @@ impl Render for PlayElement {
         _window: &mut gpui::Window,
         cx: &mut gpui::Context<Self>,
     ) -> impl gpui::IntoElement {
-        // todo(mustaq): fix and see if any other approach can be applied
-        // this is blocking the main thread so let's use `try_recv()` and move on
-        match self.state_reciever.try_recv() {
-            Ok(PlayerCommand::Paused) => {
-                self.is_playing = false;
-                self.update_icon();
-            }
-            Ok(PlayerCommand::Play) => {
-                self.is_playing = true;
-                self.update_icon();
-            }
-            _ => {}
-        }
         div()
             .id("play-button")
             .size_16()
             .on_click(cx.listener(Self::on_click))
-            .child(img(self.btn.clone()).size_full())
+            .child(
+                svg()
+                    .path(self.btn)
+                    .text_color(cx.global::<Theme>().accent_text)
+                    .size_full(),
+            )
     }
 }
@@ -0,0 +1,906 @@
+use std::path::PathBuf;
+
+use gpui::{
+    App, AppContext, Bounds, ClickEvent, Context, FontWeight, InteractiveElement, IntoElement,
+    ParentElement, PathPromptOptions, Render, SharedString, StatefulInteractiveElement, Styled,
+    Subscription, Window, WindowBounds, WindowOptions, div, prelude::FluentBuilder, px, size,
+};
+use log::error;
+
+use player_core::equalizer::{EqBand, EqMode, FilterKind, GRAPHIC_FREQUENCIES};
+use player_core::output;
+
+use crate::format;
+use crate::keymap;
+use crate::settings::{
+    self, EqualizerSettings, GlobalSettings, ReplayGainMode, Settings, SpeedMemory, VisualizerMode,
+};
+use crate::theme::{self, Theme};
+
+/// Step sizes used by the +/- buttons
+const CROSSFADE_STEP_SECS: f32 = 0.5;
+const SLEEP_FADE_STEP_SECS: f32 = 5.0;
+const RAMP_STEP_MS: u64 = 5;
+const PREAMP_STEP_DB: f32 = 0.5;
+const BALANCE_STEP: f32 = 0.1;
+const EQ_GAIN_STEP_DB: f32 = 1.0;
+/// A third of an octave
+const EQ_FREQUENCY_STEP: f32 = 1.26;
+const EQ_Q_STEP: f32 = 0.1;
+const WINDOW_SIZE_STEP: f32 = 50.0;
+
+const REPLAYGAIN_MODES: &[(ReplayGainMode, &str)] = &[
+    (ReplayGainMode::Off, "Off"),
+    (ReplayGainMode::Track, "Track"),
+    (ReplayGainMode::Album, "Album"),
+    (ReplayGainMode::Auto, "Auto"),
+];
+
+const SPEED_MEMORY: &[(SpeedMemory, &str)] = &[
+    (SpeedMemory::Off, "Off"),
+    (SpeedMemory::SpokenWord, "Spoken word"),
+    (SpeedMemory::All, "All tracks"),
+];
+
+const VISUALIZER_MODES: &[(VisualizerMode, &str)] = &[
+    (VisualizerMode::Off, "Off"),
+    (VisualizerMode::Bars, "Spectrum"),
+    (VisualizerMode::Scope, "Oscilloscope"),
+];
+
+const EQ_MODES: &[(EqMode, &str)] = &[
+    (EqMode::Graphic, "Graphic"),
+    (EqMode::Parametric, "Parametric"),
+];
+
+const FILTER_KINDS: &[(FilterKind, &str)] = &[
+    (FilterKind::Peak, "Peak"),
+    (FilterKind::LowShelf, "Low shelf"),
+    (FilterKind::HighShelf, "High shelf"),
+];
+
+/// Preferences window, every change is applied live through the `Settings` global and written
+/// back to the settings file.
+pub(crate) struct PreferencesView {
+    output_devices: Vec<String>,
+    themes: Vec<String>,
+    /// Last validation or save error, shown at the bottom of the window
+    error: Option<String>,
+    _settings_subscription: Subscription,
+}
+
+/// Open the preferences window, or bring it to the front when it is already open
+pub(crate) fn open(cx: &mut App) {
+    let existing = cx
+        .windows()
+        .into_iter()
+        .find_map(|window| window.downcast::<PreferencesView>());
+    if let Some(window) = existing {
+        let _ = window.update(cx, |_, window, _| window.activate_window());
+        return;
+    }
+
+    let bounds = Bounds::centered(None, size(px(560.0), px(640.0)), cx);
+    let window_options = WindowOptions {
+        window_bounds: Some(WindowBounds::Windowed(bounds)),
+        ..Default::default()
+    };
+    if let Err(e) = cx.open_window(window_options, |_, cx| cx.new(PreferencesView::new)) {
+        error!("Unable to open the preferences window: {}", e);
+    }
+}
+
+impl PreferencesView {
+    fn new(cx: &mut Context<Self>) -> Self {
+        Self {
+            output_devices: output::output_device_names(),
+            themes: theme::available_themes(),
+            error: None,
+            _settings_subscription: cx.observe_global::<GlobalSettings>(|_, cx| cx.notify()),
+        }
+    }
+
+    /// Apply `update` to a copy of the current settings, validate it, publish it and save it
+    fn update_settings(&mut self, cx: &mut Context<Self>, update: impl FnOnce(&mut Settings)) {
+        let mut settings = cx.global::<GlobalSettings>().0.clone();
+        update(&mut settings);
+
+        if let Err(e) = settings::validate(&settings) {
+            self.error = Some(e.to_string());
+            cx.notify();
+            return;
+        }
+
+        self.error = settings.save().err().map(|e| e.to_string());
+        cx.set_global(GlobalSettings(settings));
+        cx.notify();
+    }
+
+    fn add_library_root(&mut self, _: &ClickEvent, _: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: false,
+            directories: true,
+            multiple: true,
+            prompt: Some("Add to library".into()),
+        });
+        cx.spawn(async move |this, cx| {
+            if let Ok(Ok(Some(paths))) = paths.await {
+                let _ = this.update(cx, |this, cx| {
+                    this.update_settings(cx, |settings| {
+                        for path in paths {
+                            if !settings.library.roots.contains(&path) {
+                                settings.library.roots.push(path);
+                            }
+                        }
+                    })
+                });
+            }
+        })
+        .detach();
+    }
+
+    fn choose_keymap(&mut self, _: &ClickEvent, _: &mut Window, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: false,
+            prompt: Some("Use keymap".into()),
+        });
+        cx.spawn(async move |this, cx| {
+            if let Ok(Ok(Some(mut paths))) = paths.await
+                && let Some(path) = paths.pop()
+            {
+                let _ = this.update(cx, |this, cx| {
+                    this.update_settings(cx, |settings| settings.shortcuts.keymap = Some(path))
+                });
+            }
+        })
+        .detach();
+    }
+
+    fn render_library(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let roots = settings.library.roots.clone();
+
+        section("Library", theme)
+            .children(roots.into_iter().enumerate().map(|(ix, root)| {
+                row(root.display().to_string()).child(
+                    button(("remove-root", ix), "Remove", theme).on_click(cx.listener(
+                        move |this, _, _, cx| {
+                            this.update_settings(cx, |settings| {
+                                settings.library.roots.remove(ix);
+                            })
+                        },
+                    )),
+                )
+            }))
+            .child(
+                button("add-root", "Add folder…", theme)
+                    .on_click(cx.listener(Self::add_library_root)),
+            )
+            .child(row("Write loudness analysis to tags").children(
+                [(true, "On"), (false, "Off")].map(|(write, label)| {
+                    option(
+                        ("write-gain-tags", write as usize),
+                        label,
+                        settings.library.write_gain_tags == write,
+                        theme,
+                    )
+                    .on_click(cx.listener(move |this, _, _, cx| {
+                        this.update_settings(cx, |settings| {
+                            settings.library.write_gain_tags = write
+                        })
+                    }))
+                }),
+            ))
+    }
+
+    fn render_playback(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let playback = &settings.playback;
+
+        section("Playback", theme)
+            .child(stepper(
+                "seek-step",
+                "Seek step",
+                format!("{} s", playback.seek_step_secs),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.seek_step_secs =
+                            settings.playback.seek_step_secs.saturating_sub(1)
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| settings.playback.seek_step_secs += 1)
+                }),
+                theme,
+            ))
+            .child(stepper(
+                "crossfade",
+                "Crossfade",
+                format!("{:.1} s", playback.crossfade_secs),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.crossfade_secs -= CROSSFADE_STEP_SECS
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.crossfade_secs += CROSSFADE_STEP_SECS
+                    })
+                }),
+                theme,
+            ))
+            .child(stepper(
+                "ramp",
+                "Pause/seek fade",
+                format!("{} ms", playback.ramp_ms),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.ramp_ms =
+                            settings.playback.ramp_ms.saturating_sub(RAMP_STEP_MS)
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| settings.playback.ramp_ms += RAMP_STEP_MS)
+                }),
+                theme,
+            ))
+            .child(stepper(
+                "sleep-fade",
+                "Sleep fade",
+                format!("{:.0} s", playback.sleep_fade_secs),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.sleep_fade_secs -= SLEEP_FADE_STEP_SECS
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.sleep_fade_secs += SLEEP_FADE_STEP_SECS
+                    })
+                }),
+                theme,
+            ))
+            .child(
+                row("ReplayGain").children(REPLAYGAIN_MODES.iter().map(|&(mode, label)| {
+                    option(
+                        ("replaygain", mode as usize),
+                        label,
+                        playback.replaygain == mode,
+                        theme,
+                    )
+                    .on_click(cx.listener(move |this, _, _, cx| {
+                        this.update_settings(cx, |settings| settings.playback.replaygain = mode)
+                    }))
+                })),
+            )
+            .child(stepper(
+                "replaygain-preamp",
+                "Pre-amp",
+                format!("{:+.1} dB", playback.replaygain_preamp_db),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.replaygain_preamp_db -= PREAMP_STEP_DB
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.playback.replaygain_preamp_db += PREAMP_STEP_DB
+                    })
+                }),
+                theme,
+            ))
+            .child(
+                row("Prevent clipping").children([(true, "On"), (false, "Off")].map(
+                    |(prevent, label)| {
+                        option(
+                            ("prevent-clipping", prevent as usize),
+                            label,
+                            playback.replaygain_prevent_clipping == prevent,
+                            theme,
+                        )
+                        .on_click(cx.listener(move |this, _, _, cx| {
+                            this.update_settings(cx, |settings| {
+                                settings.playback.replaygain_prevent_clipping = prevent
+                            })
+                        }))
+                    },
+                )),
+            )
+            .child(
+                row("Remember speed").children(SPEED_MEMORY.iter().map(|&(memory, label)| {
+                    option(
+                        ("remember-speed", memory as usize),
+                        label,
+                        playback.remember_speed == memory,
+                        theme,
+                    )
+                    .on_click(cx.listener(move |this, _, _, cx| {
+                        this.update_settings(cx, |settings| {
+                            settings.playback.remember_speed = memory
+                        })
+                    }))
+                })),
+            )
+    }
+
+    fn render_audio_output(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let selected = settings.audio.output_device.clone();
+
+        section("Audio Output", theme)
+            .child(
+                option(
+                    "device-default",
+                    "System default",
+                    selected.is_none(),
+                    theme,
+                )
+                .on_click(cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| settings.audio.output_device = None)
+                })),
+            )
+            .children(self.output_devices.iter().enumerate().map(|(ix, name)| {
+                let device = name.clone();
+                option(
+                    ("device", ix),
+                    name.clone(),
+                    selected.as_deref() == Some(name.as_str()),
+                    theme,
+                )
+                .on_click(cx.listener(move |this, _, _, cx| {
+                    let device = device.clone();
+                    this.update_settings(cx, |settings| settings.audio.output_device = Some(device))
+                }))
+            }))
+            .child(
+                button("refresh-devices", "Refresh", theme).on_click(cx.listener(
+                    |this, _, _, cx| {
+                        this.output_devices = output::output_device_names();
+                        cx.notify();
+                    },
+                )),
+            )
+            .child(div().text_sm().text_color(theme.muted_text).child(
+                "The current track keeps playing on the new device. When it is unplugged, \
+                 the system default takes over.",
+            ))
+    }
+
+    fn render_channels(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let channels = &settings.channels;
+        // in tenths, so repeated steps land on round values
+        let step_balance = |balance: f32, step: f32| ((balance + step) * 10.0).round() / 10.0;
+
+        section("Channels", theme)
+            .child(stepper(
+                "balance",
+                "Balance",
+                format::balance_label(channels.balance),
+                cx.listener(move |this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.channels.balance =
+                            step_balance(settings.channels.balance, -BALANCE_STEP)
+                    })
+                }),
+                cx.listener(move |this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.channels.balance =
+                            step_balance(settings.channels.balance, BALANCE_STEP)
+                    })
+                }),
+                theme,
+            ))
+            .child(
+                row("Mono").children([(true, "On"), (false, "Off")].map(|(mono, label)| {
+                    option(("mono", mono as usize), label, channels.mono == mono, theme).on_click(
+                        cx.listener(move |this, _, _, cx| {
+                            this.update_settings(cx, |settings| settings.channels.mono = mono)
+                        }),
+                    )
+                })),
+            )
+            .child(
+                row("Swap left and right").children([(true, "On"), (false, "Off")].map(
+                    |(swap, label)| {
+                        option(("swap", swap as usize), label, channels.swap == swap, theme)
+                            .on_click(cx.listener(move |this, _, _, cx| {
+                                this.update_settings(cx, |settings| settings.channels.swap = swap)
+                            }))
+                    },
+                )),
+            )
+    }
+
+    fn render_equalizer(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let equalizer = &settings.equalizer;
+        let Some(active) = equalizer.active() else {
+            return section("Equalizer", theme);
+        };
+        let is_user_preset = equalizer
+            .presets
+            .iter()
+            .any(|preset| preset.name == active.name);
+
+        let section = section("Equalizer", theme)
+            .child(row("Enabled").children([(true, "On"), (false, "Off")].map(
+                |(enabled, label)| {
+                    option(
+                        ("equalizer", enabled as usize),
+                        label,
+                        equalizer.enabled == enabled,
+                        theme,
+                    )
+                    .on_click(cx.listener(move |this, _, _, cx| {
+                        this.update_settings(cx, |settings| settings.equalizer.enabled = enabled)
+                    }))
+                },
+            )))
+            .child(
+                row("Preset")
+                    .children(equalizer.all_presets().into_iter().enumerate().map(
+                        |(ix, preset)| {
+                            let name = preset.name;
+                            option(("eq-preset", ix), name.clone(), name == active.name, theme)
+                                .on_click(cx.listener(move |this, _, _, cx| {
+                                    let name = name.clone();
+                                    this.update_settings(cx, |settings| {
+                                        settings.equalizer.preset = name
+                                    })
+                                }))
+                        },
+                    ))
+                    .when(is_user_preset, |row| {
+                        row.child(button("delete-eq-preset", "Delete", theme).on_click(
+                            cx.listener(|this, _, _, cx| {
+                                this.update_settings(cx, |settings| {
+                                    let name = settings.equalizer.preset.clone();
+                                    settings
+                                        .equalizer
+                                        .presets
+                                        .retain(|preset| preset.name != name);
+                                    settings.equalizer.preset = EqualizerSettings::default().preset;
+                                })
+                            }),
+                        ))
+                    }),
+            )
+            .child(row("Mode").children(EQ_MODES.iter().map(|&(mode, label)| {
+                option(
+                    ("eq-mode", mode as usize),
+                    label,
+                    active.mode == mode,
+                    theme,
+                )
+                .on_click(cx.listener(move |this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.equalizer.edit_active(|preset| {
+                            // parametric mode starts out with the graphic curve
+                            if mode == EqMode::Parametric && preset.bands.is_empty() {
+                                preset.bands = preset.filters();
+                            }
+                            preset.mode = mode;
+                        })
+                    })
+                }))
+            })))
+            .child(stepper(
+                "eq-preamp",
+                "Pre-amp",
+                format!("{:+.1} dB", active.preamp_db),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings
+                            .equalizer
+                            .edit_active(|preset| preset.preamp_db -= PREAMP_STEP_DB)
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings
+                            .equalizer
+                            .edit_active(|preset| preset.preamp_db += PREAMP_STEP_DB)
+                    })
+                }),
+                theme,
+            ));
+
+        let section = match active.mode {
+            EqMode::Graphic => section.children(GRAPHIC_FREQUENCIES.iter().enumerate().map(
+                |(ix, &frequency)| {
+                    stepper(
+                        format!("eq-gain-{}", ix),
+                        format_frequency(frequency),
+                        format!("{:+.1} dB", active.gains_db[ix]),
+                        cx.listener(move |this, _, _, cx| {
+                            this.update_settings(cx, |settings| {
+                                settings
+                                    .equalizer
+                                    .edit_active(|preset| preset.gains_db[ix] -= EQ_GAIN_STEP_DB)
+                            })
+                        }),
+                        cx.listener(move |this, _, _, cx| {
+                            this.update_settings(cx, |settings| {
+                                settings
+                                    .equalizer
+                                    .edit_active(|preset| preset.gains_db[ix] += EQ_GAIN_STEP_DB)
+                            })
+                        }),
+                        theme,
+                    )
+                },
+            )),
+            EqMode::Parametric => section
+                .children(
+                    active
+                        .bands
+                        .iter()
+                        .enumerate()
+                        .map(|(ix, band)| Self::render_parametric_band(ix, band, theme, cx)),
+                )
+                .child(
+                    button("add-eq-band", "Add band", theme).on_click(cx.listener(
+                        |this, _, _, cx| {
+                            this.update_settings(cx, |settings| {
+                                settings.equalizer.edit_active(|preset| {
+                                    preset.bands.push(EqBand {
+                                        frequency: 1000.0,
+                                        gain_db: 0.0,
+                                        q: 1.0,
+                                        kind: FilterKind::Peak,
+                                    })
+                                })
+                            })
+                        },
+                    )),
+                ),
+        };
+
+        section.child(div().text_sm().text_color(theme.muted_text).child(
+            "Changes apply to the playing track right away. Editing a built-in preset saves \
+             it as Custom.",
+        ))
+    }
+
+    /// Click handler applying `edit` to parametric band `ix` of the selected preset
+    fn edit_band<F: Fn(&mut EqBand) + 'static>(
+        ix: usize,
+        edit: F,
+        cx: &mut Context<Self>,
+    ) -> impl Fn(&ClickEvent, &mut Window, &mut App) + use<F> {
+        let view = cx.entity().downgrade();
+        move |_, _, cx| {
+            let _ = view.update(cx, |this, cx| {
+                this.update_settings(cx, |settings| {
+                    settings.equalizer.edit_active(|preset| {
+                        if let Some(band) = preset.bands.get_mut(ix) {
+                            edit(band)
+                        }
+                    })
+                })
+            });
+        }
+    }
+
+    /// Shape, frequency, gain and Q of parametric band `ix`
+    fn render_parametric_band(
+        ix: usize,
+        band: &EqBand,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> gpui::Div {
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .child(
+                row(format!("Band {}", ix + 1))
+                    .children(
+                        FILTER_KINDS
+                            .iter()
+                            .enumerate()
+                            .map(|(kind_ix, &(kind, label))| {
+                                option(
+                                    (SharedString::from(format!("eq-kind-{}", ix)), kind_ix),
+                                    label,
+                                    band.kind == kind,
+                                    theme,
+                                )
+                                .on_click(Self::edit_band(
+                                    ix,
+                                    move |band| band.kind = kind,
+                                    cx,
+                                ))
+                            }),
+                    )
+                    .child(
+                        button(("remove-eq-band", ix), "Remove", theme).on_click(cx.listener(
+                            move |this, _, _, cx| {
+                                this.update_settings(cx, |settings| {
+                                    settings.equalizer.edit_active(|preset| {
+                                        if ix < preset.bands.len() {
+                                            preset.bands.remove(ix);
+                                        }
+                                    })
+                                })
+                            },
+                        )),
+                    ),
+            )
+            .child(stepper(
+                format!("eq-frequency-{}", ix),
+                "Frequency",
+                format_frequency(band.frequency),
+                Self::edit_band(
+                    ix,
+                    |band| band.frequency = (band.frequency / EQ_FREQUENCY_STEP).round().max(20.0),
+                    cx,
+                ),
+                Self::edit_band(
+                    ix,
+                    |band| {
+                        band.frequency = (band.frequency * EQ_FREQUENCY_STEP).round().min(20000.0)
+                    },
+                    cx,
+                ),
+                theme,
+            ))
+            .child(stepper(
+                format!("eq-band-gain-{}", ix),
+                "Gain",
+                format!("{:+.1} dB", band.gain_db),
+                Self::edit_band(ix, |band| band.gain_db -= EQ_GAIN_STEP_DB, cx),
+                Self::edit_band(ix, |band| band.gain_db += EQ_GAIN_STEP_DB, cx),
+                theme,
+            ))
+            .child(stepper(
+                format!("eq-q-{}", ix),
+                "Q",
+                format!("{:.1}", band.q),
+                Self::edit_band(
+                    ix,
+                    |band| band.q = ((band.q - EQ_Q_STEP) * 10.0).round() / 10.0,
+                    cx,
+                ),
+                Self::edit_band(
+                    ix,
+                    |band| band.q = ((band.q + EQ_Q_STEP) * 10.0).round() / 10.0,
+                    cx,
+                ),
+                theme,
+            ))
+    }
+
+    fn render_appearance(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let appearance = &settings.appearance;
+
+        section("Appearance", theme)
+            .child(
+                row("Theme").children(self.themes.iter().enumerate().map(|(ix, name)| {
+                    let name = name.clone();
+                    option(("theme", ix), name.clone(), appearance.theme == name, theme).on_click(
+                        cx.listener(move |this, _, _, cx| {
+                            let name = name.clone();
+                            this.update_settings(cx, |settings| settings.appearance.theme = name)
+                        }),
+                    )
+                })),
+            )
+            .child(stepper(
+                "window-width",
+                "Window width",
+                format!("{}", appearance.window_width),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.appearance.window_width -= WINDOW_SIZE_STEP
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.appearance.window_width += WINDOW_SIZE_STEP
+                    })
+                }),
+                theme,
+            ))
+            .child(stepper(
+                "window-height",
+                "Window height",
+                format!("{}", appearance.window_height),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.appearance.window_height -= WINDOW_SIZE_STEP
+                    })
+                }),
+                cx.listener(|this, _, _, cx| {
+                    this.update_settings(cx, |settings| {
+                        settings.appearance.window_height += WINDOW_SIZE_STEP
+                    })
+                }),
+                theme,
+            ))
+            .child(
+                row("Visualizer").children(VISUALIZER_MODES.iter().map(|&(mode, label)| {
+                    option(
+                        ("visualizer", mode as usize),
+                        label,
+                        appearance.visualizer == mode,
+                        theme,
+                    )
+                    .on_click(cx.listener(move |this, _, _, cx| {
+                        this.update_settings(cx, |settings| settings.appearance.visualizer = mode)
+                    }))
+                })),
+            )
+    }
+
+    fn render_shortcuts(
+        &self,
+        settings: &Settings,
+        theme: &Theme,
+        cx: &mut Context<Self>,
+    ) -> impl IntoElement {
+        let keymap_path = settings
+            .shortcuts
+            .keymap
+            .as_ref()
+            .map(|path: &PathBuf| path.display().to_string())
+            .unwrap_or_else(|| String::from("No keymap file"));
+
+        section("Shortcuts", theme)
+            .child(
+                row(keymap_path)
+                    .child(
+                        button("choose-keymap", "Choose…", theme)
+                            .on_click(cx.listener(Self::choose_keymap)),
+                    )
+                    .when(settings.shortcuts.keymap.is_some(), |row| {
+                        row.child(button("clear-keymap", "Clear", theme).on_click(cx.listener(
+                            |this, _, _, cx| {
+                                this.update_settings(cx, |settings| {
+                                    settings.shortcuts.keymap = None
+                                })
+                            },
+                        )))
+                    }),
+            )
+            .children(
+                keymap::bindings(settings)
+                    .into_iter()
+                    .map(|(keystrokes, action)| row(action).child(div().child(keystrokes))),
+            )
+    }
+}
+
+impl Render for PreferencesView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let settings = cx.global::<GlobalSettings>().0.clone();
+        let theme = cx.global::<Theme>().clone();
+
+        div()
+            .id("preferences")
+            .bg(theme.background)
+            .text_color(theme.text)
+            .size_full()
+            .flex()
+            .flex_col()
+            .gap_4()
+            .p_4()
+            .overflow_y_scroll()
+            .child(div().text_2xl().child("Preferences"))
+            .child(self.render_library(&settings, &theme, cx))
+            .child(self.render_playback(&settings, &theme, cx))
+            .child(self.render_audio_output(&settings, &theme, cx))
+            .child(self.render_channels(&settings, &theme, cx))
+            .child(self.render_equalizer(&settings, &theme, cx))
+            .child(self.render_appearance(&settings, &theme, cx))
+            .child(self.render_shortcuts(&settings, &theme, cx))
+            .when_some(self.error.clone(), |div_, error| {
+                div_.child(div().text_color(theme.error).child(error))
+            })
+    }
+}
+
+fn section(title: &'static str, theme: &Theme) -> gpui::Div {
+    div().flex().flex_col().gap_2().child(
+        div()
+            .font_weight(FontWeight::BOLD)
+            .border_b_1()
+            .border_color(theme.accent)
+            .child(title),
+    )
+}
+
+fn row(label: impl Into<SharedString>) -> gpui::Div {
+    div().flex().flex_row().items_center().gap_2().child(
+        div()
+            .flex_1()
+            .min_w_0()
+            .overflow_hidden()
+            .text_ellipsis()
+            .child(label.into()),
+    )
+}
+
+fn button(
+    id: impl Into<gpui::ElementId>,
+    label: &'static str,
+    theme: &Theme,
+) -> gpui::Stateful<gpui::Div> {
+    div()
+        .id(id)
+        .px_2()
+        .rounded_md()
+        .bg(theme.accent)
+        .text_color(theme.accent_text)
+        .cursor_pointer()
+        .child(label)
+}
+
+fn option(
+    id: impl Into<gpui::ElementId>,
+    label: impl Into<SharedString>,
+    selected: bool,
+    theme: &Theme,
+) -> gpui::Stateful<gpui::Div> {
+    div()
+        .id(id)
+        .px_2()
+        .rounded_md()
+        .border_1()
+        .border_color(theme.accent)
+        .cursor_pointer()
+        .when(selected, |div| div.bg(theme.selection))
+        .child(label.into())
+}
+
+fn stepper(
+    id: impl Into<SharedString>,
+    label: impl Into<SharedString>,
+    value: String,
+    on_decrement: impl Fn(&ClickEvent, &mut Window, &mut App) + 'static,
+    on_increment: impl Fn(&ClickEvent, &mut Window, &mut App) + 'static,
+    theme: &Theme,
+) -> gpui::Div {
+    let id = id.into();
+    row(label)
+        .child(button((id.clone(), 0usize), "−", theme).on_click(on_decrement))
+        .child(div().w(px(64.)).text_center().child(value))
+        .child(button((id, 1usize), "+", theme).on_click(on_increment))
+}
+
+/// `31 Hz`, `1 kHz`, `2.5 kHz`
+fn format_frequency(frequency: f32) -> String {
+    if frequency >= 1000.0 {
+        format!("{} kHz", (frequency / 100.0).round() / 10.0)
+    } else {
+        format!("{} Hz", frequency.round())
+    }
+}
@@ -0,0 +1,348 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use gpui::{
+    Bounds, ClickEvent, Context, ElementId, InteractiveElement, IntoElement, MouseButton,
+    MouseDownEvent, MouseMoveEvent, MouseUpEvent, ParentElement, Pixels, Point, Render, Rgba,
+    SharedString, StatefulInteractiveElement, Styled, Window, canvas, div, fill, point,
+    prelude::FluentBuilder, px, relative, size,
+};
+
+use player_core::player::{PlaybackState, Player};
+use player_core::waveform::Waveform;
+
+use crate::format::format_time;
+use crate::theme::Theme;
+
+/// How far the mouse has to move while pressed to select a loop instead of seeking
+const DRAG_THRESHOLD: Pixels = px(4.0);
+/// Height of the bar, taller once the waveform of the track is known
+const BAR_HEIGHT: Pixels = px(20.);
+const WAVEFORM_BAR_HEIGHT: Pixels = px(40.);
+/// Space between two columns of the waveform
+const COLUMN_GAP: Pixels = px(1.);
+
+/// Progress of the current track over its waveform, with its A–B loop and saved loops.
+/// Clicking seeks, dragging selects a loop.
+pub(crate) struct SeekBar {
+    player: Arc<Player>,
+    /// Where the bar was painted last, to turn mouse positions into track positions
+    bounds: Bounds<Pixels>,
+    /// Start and current position of a drag, as fractions of the bar
+    drag: Option<(f32, f32)>,
+    /// Overview of the current track, once it was computed. Shared with the canvas painting it,
+    /// so a render doesn't copy it.
+    waveform: Option<(PathBuf, Arc<Waveform>)>,
+}
+
+impl SeekBar {
+    pub(crate) fn new(player: Arc<Player>) -> Self {
+        Self {
+            player,
+            bounds: Bounds::default(),
+            drag: None,
+            waveform: None,
+        }
+    }
+
+    /// The overview of `track`, asking the player until it is there
+    fn waveform(&mut self, track: Option<PathBuf>) -> Option<&Arc<Waveform>> {
+        let known = self.waveform.as_ref().map(|(path, _)| path);
+        if track.as_ref() != known {
+            self.waveform = track.and_then(|track| {
+                let waveform = self.player.waveform(&track)?;
+                Some((track, Arc::new(waveform)))
+            });
+        }
+        self.waveform
+            .as_ref()
+            .map(|(_, waveform)| waveform)
+            .filter(|waveform| !waveform.is_empty())
+    }
+
+    /// Fraction of the bar at `position`
+    fn fraction(&self, position: Point<Pixels>) -> f32 {
+        if self.bounds.size.width <= px(0.0) {
+            return 0.0;
+        }
+        ((position.x - self.bounds.origin.x) / self.bounds.size.width).clamp(0.0, 1.0)
+    }
+
+    fn on_mouse_down(&mut self, event: &MouseDownEvent, _: &mut Window, cx: &mut Context<Self>) {
+        let fraction = self.fraction(event.position);
+        self.drag = Some((fraction, fraction));
+        cx.notify();
+    }
+
+    fn on_mouse_move(&mut self, event: &MouseMoveEvent, _: &mut Window, cx: &mut Context<Self>) {
+        if event.pressed_button != Some(MouseButton::Left) {
+            return;
+        }
+        let fraction = self.fraction(event.position);
+        if let Some((_, end)) = &mut self.drag {
+            *end = fraction;
+            cx.notify();
+        }
+    }
+
+    /// Seek to where the drag started, or loop the dragged section
+    fn on_mouse_up(&mut self, _: &MouseUpEvent, _: &mut Window, cx: &mut Context<Self>) {
+        let Some((start, end)) = self.drag.take() else {
+            return;
+        };
+        cx.notify();
+        let Some(duration) = self.player.status().duration_ms.map(Duration::from_millis) else {
+            return;
+        };
+        if self.bounds.size.width * (end - start).abs() < DRAG_THRESHOLD {
+            self.player.seek_to(duration.mul_f32(start));
+        } else {
+            self.player.set_ab_loop(
+                duration.mul_f32(start.min(end)),
+                duration.mul_f32(start.max(end)),
+            );
+        }
+    }
+}
+
+/// Paint `waveform` across `bounds`, the columns before `played` in the first colour
+fn paint_waveform(
+    bounds: Bounds<Pixels>,
+    waveform: &Waveform,
+    played: f32,
+    (played_color, unplayed_color): (Rgba, Rgba),
+    window: &mut Window,
+) {
+    let columns = waveform.columns();
+    let count = columns.len();
+    let width = bounds.size.width / count as f32;
+    let middle = bounds.origin.y + bounds.size.height / 2.;
+    let half_height = bounds.size.height / 2.;
+    for (ix, (min, max)) in columns.enumerate() {
+        let x = bounds.origin.x + width * ix as f32;
+        let top = middle - half_height * max;
+        // silence still shows as a line
+        let height = (half_height * (max - min)).max(px(1.));
+        let color = if (ix as f32 + 0.5) / count as f32 <= played {
+            played_color
+        } else {
+            unplayed_color
+        };
+        window.paint_quad(fill(
+            Bounds::new(
+                point(x, top),
+                size((width - COLUMN_GAP).max(px(1.)), height),
+            ),
+            color,
+        ));
+    }
+}
+
+impl Render for SeekBar {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>().clone();
+        let status = self.player.status();
+        let duration_ms = status
+            .duration_ms
+            .filter(|&duration| duration > 0 && status.state != PlaybackState::Stopped);
+        let fraction = |ms: u64| match duration_ms {
+            Some(duration) => (ms as f32 / duration as f32).clamp(0.0, 1.0),
+            None => 0.0,
+        };
+        let waveform = self
+            .waveform(status.track.clone().filter(|_| duration_ms.is_some()))
+            .cloned();
+        let played = fraction(status.position_ms);
+        let loops = self.player.loops();
+        let ab_loop = status.ab_loop;
+        // the dragged section, or the loop, as fractions of the bar
+        let section = match self.drag {
+            Some((start, end)) => Some((start.min(end), Some(start.max(end)))),
+            None => {
+                ab_loop.map(|ab_loop| (fraction(ab_loop.start_ms), ab_loop.end_ms.map(&fraction)))
+            }
+        };
+        let entity = cx.entity();
+
+        let bar = div()
+            .id("seek-bar")
+            .relative()
+            .flex_1()
+            .h(if waveform.is_some() {
+                WAVEFORM_BAR_HEIGHT
+            } else {
+                BAR_HEIGHT
+            })
+            .bg(theme.list_background)
+            .when_some(section, |bar, (start, end)| match end {
+                Some(end) => bar.child(
+                    div()
+                        .absolute()
+                        .top_0()
+                        .h_full()
+                        .left(relative(start))
+                        .w(relative(end - start))
+                        .bg(theme.selection)
+                        .border_x_1()
+                        .border_color(theme.accent),
+                ),
+                // only point A is set
+                None => bar.child(
+                    div()
+                        .absolute()
+                        .top_0()
+                        .h_full()
+                        .left(relative(start))
+                        .w(px(2.))
+                        .bg(theme.muted_text),
+                ),
+            })
+            .child({
+                let (played_color, unplayed_color) = (theme.accent, theme.muted_text);
+                let waveform = waveform.clone();
+                canvas(
+                    move |bounds, _, cx| entity.update(cx, |bar, _| bar.bounds = bounds),
+                    move |bounds, _, window, _| {
+                        if let Some(waveform) = &waveform {
+                            paint_waveform(
+                                bounds,
+                                waveform,
+                                played,
+                                (played_color, unplayed_color),
+                                window,
+                            );
+                        }
+                    },
+                )
+                .absolute()
+                .size_full()
+            })
+            // the waveform shows the progress by itself
+            .when(waveform.is_none(), |bar| {
+                bar.child(
+                    div()
+                        .absolute()
+                        .bottom_0()
+                        .left_0()
+                        .h(relative(0.3))
+                        .w(relative(played))
+                        .bg(theme.accent),
+                )
+            })
+            .child(
+                div()
+                    .absolute()
+                    .top_0()
+                    .h_full()
+                    .left(relative(played))
+                    .w(px(2.))
+                    .bg(theme.accent),
+            )
+            .when(duration_ms.is_some(), |bar| {
+                bar.cursor_pointer()
+                    .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
+                    .on_mouse_move(cx.listener(Self::on_mouse_move))
+                    .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
+                    .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
+            });
+
+        let button = |id: ElementId, label: SharedString, active: bool| {
+            div()
+                .id(id)
+                .px_2()
+                .rounded_md()
+                .cursor_pointer()
+                .when_else(
+                    active,
+                    |button| button.bg(theme.accent).text_color(theme.accent_text),
+                    |button| button.bg(theme.list_background),
+                )
+                .child(label)
+        };
+
+        div()
+            .flex()
+            .flex_col()
+            .gap_1()
+            .w_full()
+            .px_4()
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .gap_2()
+                    .child(format_time(status.position_ms))
+                    .child(bar)
+                    .child(duration_ms.map(format_time).unwrap_or_default()),
+            )
+            .when(ab_loop.is_some() || !loops.is_empty(), |this| {
+                this.child(
+                    div()
+                        .flex()
+                        .flex_row()
+                        .flex_wrap()
+                        .gap_2()
+                        .text_sm()
+                        .children(loops.into_iter().enumerate().map(|(ix, region)| {
+                            let playing = ab_loop.is_some_and(|ab_loop| {
+                                ab_loop.start_ms == region.start_ms
+                                    && ab_loop.end_ms == Some(region.end_ms)
+                            });
+                            let name = region.name.clone();
+                            let player = Arc::clone(&self.player);
+                            let delete_player = Arc::clone(&self.player);
+                            div()
+                                .flex()
+                                .flex_row()
+                                .child(
+                                    button(
+                                        ("loop", ix).into(),
+                                        format!(
+                                            "{} {}–{}",
+                                            region.name,
+                                            format_time(region.start_ms),
+                                            format_time(region.end_ms)
+                                        )
+                                        .into(),
+                                        playing,
+                                    )
+                                    .on_click(
+                                        move |_: &ClickEvent, _, _| {
+                                            player.play_loop(&name);
+                                        },
+                                    ),
+                                )
+                                .child(
+                                    button(("delete-loop", ix).into(), "×".into(), false).on_click(
+                                        move |_: &ClickEvent, _, _| {
+                                            delete_player.delete_loop(&region.name);
+                                        },
+                                    ),
+                                )
+                        }))
+                        .when(
+                            ab_loop.is_some_and(|ab_loop| ab_loop.end_ms.is_some()),
+                            |this| {
+                                let player = Arc::clone(&self.player);
+                                this.child(
+                                    button("save-loop".into(), "Save loop".into(), false).on_click(
+                                        move |_: &ClickEvent, _, _| {
+                                            player.save_loop(None);
+                                        },
+                                    ),
+                                )
+                            },
+                        )
+                        .when(ab_loop.is_some(), |this| {
+                            let player = Arc::clone(&self.player);
+                            this.child(
+                                button("clear-loop".into(), "Clear loop".into(), false)
+                                    .on_click(move |_: &ClickEvent, _, _| player.clear_ab_loop()),
+                            )
+                        }),
+                )
+            })
+    }
+}
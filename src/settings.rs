@@ -0,0 +1,58 @@
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use log::error;
+
+pub(crate) use player_core::settings::{
+    EqualizerSettings, ReplayGainMode, Settings, SettingsError, SpeedMemory, VisualizerMode,
+};
+
+use crate::keymap;
+use crate::theme::Theme;
+
+/// The settings as a GPUI global, every view observes it to pick up changes
+pub(crate) struct GlobalSettings(pub(crate) Settings);
+
+impl gpui::Global for GlobalSettings {}
+
+/// Validate `settings` like the core does, plus the theme and keymap it names, which only the
+/// front-ends know how to load
+pub(crate) fn validate(settings: &Settings) -> Result<(), SettingsError> {
+    settings.validate()?;
+    Theme::load(&settings.appearance.theme, gpui::WindowAppearance::Light)?;
+    if let Some(keymap) = &settings.shortcuts.keymap {
+        keymap::load(keymap)?;
+    }
+    Ok(())
+}
+
+/// Load the settings, logging the error and falling back to the defaults when invalid
+pub(crate) fn load_or_default() -> Settings {
+    Settings::load()
+        .and_then(|settings| validate(&settings).map(|_| settings))
+        .unwrap_or_else(|e| {
+            error!("{}; using default settings", e);
+            Settings::default()
+        })
+}
+
+/// Like `player_core::settings::watch`, but edits with an unknown theme or a broken keymap are
+/// dropped as well
+pub(crate) fn watch() -> Receiver<Settings> {
+    let core_rx = player_core::settings::watch();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for settings in core_rx {
+            if let Err(e) = validate(&settings) {
+                error!("{}; keeping previous settings", e);
+                continue;
+            }
+            if tx.send(settings).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
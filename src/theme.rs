@@ -0,0 +1,142 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+use gpui::{App, Global, Rgba, WindowAppearance, rgb};
+use log::error;
+
+use crate::settings::{GlobalSettings, Settings, SettingsError};
+use player_core::settings::SYSTEM_THEME;
+
+/// Names of the built-in themes
+const LIGHT_THEME: &str = "light";
+const DARK_THEME: &str = "dark";
+
+/// Directory inside the config dir holding user themes as `<name>.json`
+const THEMES_DIR_NAME: &str = "themes";
+
+/// Colours used by every view. User themes are JSON files with one `"#RRGGBB"` string per field:
+///
+/// ```json
+/// { "background": "#202020", "text": "#EEEEEE", "muted_text": "#999999", ... }
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Theme {
+    /// Window background
+    pub(crate) background: Rgba,
+    pub(crate) text: Rgba,
+    /// Secondary text such as hints
+    pub(crate) muted_text: Rgba,
+    /// Background of the song list
+    pub(crate) list_background: Rgba,
+    /// Separator between songs
+    pub(crate) list_border: Rgba,
+    /// Header row, control bar and buttons
+    pub(crate) accent: Rgba,
+    /// Text drawn on top of `accent`
+    pub(crate) accent_text: Rgba,
+    /// Highlight of selected options
+    pub(crate) selection: Rgba,
+    pub(crate) error: Rgba,
+}
+
+impl Global for Theme {}
+
+impl Theme {
+    pub(crate) fn light() -> Self {
+        Self {
+            background: rgb(0xFAF9F6),
+            text: rgb(0x000000),
+            muted_text: rgb(0x5A5A5A),
+            list_background: rgb(0xC8E5EE),
+            list_border: rgb(0x000000),
+            accent: rgb(0x1C4A5A),
+            accent_text: rgb(0xf1f1f1),
+            selection: rgb(0xC8E5EE),
+            error: rgb(0xB00020),
+        }
+    }
+
+    pub(crate) fn dark() -> Self {
+        Self {
+            background: rgb(0x1E1F22),
+            text: rgb(0xE6E6E6),
+            muted_text: rgb(0x9A9A9A),
+            list_background: rgb(0x23343A),
+            list_border: rgb(0x3A3D41),
+            accent: rgb(0x2E6F85),
+            accent_text: rgb(0xf1f1f1),
+            selection: rgb(0x2E4A55),
+            error: rgb(0xCF6679),
+        }
+    }
+
+    /// Load the theme called `name`: one of the built-in themes or a user theme file
+    pub(crate) fn load(name: &str, appearance: WindowAppearance) -> Result<Self, SettingsError> {
+        match name {
+            SYSTEM_THEME => Ok(match appearance {
+                WindowAppearance::Dark | WindowAppearance::VibrantDark => Self::dark(),
+                WindowAppearance::Light | WindowAppearance::VibrantLight => Self::light(),
+            }),
+            LIGHT_THEME => Ok(Self::light()),
+            DARK_THEME => Ok(Self::dark()),
+            _ => {
+                let path = user_theme_path(name)
+                    .ok_or_else(|| SettingsError::Invalid(format!("unknown theme `{}`", name)))?;
+                let contents = fs::read_to_string(&path).map_err(|e| {
+                    SettingsError::Io(path.clone(), format!("unknown theme `{}`: {}", name, e))
+                })?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| SettingsError::Parse(path.clone(), e.to_string()))
+            }
+        }
+    }
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    Settings::config_dir().map(|dir| dir.join(THEMES_DIR_NAME))
+}
+
+fn user_theme_path(name: &str) -> Option<PathBuf> {
+    themes_dir().map(|dir| dir.join(format!("{}.json", name)))
+}
+
+/// Names of all themes that can be selected, built-in themes first
+pub(crate) fn available_themes() -> Vec<String> {
+    let mut themes = vec![
+        String::from(SYSTEM_THEME),
+        String::from(LIGHT_THEME),
+        String::from(DARK_THEME),
+    ];
+
+    if let Some(entries) = themes_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        let mut user_themes = entries
+            .filter_map(|res| res.ok().map(|entry| entry.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|p| {
+                p.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .collect::<Vec<_>>();
+        user_themes.sort();
+        themes.extend(user_themes);
+    }
+
+    themes
+}
+
+/// Resolve the theme selected in the settings against the current desktop appearance and make it
+/// the active theme of every window
+pub(crate) fn apply(cx: &mut App) {
+    let name = cx.global::<GlobalSettings>().0.appearance.theme.clone();
+    let theme = Theme::load(&name, cx.window_appearance()).unwrap_or_else(|e| {
+        error!("{}; using the light theme", e);
+        Theme::light()
+    });
+
+    if cx.try_global::<Theme>() != Some(&theme) {
+        cx.set_global(theme);
+        cx.refresh_windows();
+    }
+}
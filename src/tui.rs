@@ -0,0 +1,523 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use gpui::Keystroke;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+
+use player_core::library::SongInfo;
+use player_core::player::{PlaybackState, Player, PlayerEvent, PlayerStatus};
+
+use crate::format::{format_time, sleep_label};
+use crate::keymap;
+use crate::override_library;
+use crate::settings::Settings;
+
+/// How long to wait for a key before redrawing the progress
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+/// Volume change of the `+` and `-` keys
+const VOLUME_STEP: f32 = 0.05;
+/// Speed change of the `[` and `]` keys
+const SPEED_STEP: f32 = 0.1;
+/// Pitch change of the `{` and `}` keys, in semitones
+const PITCH_STEP: f32 = 1.0;
+/// How long a notice replaces the help line
+const NOTICE_DURATION: Duration = Duration::from_secs(10);
+
+const HELP: &str = "space play/pause  ←/→ seek  enter play  n/p next/previous  / search  +/- volume  [/] speed  {/} pitch  \\ normal speed  a/b loop start/end  A clear loop  s save loop  l next saved loop  z/Z sleep timer/cancel  g/G loudness of song/library  q quit";
+
+/// An action of the keymap, the TUI has no preferences window
+#[derive(Clone, Copy)]
+enum Action {
+    Quit,
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+}
+
+/// The terminal front-end: the library as a searchable list plus a now-playing line, driven by
+/// the same `Player` and key bindings as the window
+struct Tui<'a> {
+    player: &'a Player,
+    settings: Settings,
+    bindings: Vec<(Keystroke, Action)>,
+    library: Vec<SongInfo>,
+    /// Tags of the current track, read once when it starts as it may be outside the library
+    now_playing: Option<SongInfo>,
+    /// Indices into `library` of the songs matching `query`
+    visible: Vec<usize>,
+    list_state: ListState,
+    query: String,
+    /// Whether keys are typed into the search field
+    searching: bool,
+    /// Why audio moved to another output and when, shown instead of the help for a while
+    notice: Option<(String, Instant)>,
+    /// Tracks measured and to measure by the running loudness analysis
+    analysis: Option<(usize, usize)>,
+    quit: bool,
+}
+
+/// Run the terminal UI until the user quits. `settings_rx` delivers reloaded settings, which are
+/// applied the same way the window applies them.
+pub(crate) fn run(
+    player: &Player,
+    settings: Settings,
+    settings_rx: &Receiver<Settings>,
+    library_override: &[PathBuf],
+) -> io::Result<()> {
+    let events = player.subscribe();
+    let mut tui = Tui {
+        player,
+        bindings: bindings(&settings),
+        settings,
+        library: Vec::new(),
+        now_playing: None,
+        visible: Vec::new(),
+        list_state: ListState::default(),
+        query: String::new(),
+        searching: false,
+        notice: None,
+        analysis: None,
+        quit: false,
+    };
+    tui.reload_library();
+    tui.track_changed(player.status().track);
+
+    let mut terminal = ratatui::try_init()?;
+    let result = tui.run(&mut terminal, &events, settings_rx, library_override);
+    ratatui::restore();
+    result
+}
+
+/// The key bindings of `settings` the TUI can carry out. Terminals have no `cmd` key and the
+/// bindings are single keystrokes, so sequences and `cmd` bindings are left out.
+fn bindings(settings: &Settings) -> Vec<(Keystroke, Action)> {
+    keymap::bindings(settings)
+        .into_iter()
+        .filter_map(|(keystrokes, action)| {
+            let action = match action.as_str() {
+                "Quit" => Action::Quit,
+                "PlayPause" => Action::PlayPause,
+                "SeekForward" => Action::SeekForward,
+                "SeekBackward" => Action::SeekBackward,
+                _ => return None,
+            };
+            let keystroke = Keystroke::parse(&keystrokes).ok()?;
+            (!keystroke.modifiers.platform && !keystroke.modifiers.function)
+                .then_some((keystroke, action))
+        })
+        .collect()
+}
+
+/// Whether `key` is the keystroke `binding` describes, e.g. `ctrl-c` or `space`
+fn matches(binding: &Keystroke, key: &KeyEvent) -> bool {
+    let name = match key.code {
+        KeyCode::Char(' ') => String::from("space"),
+        KeyCode::Char(c) => c.to_lowercase().to_string(),
+        KeyCode::Left => String::from("left"),
+        KeyCode::Right => String::from("right"),
+        KeyCode::Up => String::from("up"),
+        KeyCode::Down => String::from("down"),
+        KeyCode::Enter => String::from("enter"),
+        KeyCode::Esc => String::from("escape"),
+        KeyCode::Backspace => String::from("backspace"),
+        KeyCode::Tab => String::from("tab"),
+        KeyCode::Home => String::from("home"),
+        KeyCode::End => String::from("end"),
+        KeyCode::PageUp => String::from("pageup"),
+        KeyCode::PageDown => String::from("pagedown"),
+        KeyCode::Delete => String::from("delete"),
+        KeyCode::F(n) => format!("f{}", n),
+        _ => return false,
+    };
+    // shift is part of the character for everything but letters, e.g. `+`
+    let shift_matters = !matches!(key.code, KeyCode::Char(c) if !c.is_alphabetic());
+
+    binding.key == name
+        && binding.modifiers.control == key.modifiers.contains(KeyModifiers::CONTROL)
+        && binding.modifiers.alt == key.modifiers.contains(KeyModifiers::ALT)
+        && (!shift_matters
+            || binding.modifiers.shift == key.modifiers.contains(KeyModifiers::SHIFT))
+}
+
+impl Tui<'_> {
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        events: &Receiver<PlayerEvent>,
+        settings_rx: &Receiver<Settings>,
+        library_override: &[PathBuf],
+    ) -> io::Result<()> {
+        while !self.quit {
+            let status = self.player.status();
+            terminal.draw(|frame| self.draw(frame, &status))?;
+
+            if event::poll(FRAME_INTERVAL)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.handle_key(key);
+            }
+
+            loop {
+                match events.try_recv() {
+                    Ok(PlayerEvent::LibraryChanged) => {
+                        self.reload_library();
+                        let track = self.now_playing.take().map(|song| song.path);
+                        self.track_changed(track);
+                    }
+                    Ok(PlayerEvent::TrackChanged(track)) => self.track_changed(track),
+                    Ok(PlayerEvent::OutputFallback(message)) => {
+                        self.notice = Some((message, Instant::now()))
+                    }
+                    Ok(PlayerEvent::AnalysisProgress { done, total }) => {
+                        self.analysis = Some((done, total))
+                    }
+                    Ok(PlayerEvent::AnalysisFinished(_)) => self.analysis = None,
+                    Ok(_) => {}
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            if let Some(mut settings) = settings_rx.try_iter().last() {
+                override_library(&mut settings, library_override);
+                self.apply_settings(settings);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, settings: Settings) {
+        self.player.apply_settings(&self.settings, &settings);
+        self.bindings = bindings(&settings);
+        self.settings = settings;
+    }
+
+    fn reload_library(&mut self) {
+        self.library = self.player.library();
+        self.filter();
+    }
+
+    /// Look up the tags of the track that started, from the library when it is part of it
+    fn track_changed(&mut self, track: Option<PathBuf>) {
+        self.now_playing = track.map(|track| {
+            self.library
+                .iter()
+                .find(|song| song.path == track)
+                .cloned()
+                .unwrap_or_else(|| SongInfo::read(&track))
+        });
+    }
+
+    /// Show the songs whose name, artist or album contain the query
+    fn filter(&mut self) {
+        let query = self.query.to_lowercase();
+        self.visible = self
+            .library
+            .iter()
+            .enumerate()
+            .filter(|(_, song)| {
+                query.is_empty()
+                    || [Some(song.name()), song.artist.clone(), song.album.clone()]
+                        .into_iter()
+                        .flatten()
+                        .any(|tag| tag.to_lowercase().contains(&query))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        self.list_state
+            .select((!self.visible.is_empty()).then_some(0));
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+        if self.searching {
+            match key.code {
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.query.clear();
+                    self.filter();
+                }
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.filter();
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.filter();
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        let action = self
+            .bindings
+            .iter()
+            .rev()
+            .find(|(binding, _)| matches(binding, &key))
+            .map(|(_, action)| *action);
+        match action {
+            Some(Action::Quit) => self.quit = true,
+            Some(Action::PlayPause) => self.player.toggle(),
+            Some(Action::SeekForward) => self.player.seek_forward(),
+            Some(Action::SeekBackward) => self.player.seek_backward(),
+            None => self.handle_tui_key(key),
+        }
+    }
+
+    /// Keys of the list and the search field, which the window has no bindings for
+    fn handle_tui_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.list_state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.list_state.select_next(),
+            KeyCode::PageUp => self.list_state.scroll_up_by(10),
+            KeyCode::PageDown => self.list_state.scroll_down_by(10),
+            KeyCode::Home => self.list_state.select_first(),
+            KeyCode::End => self.list_state.select_last(),
+            KeyCode::Enter => {
+                if let Some(&index) = self
+                    .list_state
+                    .selected()
+                    .and_then(|selected| self.visible.get(selected))
+                {
+                    self.player.play_library(index);
+                }
+            }
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.filter();
+            }
+            KeyCode::Char('g') => {
+                if let Some(&index) = self
+                    .list_state
+                    .selected()
+                    .and_then(|selected| self.visible.get(selected))
+                {
+                    let track = self.library[index].path.clone();
+                    self.analyze(Some(vec![track]));
+                }
+            }
+            KeyCode::Char('G') => self.analyze(None),
+            KeyCode::Char('n') => self.player.next(),
+            KeyCode::Char('p') => self.player.previous(),
+            KeyCode::Char('+' | '=') => {
+                self.player
+                    .set_volume(self.player.status().volume + VOLUME_STEP);
+            }
+            KeyCode::Char('-') => {
+                self.player
+                    .set_volume(self.player.status().volume - VOLUME_STEP);
+            }
+            // rounded, so repeated steps don't drift
+            KeyCode::Char('[') => {
+                let speed = self.player.rate().speed - SPEED_STEP;
+                self.player.set_speed((speed * 10.0).round() / 10.0);
+            }
+            KeyCode::Char(']') => {
+                let speed = self.player.rate().speed + SPEED_STEP;
+                self.player.set_speed((speed * 10.0).round() / 10.0);
+            }
+            KeyCode::Char('{') => {
+                self.player
+                    .set_pitch(self.player.rate().pitch_semitones - PITCH_STEP);
+            }
+            KeyCode::Char('}') => {
+                self.player
+                    .set_pitch(self.player.rate().pitch_semitones + PITCH_STEP);
+            }
+            KeyCode::Char('a') => self.player.set_loop_start(),
+            KeyCode::Char('b') => {
+                let looping = self.player.set_loop_end();
+                if !looping {
+                    self.notice = Some((
+                        String::from("The loop end must be after its start"),
+                        Instant::now(),
+                    ));
+                }
+            }
+            KeyCode::Char('A') => self.player.clear_ab_loop(),
+            KeyCode::Char('s') => {
+                let message = match self.player.save_loop(None) {
+                    Some(name) => format!("Saved the loop as {}", name),
+                    None => String::from("Set a loop with a and b first"),
+                };
+                self.notice = Some((message, Instant::now()));
+            }
+            KeyCode::Char('l') => self.next_saved_loop(),
+            KeyCode::Char('z') => self.player.cycle_sleep_timer(),
+            KeyCode::Char('Z') => self.player.cancel_sleep_timer(),
+            KeyCode::Char('\\') => {
+                self.player.set_speed(1.0);
+                self.player.set_pitch(0.0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Loop the saved loop after the one playing, or the first one
+    fn next_saved_loop(&mut self) {
+        let loops = self.player.loops();
+        let current = self.player.ab_loop().and_then(|ab_loop| {
+            loops.iter().position(|region| {
+                region.start_ms == ab_loop.start_ms && Some(region.end_ms) == ab_loop.end_ms
+            })
+        });
+        let next = current.map_or(0, |index| index + 1) % loops.len().max(1);
+        match loops.get(next) {
+            Some(region) => {
+                self.player.play_loop(&region.name);
+                self.notice = Some((format!("Looping {}", region.name), Instant::now()));
+            }
+            None => {
+                self.notice = Some((
+                    String::from("No loops saved for this track"),
+                    Instant::now(),
+                ))
+            }
+        }
+    }
+
+    /// Measure the loudness of `tracks`, or of the whole library
+    fn analyze(&mut self, tracks: Option<Vec<PathBuf>>) {
+        let write_tags = self.settings.library.write_gain_tags;
+        let started = match tracks {
+            Some(tracks) => self.player.analyze_loudness(tracks, write_tags),
+            None => self.player.analyze_library(write_tags),
+        };
+        if !started {
+            self.notice = Some((
+                String::from("A loudness analysis is already running"),
+                Instant::now(),
+            ));
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame, status: &PlayerStatus) {
+        let [search, list, now_playing, progress, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let search_line = if self.searching || !self.query.is_empty() {
+            Line::from(vec![
+                Span::from("/").bold(),
+                Span::from(self.query.as_str()),
+                Span::from(if self.searching { "█" } else { "" }),
+            ])
+        } else {
+            let mut spans = vec![Span::from("Music Player").bold()];
+            if let Some((done, total)) = self.analysis {
+                spans.push(Span::from(format!(" · analyzing loudness {}/{}", done, total)).dim());
+            }
+            Line::from(spans)
+        };
+        frame.render_widget(Paragraph::new(search_line), search);
+
+        let items = self.visible.iter().map(|&index| {
+            let song = &self.library[index];
+            let mut spans = vec![Span::from(song.name())];
+            if let Some(artist) = &song.artist {
+                spans.push(Span::from(format!("  {}", artist)).dim());
+            }
+            let item = ListItem::new(Line::from(spans));
+            if status.track.as_ref() == Some(&song.path) {
+                item.bold()
+            } else {
+                item
+            }
+        });
+        let list_widget = List::new(items)
+            .block(Block::bordered().title(format!(" Library ({}) ", self.visible.len())))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list_widget, list, &mut self.list_state);
+
+        frame.render_widget(
+            Paragraph::new(now_playing_line(status, self.now_playing.as_ref())),
+            now_playing,
+        );
+
+        let ratio = status
+            .duration_ms
+            .filter(|&duration| duration > 0)
+            .map_or(0.0, |duration| {
+                (status.position_ms as f64 / duration as f64).clamp(0.0, 1.0)
+            });
+        let mut label = match status.duration_ms {
+            Some(duration) => format!(
+                "{} / {}",
+                format_time(status.position_ms),
+                format_time(duration)
+            ),
+            None => format_time(status.position_ms),
+        };
+        if let Some(ab_loop) = status.ab_loop {
+            label.push_str(&format!(" · A {}", format_time(ab_loop.start_ms)));
+            if let Some(end_ms) = ab_loop.end_ms {
+                label.push_str(&format!(" – B {}", format_time(end_ms)));
+            }
+        }
+        if let Some(timer) = status.sleep_timer {
+            label.push_str(&format!(" · sleep {}", sleep_label(timer)));
+        }
+        frame.render_widget(Gauge::default().ratio(ratio).label(label), progress);
+
+        self.notice = self
+            .notice
+            .take()
+            .filter(|(_, since)| since.elapsed() < NOTICE_DURATION);
+        match &self.notice {
+            Some((message, _)) => frame.render_widget(Paragraph::new(message.as_str()).red(), help),
+            None => frame.render_widget(Paragraph::new(HELP).dim(), help),
+        }
+    }
+}
+
+/// e.g. `▶ Title — Artist · vol 80% · 1.5× · shuffle`
+fn now_playing_line<'a>(status: &PlayerStatus, song: Option<&SongInfo>) -> Line<'a> {
+    let symbol = match status.state {
+        PlaybackState::Playing => "▶",
+        PlaybackState::Paused => "⏸",
+        PlaybackState::Stopped => "⏹",
+    };
+    let title = match song.filter(|_| status.track.is_some()) {
+        Some(song) => match &song.artist {
+            Some(artist) => format!("{} — {}", song.name(), artist),
+            None => song.name(),
+        },
+        None => String::from("Nothing playing"),
+    };
+
+    let mut spans = vec![
+        Span::from(format!("{} ", symbol)),
+        Span::from(title).bold(),
+        Span::from(format!(" · vol {}%", (status.volume * 100.0).round())).dim(),
+    ];
+    if status.speed != 1.0 {
+        spans.push(Span::from(format!(" · {}×", status.speed)).dim());
+    }
+    if status.pitch_semitones != 0.0 {
+        spans.push(Span::from(format!(" · {:+} st", status.pitch_semitones)).dim());
+    }
+    if status.shuffle {
+        spans.push(Span::from(" · shuffle").dim());
+    }
+    Line::from(spans)
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use gpui::{
+    Bounds, ClickEvent, Context, InteractiveElement, IntoElement, ParentElement, PathBuilder,
+    Pixels, Render, StatefulInteractiveElement, Styled, Window, canvas, div, fill, point, px, size,
+};
+
+use player_core::player::Player;
+use player_core::spectrum;
+
+use crate::settings::VisualizerMode;
+use crate::theme::Theme;
+
+/// How often the spectrum is read, about 30 frames per second
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+/// Bars of the spectrum
+const BANDS: usize = 48;
+/// How far bars and meters fall per frame once the level drops, as a fraction of their height
+const FALL_PER_FRAME: f32 = 0.04;
+/// Points of the oscilloscope line
+const SCOPE_POINTS: usize = 256;
+/// Space between two bars
+const BAR_GAP: Pixels = px(1.);
+
+/// Spectrum or oscilloscope of what is playing, with a peak and RMS meter per channel. Clicking
+/// switches between the spectrum and the oscilloscope.
+pub(crate) struct Visualizer {
+    player: Arc<Player>,
+    mode: VisualizerMode,
+    /// Height of every bar, from 0.0 to 1.0
+    bars: Vec<f32>,
+    /// The last frames played, mono
+    waveform: Vec<f32>,
+    /// Peak and RMS of the left and right channel, on the meter scale
+    meters: [(f32, f32); 2],
+}
+
+impl Visualizer {
+    pub(crate) fn new(player: Arc<Player>, mode: VisualizerMode, cx: &mut Context<Self>) -> Self {
+        cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(FRAME_INTERVAL).await;
+                if this.update(cx, |this, cx| this.refresh(cx)).is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+        Self {
+            player,
+            mode,
+            bars: vec![0.0; BANDS],
+            waveform: Vec::new(),
+            meters: [(0.0, 0.0); 2],
+        }
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: VisualizerMode, cx: &mut Context<Self>) {
+        self.mode = mode;
+        cx.notify();
+    }
+
+    /// Read the spectrum of the last frames played, redrawing only when something moved
+    fn refresh(&mut self, cx: &mut Context<Self>) {
+        if self.mode == VisualizerMode::Off {
+            return;
+        }
+        let spectrum = self.player.spectrum(BANDS).unwrap_or_default();
+        // rising levels show right away, falling ones fall slowly
+        let follow = |shown: f32, level: f32| level.max(shown - FALL_PER_FRAME).max(0.0);
+
+        let mut bars = self.bars.clone();
+        for (ix, bar) in bars.iter_mut().enumerate() {
+            *bar = follow(*bar, spectrum.bands.get(ix).copied().unwrap_or(0.0));
+        }
+        let mut meters = self.meters;
+        for ((peak, rms), level) in meters.iter_mut().zip(spectrum.levels) {
+            *peak = follow(*peak, spectrum::meter(level.peak));
+            *rms = follow(*rms, spectrum::meter(level.rms));
+        }
+
+        if bars != self.bars || meters != self.meters || spectrum.waveform != self.waveform {
+            self.bars = bars;
+            self.meters = meters;
+            self.waveform = spectrum.waveform;
+            cx.notify();
+        }
+    }
+
+    fn toggle_mode(&mut self, _: &ClickEvent, _: &mut Window, cx: &mut Context<Self>) {
+        let mode = match self.mode {
+            VisualizerMode::Bars => VisualizerMode::Scope,
+            _ => VisualizerMode::Bars,
+        };
+        self.set_mode(mode, cx);
+    }
+}
+
+/// Paint the spectrum as bars growing from the bottom of `bounds`
+fn paint_bars(bounds: Bounds<Pixels>, bars: &[f32], theme: &Theme, window: &mut Window) {
+    let width = bounds.size.width / bars.len() as f32;
+    for (ix, &bar) in bars.iter().enumerate() {
+        let height = bounds.size.height * bar;
+        window.paint_quad(fill(
+            Bounds::new(
+                point(
+                    bounds.origin.x + width * ix as f32,
+                    bounds.bottom() - height,
+                ),
+                size((width - BAR_GAP).max(px(1.)), height),
+            ),
+            theme.accent,
+        ));
+    }
+}
+
+/// Paint the waveform as a line through the middle of `bounds`
+fn paint_scope(bounds: Bounds<Pixels>, waveform: &[f32], theme: &Theme, window: &mut Window) {
+    if waveform.is_empty() {
+        return;
+    }
+    let middle = bounds.origin.y + bounds.size.height / 2.;
+    let step = waveform.len().div_ceil(SCOPE_POINTS);
+    let points = waveform.len().div_ceil(step);
+    let mut path = PathBuilder::stroke(px(1.5));
+    for (ix, &sample) in waveform.iter().step_by(step).enumerate() {
+        let position = point(
+            bounds.origin.x + bounds.size.width * (ix as f32 / (points - 1).max(1) as f32),
+            middle - bounds.size.height / 2. * sample.clamp(-1.0, 1.0),
+        );
+        if ix == 0 {
+            path.move_to(position);
+        } else {
+            path.line_to(position);
+        }
+    }
+    if let Ok(path) = path.build() {
+        window.paint_path(path, theme.accent);
+    }
+}
+
+/// Paint a level meter: the RMS as a bar, the peak as a line above it
+fn paint_meter(
+    bounds: Bounds<Pixels>,
+    (peak, rms): (f32, f32),
+    theme: &Theme,
+    window: &mut Window,
+) {
+    window.paint_quad(fill(bounds, theme.list_background));
+    let height = bounds.size.height * rms;
+    window.paint_quad(fill(
+        Bounds::new(
+            point(bounds.origin.x, bounds.bottom() - height),
+            size(bounds.size.width, height),
+        ),
+        theme.accent,
+    ));
+    let peak = bounds.bottom() - bounds.size.height * peak;
+    window.paint_quad(fill(
+        Bounds::new(
+            point(bounds.origin.x, peak.min(bounds.bottom() - px(2.))),
+            size(bounds.size.width, px(2.)),
+        ),
+        theme.muted_text,
+    ));
+}
+
+impl Render for Visualizer {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = cx.global::<Theme>().clone();
+        let meter_theme = theme.clone();
+        let mode = self.mode;
+        let bars = self.bars.clone();
+        let waveform = self.waveform.clone();
+        let meters = self.meters;
+
+        div()
+            .id("visualizer")
+            .flex()
+            .flex_row()
+            .gap_2()
+            .w_full()
+            .h(px(80.))
+            .px_4()
+            .cursor_pointer()
+            .on_click(cx.listener(Self::toggle_mode))
+            .child(
+                canvas(
+                    |_, _, _| {},
+                    move |bounds, _, window, _| match mode {
+                        VisualizerMode::Scope => paint_scope(bounds, &waveform, &theme, window),
+                        _ => paint_bars(bounds, &bars, &theme, window),
+                    },
+                )
+                .flex_1()
+                .h_full(),
+            )
+            .child(
+                canvas(
+                    |_, _, _| {},
+                    move |bounds, _, window, _| {
+                        let width = (bounds.size.width - px(2.)) / 2.;
+                        for (ix, meter) in meters.into_iter().enumerate() {
+                            let origin = point(
+                                bounds.origin.x + (width + px(2.)) * ix as f32,
+                                bounds.origin.y,
+                            );
+                            paint_meter(
+                                Bounds::new(origin, size(width, bounds.size.height)),
+                                meter,
+                                &meter_theme,
+                                window,
+                            );
+                        }
+                    },
+                )
+                .w(px(14.))
+                .h_full(),
+            )
+    }
+}
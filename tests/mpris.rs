@@ -0,0 +1,174 @@
+//! The MPRIS interface of a headless player, on a private `dbus-daemon` so the desktop session
+//! is left alone. Skipped when `dbus-daemon` is not installed.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zbus::blocking::{Connection, Proxy, connection, proxy};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::OwnedValue;
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.music_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+/// How long the player may take to register and to follow a call
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A child process killed when the test ends, also when it fails
+struct Killed(Child);
+
+impl Drop for Killed {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A 10 s, 440 Hz stereo WAV file. The player only looks at the extension for `.mp3`, the decoder
+/// recognizes the WAV data.
+fn write_track(path: &Path) {
+    let (sample_rate, frames) = (44_100u32, 441_000u32);
+    let data_len = frames * 4;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+    wav.extend_from_slice(&4u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for frame in 0..frames {
+        let phase = 2.0 * std::f32::consts::PI * 440.0 * frame as f32 / sample_rate as f32;
+        let sample = (phase.sin() * 8000.0) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, wav).unwrap();
+}
+
+/// Wait until `done` holds, failing the test after `TIMEOUT`
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn playback_status(player: &Proxy) -> Option<String> {
+    player.get_property("PlaybackStatus").ok()
+}
+
+#[test]
+fn controls_playback_over_a_private_bus() {
+    let bus = match Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address=1"])
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(bus) => bus,
+        Err(e) => {
+            eprintln!("skipping, unable to start dbus-daemon: {}", e);
+            return;
+        }
+    };
+    let mut bus = Killed(bus);
+    let mut address = String::new();
+    BufReader::new(bus.0.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+    let address = address.trim().to_string();
+
+    let home: PathBuf = std::env::temp_dir().join(format!("music-player-mpris-{}", process::id()));
+    let _ = fs::remove_dir_all(&home);
+    let music = home.join("music");
+    fs::create_dir_all(&music).unwrap();
+    fs::create_dir_all(home.join("config/music-player")).unwrap();
+    fs::create_dir_all(home.join("runtime")).unwrap();
+    let track = music.join("track.mp3");
+    write_track(&track);
+    fs::write(
+        home.join("config/music-player/settings.toml"),
+        format!(
+            "[library]\nroots = [{:?}]\n\n[audio]\noutput = \"null\"\n",
+            music.display().to_string()
+        ),
+    )
+    .unwrap();
+
+    let player = Command::new(env!("CARGO_BIN_EXE_music-player-gpui"))
+        .arg("--headless")
+        .arg(&track)
+        .env("DBUS_SESSION_BUS_ADDRESS", &address)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_STATE_HOME", home.join("state"))
+        .env("XDG_RUNTIME_DIR", home.join("runtime"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let player = Killed(player);
+
+    let connection: Connection = connection::Builder::address(address.as_str())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mpris: Proxy = proxy::Builder::new(&connection)
+        .destination(BUS_NAME)
+        .unwrap()
+        .path(OBJECT_PATH)
+        .unwrap()
+        .interface(PLAYER_INTERFACE)
+        .unwrap()
+        .cache_properties(CacheProperties::No)
+        .build()
+        .unwrap();
+
+    wait_for("the track to play", || {
+        playback_status(&mpris).as_deref() == Some("Playing")
+    });
+
+    let metadata: HashMap<String, OwnedValue> = mpris.get_property("Metadata").unwrap();
+    let url = String::try_from(metadata["xesam:url"].clone()).unwrap();
+    assert!(url.starts_with("file://"), "{}", url);
+    assert!(url.ends_with("/music/track.mp3"), "{}", url);
+    let title = String::try_from(metadata["xesam:title"].clone()).unwrap();
+    assert_eq!(title, "track");
+    assert!(metadata.contains_key("mpris:trackid"));
+    let length = i64::try_from(metadata["mpris:length"].clone()).unwrap();
+    assert!((9_900_000..=10_100_000).contains(&length), "{}", length);
+
+    mpris.call_method("PlayPause", &()).unwrap();
+    wait_for("PlayPause to pause", || {
+        playback_status(&mpris).as_deref() == Some("Paused")
+    });
+    mpris.call_method("PlayPause", &()).unwrap();
+    wait_for("PlayPause to resume", || {
+        playback_status(&mpris).as_deref() == Some("Playing")
+    });
+
+    // the spec asks to pause instead of playing at rate 0
+    mpris.set_property("Rate", 0.0f64).unwrap();
+    wait_for("rate 0 to pause", || {
+        playback_status(&mpris).as_deref() == Some("Paused")
+    });
+    let rate: f64 = mpris.get_property("Rate").unwrap();
+    assert_eq!(rate, 1.0);
+
+    drop(player);
+    let _ = fs::remove_dir_all(&home);
+}